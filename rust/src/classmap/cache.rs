@@ -1,7 +1,7 @@
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::time::SystemTime;
 
 pub(crate) const CACHE_VERSION: u32 = 2;
@@ -36,6 +36,56 @@ pub(crate) fn save_cache(cache_path: &Path, cache: &CacheData) {
     }
 }
 
+/// In-memory home for `.turbo-cache` data, keyed by cache file path, so a
+/// long-running process can skip the JSON load/save on every request.
+#[derive(Debug, Default)]
+pub struct CacheStore {
+    entries: HashMap<PathBuf, StoredCache>,
+}
+
+#[derive(Debug)]
+struct StoredCache {
+    data: CacheData,
+    dirty: bool,
+}
+
+impl CacheStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take the cache for `path` out of the store, loading it from disk the
+    /// first time it is requested.
+    pub(crate) fn take(&mut self, path: &Path) -> CacheData {
+        match self.entries.remove(path) {
+            Some(stored) => stored.data,
+            None => load_cache(path),
+        }
+    }
+
+    pub(crate) fn put(&mut self, path: PathBuf, data: CacheData) {
+        self.entries.insert(path, StoredCache { data, dirty: true });
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.entries.values().any(|c| c.dirty)
+    }
+
+    /// Write every cache modified since the last flush back to disk.
+    /// Returns the number of cache files written.
+    pub fn flush(&mut self) -> usize {
+        let mut written = 0;
+        for (path, stored) in &mut self.entries {
+            if stored.dirty {
+                save_cache(path, &stored.data);
+                stored.dirty = false;
+                written += 1;
+            }
+        }
+        written
+    }
+}
+
 pub(crate) fn get_mtime(path: &Path) -> u64 {
     fs::metadata(path)
         .ok()
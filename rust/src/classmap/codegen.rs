@@ -1,7 +1,8 @@
 use std::collections::BTreeMap;
 use std::fmt::Write;
 
-use super::{FileAutoload, NamespaceMapping};
+use super::platform::PlatformRequirements;
+use super::{ClassmapPath, FileAutoload, NamespaceMapping};
 
 pub(crate) fn php_var_export(s: &str) -> String {
     let mut out = String::with_capacity(s.len() + 4);
@@ -17,13 +18,53 @@ pub(crate) fn php_var_export(s: &str) -> String {
     out
 }
 
+/// Where Composer's `getPathCode()` anchors an absolute path.
+enum Anchor<'a> {
+    /// Under the vendor dir; the rest of the path, `""` or `/...`.
+    Vendor(&'a str),
+    /// Relative to the project dir, `/src` or `/../shared/src`.
+    Base(String),
+    /// Shares nothing with the project dir worth relativizing.
+    Absolute,
+}
+
+fn anchor<'a>(abs_path: &'a str, vendor_dir: &str, project_dir: &str) -> Anchor<'a> {
+    // Whole components only: /app/vendor-bin is not under /app/vendor.
+    let vendor_dir = vendor_dir.trim_end_matches('/');
+    if let Some(rel) = abs_path
+        .strip_prefix(vendor_dir)
+        .filter(|rel| rel.is_empty() || rel.starts_with('/'))
+    {
+        return Anchor::Vendor(rel);
+    }
+    match shortest_path(project_dir, abs_path) {
+        Some(rel) => Anchor::Base(format!("/{rel}")),
+        None => Anchor::Absolute,
+    }
+}
+
+/// Composer's `Filesystem::findShortestPath($from, $to, true)` for absolute,
+/// normalized paths: `to` relative to the directory `from`, or `None` when
+/// they only share the filesystem root and `from` is more than one level
+/// deep, where Composer keeps the absolute path.
+fn shortest_path(from: &str, to: &str) -> Option<String> {
+    let from: Vec<&str> = from.split('/').filter(|s| !s.is_empty()).collect();
+    let to: Vec<&str> = to.split('/').filter(|s| !s.is_empty()).collect();
+    let common = from.iter().zip(&to).take_while(|(a, b)| a == b).count();
+    if common == 0 && from.len() > 1 {
+        return None;
+    }
+
+    let mut parts = vec![".."; from.len() - common];
+    parts.extend(&to[common..]);
+    Some(parts.join("/"))
+}
+
 pub(crate) fn file_path_code(abs_path: &str, vendor_dir: &str, project_dir: &str) -> String {
-    if let Some(rel) = abs_path.strip_prefix(vendor_dir) {
-        format!("$vendorDir . {}", php_var_export(rel))
-    } else if let Some(rel) = abs_path.strip_prefix(project_dir) {
-        format!("$baseDir . {}", php_var_export(rel))
-    } else {
-        php_var_export(abs_path)
+    match anchor(abs_path, vendor_dir, project_dir) {
+        Anchor::Vendor(rel) => format!("$vendorDir . {}", php_var_export(rel)),
+        Anchor::Base(rel) => format!("$baseDir . {}", php_var_export(&rel)),
+        Anchor::Absolute => php_var_export(abs_path),
     }
 }
 
@@ -34,21 +75,16 @@ fn static_path_code(
     vendor_rel: &str,
     base_rel: &str,
 ) -> String {
-    if let Some(rel) = abs_path.strip_prefix(vendor_dir) {
-        format!(
-            "__DIR__ . {} . {}",
-            php_var_export(vendor_rel),
-            php_var_export(rel)
-        )
-    } else if let Some(rel) = abs_path.strip_prefix(project_dir) {
-        format!(
-            "__DIR__ . {} . {}",
-            php_var_export(base_rel),
-            php_var_export(rel)
-        )
-    } else {
-        php_var_export(abs_path)
-    }
+    let (dir_rel, rel) = match anchor(abs_path, vendor_dir, project_dir) {
+        Anchor::Vendor(rel) => (vendor_rel, rel.to_string()),
+        Anchor::Base(rel) => (base_rel, rel),
+        Anchor::Absolute => return php_var_export(abs_path),
+    };
+    format!(
+        "__DIR__ . {} . {}",
+        php_var_export(dir_rel),
+        php_var_export(&rel)
+    )
 }
 
 pub(crate) fn relative_dir_path(from: &str, to: &str) -> String {
@@ -167,6 +203,29 @@ pub(crate) fn generate_files_file(
     out
 }
 
+/// Composer's `include_paths.php`, or `None` without `include-path` entries.
+pub(crate) fn generate_include_paths_file(
+    include_paths: &[ClassmapPath],
+    vendor_dir: &str,
+    project_dir: &str,
+) -> Option<String> {
+    if include_paths.is_empty() {
+        return None;
+    }
+
+    let mut out = php_file_header("include_paths.php");
+    for p in include_paths {
+        let _ = writeln!(
+            out,
+            "    {},",
+            file_path_code(&p.path, vendor_dir, project_dir)
+        );
+    }
+
+    out.push_str(");\n");
+    Some(out)
+}
+
 pub(crate) fn generate_classmap_file(
     classmap: &BTreeMap<String, String>,
     vendor_dir: &str,
@@ -205,9 +264,18 @@ pub(crate) fn generate_static_file(
 
     let mut initializer_props = Vec::new();
 
+    // An empty prefix is not a namespace: `ClassLoader::setPsr4('', ...)`
+    // and `set('', ...)` store it as a fallback directory list instead.
+    let (psr4_fallback, psr4): (Vec<&NamespaceMapping>, Vec<&NamespaceMapping>) =
+        psr4.iter().partition(|m| m.namespace.is_empty());
+    let (psr0_fallback, psr0): (Vec<&NamespaceMapping>, Vec<&NamespaceMapping>) =
+        psr0.iter().partition(|m| m.namespace.is_empty());
+    let static_path =
+        |path: &str| static_path_code(path, vendor_dir, project_dir, &vendor_rel, &base_rel);
+
     if !psr4.is_empty() {
         let mut prefix_lengths: BTreeMap<char, BTreeMap<&str, usize>> = BTreeMap::new();
-        for m in psr4 {
+        for m in &psr4 {
             if let Some(first_char) = m.namespace.chars().next() {
                 prefix_lengths
                     .entry(first_char)
@@ -227,11 +295,9 @@ pub(crate) fn generate_static_file(
         }
         file.push_str("    );\n\n");
         initializer_props.push("prefixLengthsPsr4");
-    }
 
-    if !psr4.is_empty() {
         let mut ns_paths: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
-        for m in psr4 {
+        for m in &psr4 {
             ns_paths.entry(&m.namespace).or_default().push(&m.path);
         }
 
@@ -240,8 +306,7 @@ pub(crate) fn generate_static_file(
             let _ = writeln!(file, "        {} => ", php_var_export(ns));
             file.push_str("        array (\n");
             for (i, path) in paths.iter().enumerate() {
-                let code = static_path_code(path, vendor_dir, project_dir, &vendor_rel, &base_rel);
-                let _ = writeln!(file, "            {i} => {code},");
+                let _ = writeln!(file, "            {i} => {},", static_path(path));
             }
             file.push_str("        ),\n");
         }
@@ -249,16 +314,22 @@ pub(crate) fn generate_static_file(
         initializer_props.push("prefixDirsPsr4");
     }
 
+    if !psr4_fallback.is_empty() {
+        push_fallback_dirs(&mut file, "fallbackDirsPsr4", &psr4_fallback, &static_path);
+        initializer_props.push("fallbackDirsPsr4");
+    }
+
     if !psr0.is_empty() {
         let mut by_char: BTreeMap<char, BTreeMap<&str, Vec<&str>>> = BTreeMap::new();
-        for m in psr0 {
-            let first_char = m.namespace.chars().next().unwrap_or('_');
-            by_char
-                .entry(first_char)
-                .or_default()
-                .entry(&m.namespace)
-                .or_default()
-                .push(&m.path);
+        for m in &psr0 {
+            if let Some(first_char) = m.namespace.chars().next() {
+                by_char
+                    .entry(first_char)
+                    .or_default()
+                    .entry(&m.namespace)
+                    .or_default()
+                    .push(&m.path);
+            }
         }
 
         file.push_str("    public static $prefixesPsr0 = array (\n");
@@ -269,9 +340,7 @@ pub(crate) fn generate_static_file(
                 let _ = write!(file, "            {} => ", php_var_export(ns));
                 file.push_str("array (\n");
                 for (i, path) in paths.iter().enumerate() {
-                    let code =
-                        static_path_code(path, vendor_dir, project_dir, &vendor_rel, &base_rel);
-                    let _ = writeln!(file, "                {i} => {code},");
+                    let _ = writeln!(file, "                {i} => {},", static_path(path));
                 }
                 file.push_str("            ),\n");
             }
@@ -281,6 +350,11 @@ pub(crate) fn generate_static_file(
         initializer_props.push("prefixesPsr0");
     }
 
+    if !psr0_fallback.is_empty() {
+        push_fallback_dirs(&mut file, "fallbackDirsPsr0", &psr0_fallback, &static_path);
+        initializer_props.push("fallbackDirsPsr0");
+    }
+
     if !classmap.is_empty() {
         file.push_str("    public static $classMap = array (\n");
         for (class, abs_path) in classmap {
@@ -322,6 +396,100 @@ pub(crate) fn generate_static_file(
     file
 }
 
+/// A `$fallbackDirsPsr4`/`$fallbackDirsPsr0` property: the paths of the
+/// empty-prefix mappings as a list, the way `var_export` prints it.
+fn push_fallback_dirs(
+    file: &mut String,
+    prop: &str,
+    mappings: &[&NamespaceMapping],
+    static_path: &dyn Fn(&str) -> String,
+) {
+    let _ = writeln!(file, "    public static ${prop} = array (");
+    for (i, m) in mappings.iter().enumerate() {
+        let _ = writeln!(file, "        {i} => {},", static_path(&m.path));
+    }
+    file.push_str("    );\n\n");
+}
+
+/// Composer's `platform_check.php`, or `None` when there is nothing to check.
+pub(crate) fn generate_platform_check_file(requirements: &PlatformRequirements) -> Option<String> {
+    let mut checks = String::new();
+    if let Some(php) = requirements.php {
+        let operator = php.operator();
+        let _ = write!(
+            checks,
+            r#"
+if (!(PHP_VERSION_ID {operator} {id})) {{
+    $issues[] = 'Your Composer dependencies require a PHP version "{operator} {human}". You are running ' . PHP_VERSION . '.';
+}}
+"#,
+            id = php.php_version_id(),
+            human = php.human(),
+        );
+    }
+    if requirements.php_64bit {
+        checks.push_str(
+            r#"
+if (PHP_INT_SIZE !== 8) {
+    $issues[] = 'Your Composer dependencies require a 64-bit build of PHP.';
+}
+"#,
+        );
+    }
+    if !requirements.extensions.is_empty() {
+        checks.push_str("\n$missingExtensions = array();\n");
+        for extension in &requirements.extensions {
+            let name = php_var_export(extension);
+            if extension == "pcntl" || extension == "readline" {
+                let _ = writeln!(
+                    checks,
+                    "PHP_SAPI !== 'cli' || extension_loaded({name}) || $missingExtensions[] = {name};"
+                );
+            } else {
+                let _ = writeln!(
+                    checks,
+                    "extension_loaded({name}) || $missingExtensions[] = {name};"
+                );
+            }
+        }
+        checks.push_str(
+            r#"
+if ($missingExtensions) {
+    $issues[] = 'Your Composer dependencies require the following PHP extensions to be installed: ' . implode(', ', $missingExtensions) . '.';
+}
+"#,
+        );
+    }
+    if checks.is_empty() {
+        return None;
+    }
+
+    Some(format!(
+        r#"<?php
+
+// platform_check.php @generated by Composer
+
+$issues = array();
+{checks}
+if ($issues) {{
+    if (!headers_sent()) {{
+        header('HTTP/1.1 500 Internal Server Error');
+    }}
+    if (!ini_get('display_errors')) {{
+        if (PHP_SAPI === 'cli' || PHP_SAPI === 'phpdbg') {{
+            fwrite(STDERR, 'Composer detected issues in your platform:' . PHP_EOL.PHP_EOL . implode(PHP_EOL, $issues) . PHP_EOL.PHP_EOL);
+        }} elseif (!headers_sent()) {{
+            echo 'Composer detected issues in your platform:' . PHP_EOL.PHP_EOL . str_replace('You are running '.PHP_VERSION.'.', '', implode(PHP_EOL, $issues)) . PHP_EOL.PHP_EOL;
+        }}
+    }}
+    throw new \RuntimeException(
+        'Composer detected issues in your platform: ' . implode(' ', $issues)
+    );
+}}
+"#
+    ))
+}
+
 pub(crate) fn generate_autoload_php(suffix: &str) -> String {
     format!(
         r#"<?php
@@ -353,18 +521,64 @@ return ComposerAutoloaderInit{suffix}::getLoader();
     )
 }
 
-pub(crate) fn generate_autoload_real_php(
-    suffix: &str,
-    has_platform_check: bool,
-    has_files_autoload: bool,
-) -> String {
-    let platform_check_block = if has_platform_check {
+/// What `getLoader()` does besides loading the static initializer; mirrors
+/// the switches of Composer's `getAutoloadRealFile()`.
+pub(crate) struct LoaderOptions<'a> {
+    pub has_platform_check: bool,
+    pub has_files_autoload: bool,
+    /// Require `include_paths.php` and prepend it to PHP's include path.
+    pub has_include_paths: bool,
+    pub authoritative: bool,
+    /// `setApcuPrefix()` argument; `None` leaves APCu caching off.
+    pub apcu_prefix: Option<&'a str>,
+    /// Argument to `register()`: prepend to the SPL autoload stack.
+    pub prepend: bool,
+}
+
+impl Default for LoaderOptions<'_> {
+    fn default() -> Self {
+        LoaderOptions {
+            has_platform_check: false,
+            has_files_autoload: false,
+            has_include_paths: false,
+            authoritative: false,
+            apcu_prefix: None,
+            prepend: true,
+        }
+    }
+}
+
+pub(crate) fn generate_autoload_real_php(suffix: &str, options: &LoaderOptions) -> String {
+    let platform_check_block = if options.has_platform_check {
         "\n        require __DIR__ . '/platform_check.php';\n"
     } else {
         ""
     };
 
-    let files_block = if has_files_autoload {
+    let mut loader_setup = String::new();
+    if options.authoritative {
+        loader_setup.push_str("        $loader->setClassMapAuthoritative(true);\n");
+    }
+    if let Some(prefix) = options.apcu_prefix {
+        let _ = writeln!(
+            loader_setup,
+            "        $loader->setApcuPrefix({});",
+            php_var_export(prefix)
+        );
+    }
+    let prepend = if options.prepend { "true" } else { "false" };
+
+    let include_paths_block = if options.has_include_paths {
+        r#"        $includePaths = require __DIR__ . '/include_paths.php';
+        $includePaths[] = get_include_path();
+        set_include_path(implode(PATH_SEPARATOR, $includePaths));
+
+"#
+    } else {
+        ""
+    };
+
+    let files_block = if options.has_files_autoload {
         format!(
             r#"
         $filesToLoad = \Composer\Autoload\ComposerStaticInit{suffix}::$files;
@@ -413,10 +627,10 @@ class ComposerAutoloaderInit{suffix}
         self::$loader = $loader = new \Composer\Autoload\ClassLoader(\dirname(__DIR__));
         spl_autoload_unregister(array('ComposerAutoloaderInit{suffix}', 'loadClassLoader'));
 
-        require __DIR__ . '/autoload_static.php';
+{include_paths_block}        require __DIR__ . '/autoload_static.php';
         call_user_func(\Composer\Autoload\ComposerStaticInit{suffix}::getInitializer($loader));
 
-        $loader->register(true);
+{loader_setup}        $loader->register({prepend});
 {files_block}
         return $loader;
     }}
@@ -440,6 +654,7 @@ mod tests {
         let psr4 = vec![NamespaceMapping {
             namespace: "App\\".to_string(),
             path: "/project/src".to_string(),
+            dev: false,
         }];
 
         let content = generate_static_file(
@@ -461,6 +676,130 @@ mod tests {
         assert!(content.contains("getInitializer"));
     }
 
+    #[test]
+    fn empty_prefixes_become_fallback_dirs() {
+        let mapping = |namespace: &str, path: &str| NamespaceMapping {
+            namespace: namespace.to_string(),
+            path: path.to_string(),
+            dev: false,
+        };
+        let psr4 = vec![
+            mapping("", "/project/src"),
+            mapping("App\\", "/project/app"),
+            mapping("", "/project/vendor/acme/lib/src"),
+        ];
+        let psr0 = vec![mapping("", "/project/legacy")];
+
+        let content = generate_static_file(
+            "abc123",
+            &psr4,
+            &psr0,
+            &BTreeMap::new(),
+            &[],
+            "/project/vendor",
+            "/project",
+            "/project/vendor/composer",
+        );
+
+        assert!(content.contains(
+            "    public static $fallbackDirsPsr4 = array (\n        \
+             0 => __DIR__ . '/../..' . '/src',\n        \
+             1 => __DIR__ . '/..' . '/acme/lib/src',\n    );\n"
+        ));
+        assert!(content.contains(
+            "    public static $fallbackDirsPsr0 = array (\n        \
+             0 => __DIR__ . '/../..' . '/legacy',\n    );\n"
+        ));
+        assert!(!content.contains("$prefixesPsr0"));
+        assert!(!content.contains("'_' =>"));
+        assert!(!content.contains("'' =>"));
+        assert!(content.contains("'A' => "));
+        assert!(content
+            .contains("$loader->fallbackDirsPsr4 = ComposerStaticInitabc123::$fallbackDirsPsr4;"));
+        assert!(content
+            .contains("$loader->fallbackDirsPsr0 = ComposerStaticInitabc123::$fallbackDirsPsr0;"));
+
+        // The plain files keep the '' key, which ClassLoader::set()/setPsr4()
+        // turn into the same fallback directories.
+        let plain = generate_psr4_file(&psr4, "/project/vendor", "/project");
+        assert!(
+            plain.contains("    '' => array($baseDir . '/src', $vendorDir . '/acme/lib/src'),\n")
+        );
+        let plain = generate_namespaces_file(&psr0, "/project/vendor", "/project");
+        assert!(plain.contains("    '' => array($baseDir . '/legacy'),\n"));
+    }
+
+    #[test]
+    fn generate_include_paths_and_loader_code() {
+        assert!(generate_include_paths_file(&[], "/project/vendor", "/project").is_none());
+
+        let paths = vec![
+            ClassmapPath::from("/project/lib".to_string()),
+            ClassmapPath::from("/project/vendor/pear/log".to_string()),
+        ];
+        let content = generate_include_paths_file(&paths, "/project/vendor", "/project").unwrap();
+        assert!(content.starts_with("<?php\n\n// include_paths.php @generated by Composer\n"));
+        assert!(content.ends_with(
+            "return array(\n    $baseDir . '/lib',\n    $vendorDir . '/pear/log',\n);\n"
+        ));
+
+        let real = generate_autoload_real_php(
+            "abc",
+            &LoaderOptions {
+                has_include_paths: true,
+                ..LoaderOptions::default()
+            },
+        );
+        assert!(real.contains(
+            "        $includePaths = require __DIR__ . '/include_paths.php';\n        \
+             $includePaths[] = get_include_path();\n        \
+             set_include_path(implode(PATH_SEPARATOR, $includePaths));\n\n        \
+             require __DIR__ . '/autoload_static.php';"
+        ));
+        assert!(
+            !generate_autoload_real_php("abc", &LoaderOptions::default())
+                .contains("include_paths.php")
+        );
+    }
+
+    #[test]
+    fn path_code_relativizes_by_whole_components() {
+        let code = |path: &str| file_path_code(path, "/app/vendor", "/app");
+        assert_eq!(
+            code("/app/vendor/acme/lib/src"),
+            "$vendorDir . '/acme/lib/src'"
+        );
+        assert_eq!(
+            code("/app/vendor-bin/x.php"),
+            "$baseDir . '/vendor-bin/x.php'"
+        );
+        assert_eq!(code("/app/src"), "$baseDir . '/src'");
+        assert_eq!(code("/app"), "$baseDir . '/'");
+        assert_eq!(code("/application/src"), "$baseDir . '/../application/src'");
+        assert_eq!(
+            code("/shared/lib/x.php"),
+            "$baseDir . '/../shared/lib/x.php'"
+        );
+
+        // Beyond the root, Composer keeps the path absolute.
+        let code = |path: &str| file_path_code(path, "/srv/app/vendor", "/srv/app");
+        assert_eq!(code("/srv/shared/src"), "$baseDir . '/../shared/src'");
+        assert_eq!(code("/opt/lib/src"), "'/opt/lib/src'");
+
+        let code =
+            |path: &str| static_path_code(path, "/srv/app/vendor", "/srv/app", "/..", "/../..");
+        assert_eq!(
+            code("/srv/app/vendor-bin/x.php"),
+            "__DIR__ . '/../..' . '/vendor-bin/x.php'"
+        );
+        assert_eq!(
+            code("/srv/shared/src"),
+            "__DIR__ . '/../..' . '/../shared/src'"
+        );
+        assert_eq!(code("/srv/app/vendor/a/b"), "__DIR__ . '/..' . '/a/b'");
+        assert_eq!(code("/opt/lib/src"), "'/opt/lib/src'");
+    }
+
     #[test]
     fn relative_dir_path_standard_layout() {
         assert_eq!(
@@ -483,7 +822,14 @@ mod tests {
 
     #[test]
     fn generate_autoload_real_with_platform_check_and_files() {
-        let content = generate_autoload_real_php("abc123def", true, true);
+        let content = generate_autoload_real_php(
+            "abc123def",
+            &LoaderOptions {
+                has_platform_check: true,
+                has_files_autoload: true,
+                ..LoaderOptions::default()
+            },
+        );
         assert!(content.contains("class ComposerAutoloaderInitabc123def"));
         assert!(content.contains("platform_check.php"));
         assert!(content.contains("ComposerStaticInitabc123def::$files"));
@@ -493,10 +839,66 @@ mod tests {
 
     #[test]
     fn generate_autoload_real_without_platform_check_or_files() {
-        let content = generate_autoload_real_php("xyz789", false, false);
+        let content = generate_autoload_real_php("xyz789", &LoaderOptions::default());
         assert!(content.contains("class ComposerAutoloaderInitxyz789"));
         assert!(!content.contains("platform_check.php"));
         assert!(!content.contains("ComposerStaticInitxyz789::$files"));
         assert!(content.contains("autoload_static.php"));
+        assert!(!content.contains("setClassMapAuthoritative"));
+        assert!(!content.contains("setApcuPrefix"));
+        assert!(content.contains("$loader->register(true);"));
+    }
+
+    #[test]
+    fn generate_autoload_real_authoritative() {
+        let options = LoaderOptions {
+            authoritative: true,
+            ..LoaderOptions::default()
+        };
+        let content = generate_autoload_real_php("xyz789", &options);
+        assert!(content.contains(
+            "getInitializer($loader));\n\n        $loader->setClassMapAuthoritative(true);\n        $loader->register(true);"
+        ));
+    }
+
+    #[test]
+    fn generate_autoload_real_apcu_and_append() {
+        let options = LoaderOptions {
+            authoritative: true,
+            apcu_prefix: Some("it's"),
+            prepend: false,
+            ..LoaderOptions::default()
+        };
+        let content = generate_autoload_real_php("xyz789", &options);
+        assert!(content.contains(
+            "        $loader->setClassMapAuthoritative(true);\n        $loader->setApcuPrefix('it\\'s');\n        $loader->register(false);"
+        ));
+    }
+
+    #[test]
+    fn generate_platform_check_for_php_and_extensions() {
+        use crate::classmap::platform::Bound;
+
+        let requirements = PlatformRequirements {
+            php: Some(Bound {
+                version: [8, 1, 0, 0],
+                inclusive: true,
+            }),
+            php_64bit: false,
+            extensions: vec!["json".to_string(), "pcntl".to_string()],
+        };
+        let content = generate_platform_check_file(&requirements).unwrap();
+        assert!(content.starts_with(
+            "<?php\n\n// platform_check.php @generated by Composer\n\n$issues = array();\n\nif (!(PHP_VERSION_ID >= 80100)) {\n    $issues[] = 'Your Composer dependencies require a PHP version \">= 8.1.0\". You are running ' . PHP_VERSION . '.';\n}\n"
+        ));
+        assert!(content.contains(
+            "\n$missingExtensions = array();\nextension_loaded('json') || $missingExtensions[] = 'json';\nPHP_SAPI !== 'cli' || extension_loaded('pcntl') || $missingExtensions[] = 'pcntl';\n\nif ($missingExtensions) {"
+        ));
+        assert!(!content.contains("PHP_INT_SIZE"));
+
+        assert_eq!(
+            generate_platform_check_file(&PlatformRequirements::default()),
+            None
+        );
     }
 }
@@ -0,0 +1,695 @@
+//! Composer's runtime files: `installed.php`, generated from
+//! `vendor/composer/installed.json` the way `FilesystemRepository::write()`
+//! does, and the `ClassLoader.php` / `InstalledVersions.php` sources that
+//! `AutoloadGenerator::dump()` copies next to the autoload files.
+
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use crate::error::{Error, ErrorCode};
+
+use super::codegen::{php_var_export, relative_dir_path};
+use super::manifest::read_json;
+
+/// The class Composer always maps, whether or not a root contains it.
+pub(crate) const INSTALLED_VERSIONS_CLASS: &str = "Composer\\InstalledVersions";
+
+/// Where [`INSTALLED_VERSIONS_CLASS`] lives under `vendor_dir`.
+pub(crate) fn installed_versions_path(vendor_dir: &str) -> String {
+    format!("{vendor_dir}/composer/InstalledVersions.php")
+}
+
+/// The root package as Composer sees it. `installed.json` does not record
+/// it, so PHP sends it or it is read from `composer.json`.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RootPackage {
+    pub name: String,
+    pub pretty_version: String,
+    pub version: String,
+    #[serde(default)]
+    pub reference: Option<String>,
+    #[serde(default = "default_type", rename = "type")]
+    pub package_type: String,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    /// Required package name => pretty constraint (`require` only, not
+    /// `require-dev`).
+    #[serde(default, deserialize_with = "crate::encoding::string_map")]
+    pub require: BTreeMap<String, String>,
+    /// Replaced package name => pretty constraint.
+    #[serde(default, deserialize_with = "crate::encoding::string_map")]
+    pub replace: BTreeMap<String, String>,
+    /// Provided package name => pretty constraint.
+    #[serde(default, deserialize_with = "crate::encoding::string_map")]
+    pub provide: BTreeMap<String, String>,
+}
+
+fn default_type() -> String {
+    "library".to_string()
+}
+
+impl RootPackage {
+    /// The root package described by a parsed `composer.json`. Without a
+    /// `version`, `COMPOSER_ROOT_VERSION` or Composer's last-resort
+    /// `1.0.0+no-version-set` is used, since there is no git guessing here.
+    pub(crate) fn from_composer_json(root: &Value) -> Self {
+        let pretty_version = root
+            .get("version")
+            .and_then(Value::as_str)
+            .map(str::to_string)
+            .or_else(|| std::env::var("COMPOSER_ROOT_VERSION").ok())
+            .unwrap_or_else(|| "1.0.0+no-version-set".to_string());
+        RootPackage {
+            name: root
+                .get("name")
+                .and_then(Value::as_str)
+                .unwrap_or("__root__")
+                .to_string(),
+            version: normalize_version(&pretty_version),
+            pretty_version,
+            reference: None,
+            package_type: root
+                .get("type")
+                .and_then(Value::as_str)
+                .map(str::to_string)
+                .unwrap_or_else(default_type),
+            aliases: Vec::new(),
+            require: links(root, "require"),
+            replace: links(root, "replace"),
+            provide: links(root, "provide"),
+        }
+    }
+}
+
+pub(crate) fn links(package: &Value, key: &str) -> BTreeMap<String, String> {
+    package
+        .get(key)
+        .and_then(Value::as_object)
+        .map(|m| {
+            m.iter()
+                .filter_map(|(name, c)| Some((name.to_lowercase(), c.as_str()?.to_string())))
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// A small subset of Composer's `VersionParser::normalize()`: enough for
+/// the versions a root package declares by hand.
+fn normalize_version(version: &str) -> String {
+    let version = version.split('+').next().unwrap_or(version).trim();
+    if version.starts_with("dev-") {
+        return version.to_string();
+    }
+    static NUMERIC: OnceLock<Regex> = OnceLock::new();
+    let re = NUMERIC.get_or_init(|| {
+        Regex::new(r"^[vV]?(\d+)(?:\.(\d+|[xX*]))?(?:\.(\d+|[xX*]))?(?:\.(\d+|[xX*]))?(.*)$")
+            .unwrap()
+    });
+    let Some(caps) = re.captures(version) else {
+        return version.to_string();
+    };
+    let mut wildcard = false;
+    let parts: Vec<&str> = (1..=4)
+        .map(|i| match caps.get(i).map(|m| m.as_str()) {
+            Some("x" | "X" | "*") => {
+                wildcard = true;
+                "9999999"
+            }
+            Some(n) => n,
+            None if wildcard => "9999999",
+            None => "0",
+        })
+        .collect();
+    let numeric = parts.join(".");
+
+    static MODIFIER: OnceLock<Regex> = OnceLock::new();
+    let modifier = MODIFIER.get_or_init(|| {
+        Regex::new(r"^[._-]?(?:(stable|beta|b|rc|alpha|a|patch|pl|p)(?:[.-]?(\d+))?)?([.-]?dev)?$")
+            .unwrap()
+    });
+    let rest = caps[5].to_lowercase();
+    let Some(m) = modifier.captures(&rest) else {
+        return version.to_string();
+    };
+    let mut normalized = numeric;
+    if let Some(stability) = m.get(1).map(|s| s.as_str()).filter(|s| *s != "stable") {
+        let stability = match stability {
+            "a" => "alpha",
+            "b" => "beta",
+            "rc" => "RC",
+            "p" | "pl" => "patch",
+            other => other,
+        };
+        let _ = write!(
+            normalized,
+            "-{stability}{}",
+            m.get(2).map_or("", |n| n.as_str())
+        );
+    }
+    if m.get(3).is_some() {
+        normalized.push_str("-dev");
+    }
+    normalized
+}
+
+/// `PlatformRepository::isPlatformPackage()`.
+fn is_platform_package(name: &str) -> bool {
+    static PLATFORM: OnceLock<Regex> = OnceLock::new();
+    PLATFORM
+        .get_or_init(|| {
+            Regex::new(
+                r"(?i)^(?:php(?:-64bit|-ipv6|-zts|-debug)?|hhvm|(?:ext|lib)-[a-z0-9](?:[_.-]?[a-z0-9]+)*|composer(?:-(?:plugin|runtime))?-api)$",
+            )
+            .unwrap()
+        })
+        .is_match(name)
+}
+
+/// A PHP array value, dumped the way Composer's `dumpToPhpCode()` does.
+enum PhpValue {
+    Null,
+    Bool(bool),
+    Str(String),
+    /// An `install_path`: relative ones are anchored at `__DIR__`.
+    InstallPath(String),
+    List(Vec<String>),
+    Map(Vec<(String, PhpValue)>),
+}
+
+impl PhpValue {
+    fn opt_str(value: Option<&str>) -> Self {
+        value.map_or(PhpValue::Null, |s| PhpValue::Str(s.to_string()))
+    }
+}
+
+fn map_entry<'a>(map: &'a mut Vec<(String, PhpValue)>, key: &str) -> &'a mut PhpValue {
+    let index = match map.iter().position(|(k, _)| k == key) {
+        Some(index) => index,
+        None => {
+            map.push((key.to_string(), PhpValue::Map(Vec::new())));
+            map.len() - 1
+        }
+    };
+    &mut map[index].1
+}
+
+fn dump_map(out: &mut String, entries: &[(String, PhpValue)], level: usize) {
+    out.push_str("array(\n");
+    for (key, value) in entries {
+        dump_entry(out, &php_var_export(key), value, level + 1);
+    }
+    let _ = write!(out, "{})", "    ".repeat(level));
+}
+
+fn dump_entry(out: &mut String, key: &str, value: &PhpValue, level: usize) {
+    let _ = write!(out, "{}{key} => ", "    ".repeat(level));
+    match value {
+        PhpValue::Null => out.push_str("null"),
+        PhpValue::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+        PhpValue::Str(s) => out.push_str(&php_var_export(s)),
+        PhpValue::InstallPath(p) if p.starts_with('/') => out.push_str(&php_var_export(p)),
+        PhpValue::InstallPath(p) => {
+            let _ = write!(out, "__DIR__ . {}", php_var_export(&format!("/{p}")));
+        }
+        PhpValue::List(items) if items.is_empty() => out.push_str("array()"),
+        PhpValue::Map(entries) if entries.is_empty() => out.push_str("array()"),
+        PhpValue::List(items) => {
+            out.push_str("array(\n");
+            for (i, item) in items.iter().enumerate() {
+                dump_entry(out, &i.to_string(), &PhpValue::Str(item.clone()), level + 1);
+            }
+            let _ = write!(out, "{})", "    ".repeat(level));
+        }
+        PhpValue::Map(entries) => dump_map(out, entries, level),
+    }
+    out.push_str(",\n");
+}
+
+/// One package as `installed.php` lists it.
+struct Installed<'a> {
+    name: &'a str,
+    pretty_version: Option<&'a str>,
+    version: Option<&'a str>,
+    reference: Option<&'a str>,
+    package_type: &'a str,
+    install_path: Option<String>,
+    aliases: Vec<String>,
+    replace: BTreeMap<String, String>,
+    provide: BTreeMap<String, String>,
+}
+
+impl<'a> Installed<'a> {
+    fn from_json(entry: &'a Value) -> Option<Self> {
+        let str_field = |key: &str| entry.get(key).and_then(Value::as_str);
+        let reference_of = |key: &str| {
+            entry
+                .get(key)
+                .and_then(|s| s.get("reference"))
+                .and_then(Value::as_str)
+        };
+        let reference = match str_field("installation-source") {
+            Some("source") => reference_of("source"),
+            Some(_) => reference_of("dist"),
+            None => None,
+        }
+        .or_else(|| reference_of("source").filter(|r| !r.is_empty()))
+        .or_else(|| reference_of("dist").filter(|r| !r.is_empty()));
+
+        let pretty_version = str_field("version");
+        let aliases = pretty_version
+            .and_then(|v| entry.get("extra")?.get("branch-alias")?.get(v)?.as_str())
+            .map(|alias| vec![alias.to_string()])
+            .unwrap_or_default();
+
+        Some(Installed {
+            name: str_field("name")?,
+            pretty_version,
+            version: str_field("version_normalized").or(pretty_version),
+            reference,
+            package_type: str_field("type").unwrap_or("library"),
+            install_path: str_field("install-path").map(str::to_string),
+            aliases,
+            replace: links(entry, "replace"),
+            provide: links(entry, "provide"),
+        })
+    }
+
+    fn fields(&self) -> Vec<(String, PhpValue)> {
+        vec![
+            (
+                "pretty_version".to_string(),
+                PhpValue::opt_str(self.pretty_version),
+            ),
+            ("version".to_string(), PhpValue::opt_str(self.version)),
+            ("reference".to_string(), PhpValue::opt_str(self.reference)),
+            (
+                "type".to_string(),
+                PhpValue::Str(self.package_type.to_string()),
+            ),
+            (
+                "install_path".to_string(),
+                self.install_path
+                    .clone()
+                    .map_or(PhpValue::Null, PhpValue::InstallPath),
+            ),
+            ("aliases".to_string(), PhpValue::List(self.aliases.clone())),
+        ]
+    }
+}
+
+/// `vendor/composer/installed.json`, in Composer 1 or 2 format.
+pub(crate) struct InstalledJson {
+    pub(crate) packages: Vec<Value>,
+    /// Whether dev requirements were installed.
+    pub(crate) dev: bool,
+    pub(crate) dev_package_names: HashSet<String>,
+}
+
+impl InstalledJson {
+    /// Read `vendor_dir`'s `installed.json`; a missing file means nothing is
+    /// installed yet.
+    pub(crate) fn load(vendor_dir: &str) -> Result<Self, Error> {
+        let path = Path::new(vendor_dir).join("composer/installed.json");
+        let installed = if path.exists() {
+            read_json(&path)?
+        } else {
+            Value::Null
+        };
+        Ok(match installed {
+            Value::Array(packages) => InstalledJson {
+                packages,
+                dev: true,
+                dev_package_names: HashSet::new(),
+            },
+            Value::Object(mut obj) => InstalledJson {
+                packages: match obj.remove("packages") {
+                    Some(Value::Array(packages)) => packages,
+                    _ => Vec::new(),
+                },
+                dev: obj.get("dev").and_then(Value::as_bool).unwrap_or(true),
+                dev_package_names: obj
+                    .get("dev-package-names")
+                    .and_then(Value::as_array)
+                    .map(|names| {
+                        names
+                            .iter()
+                            .filter_map(|n| Some(n.as_str()?.to_string()))
+                            .collect()
+                    })
+                    .unwrap_or_default(),
+            },
+            _ => InstalledJson {
+                packages: Vec::new(),
+                dev: true,
+                dev_package_names: HashSet::new(),
+            },
+        })
+    }
+}
+
+/// Generate `installed.php` for `installed`'s packages plus `root`. Paths
+/// are relative to `target_dir`, which is where the file is written.
+pub(crate) fn generate_installed_file(
+    root: &RootPackage,
+    installed: &InstalledJson,
+    project_dir: &str,
+    target_dir: &str,
+) -> String {
+    let dev_names = &installed.dev_package_names;
+
+    let root_path = format!(
+        "{}/",
+        relative_dir_path(target_dir, project_dir).trim_start_matches('/')
+    );
+    let mut packages: Vec<Installed> = installed
+        .packages
+        .iter()
+        .filter_map(Installed::from_json)
+        .collect();
+    packages.push(Installed {
+        name: &root.name,
+        pretty_version: Some(&root.pretty_version),
+        version: Some(&root.version),
+        reference: root.reference.as_deref(),
+        package_type: &root.package_type,
+        install_path: Some(root_path),
+        aliases: root.aliases.clone(),
+        replace: root.replace.clone(),
+        provide: root.provide.clone(),
+    });
+    let root_index = packages.len() - 1;
+
+    let mut versions: Vec<(String, PhpValue)> = Vec::new();
+    let mut root_entry = Vec::new();
+    for (i, package) in packages.iter().enumerate() {
+        let mut fields = package.fields();
+        if i == root_index {
+            root_entry.push(("name".to_string(), PhpValue::Str(package.name.to_string())));
+            root_entry.extend(package.fields());
+            root_entry.push(("dev".to_string(), PhpValue::Bool(installed.dev)));
+        }
+        fields.push((
+            "dev_requirement".to_string(),
+            PhpValue::Bool(dev_names.contains(package.name)),
+        ));
+        versions.push((package.name.to_string(), PhpValue::Map(fields)));
+    }
+
+    // Virtual packages: every replaced or provided name gets an entry, a
+    // dev requirement only if all packages that bring it in are.
+    for package in &packages {
+        let is_dev = dev_names.contains(package.name);
+        let links = [
+            ("replaced", &package.replace),
+            ("provided", &package.provide),
+        ];
+        for (kind, targets) in links {
+            for (target, constraint) in targets {
+                if is_platform_package(target) {
+                    continue;
+                }
+                let constraint = if constraint == "self.version" {
+                    package.pretty_version.unwrap_or_default()
+                } else {
+                    constraint
+                };
+                let PhpValue::Map(fields) = map_entry(&mut versions, target) else {
+                    continue;
+                };
+                match fields.iter_mut().find(|(k, _)| k == "dev_requirement") {
+                    Some((_, value)) if !is_dev => *value = PhpValue::Bool(false),
+                    Some(_) => {}
+                    None => fields.push(("dev_requirement".to_string(), PhpValue::Bool(is_dev))),
+                }
+                match fields.iter_mut().find(|(k, _)| k == kind) {
+                    Some((_, PhpValue::List(list))) => {
+                        if !list.iter().any(|c| c == constraint) {
+                            list.push(constraint.to_string());
+                        }
+                    }
+                    _ => fields.push((
+                        kind.to_string(),
+                        PhpValue::List(vec![constraint.to_string()]),
+                    )),
+                }
+            }
+        }
+    }
+    versions.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut out = String::from("<?php return ");
+    dump_map(
+        &mut out,
+        &[
+            ("root".to_string(), PhpValue::Map(root_entry)),
+            ("versions".to_string(), PhpValue::Map(versions)),
+        ],
+        0,
+    );
+    out.push_str(";\n");
+    out
+}
+
+/// The files `AutoloadGenerator::dump()` copies into the target dir, read
+/// from `runtime_dir` (the `src/Composer` directory of a Composer checkout
+/// or of the extracted phar): `(file name, content)`.
+pub(crate) fn runtime_files(runtime_dir: &str) -> Result<Vec<(&'static str, String)>, Error> {
+    let dir = Path::new(runtime_dir);
+    let mut files = Vec::new();
+    for (name, source, required) in [
+        (
+            "ClassLoader.php",
+            dir.join("Autoload/ClassLoader.php"),
+            true,
+        ),
+        (
+            "InstalledVersions.php",
+            dir.join("InstalledVersions.php"),
+            true,
+        ),
+        ("LICENSE", dir.join("../../LICENSE"), false),
+    ] {
+        match fs::read_to_string(&source) {
+            Ok(content) => files.push((name, content)),
+            Err(e) if !required && e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) => return Err(Error::io(e, &source)),
+        }
+    }
+    Ok(files)
+}
+
+/// Without a `runtime_dir`, check that an earlier dump left the runtime
+/// files in `target_dir` that the generated autoloader needs, so a run
+/// never writes a loader that cannot load. `needs_loader` is set when
+/// `autoload_real.php` is written, `needs_installed_versions` when the
+/// classmap maps [`INSTALLED_VERSIONS_CLASS`].
+pub(crate) fn check_existing_runtime(
+    target_dir: &Path,
+    needs_loader: bool,
+    needs_installed_versions: bool,
+) -> Result<(), Error> {
+    let needed = [
+        ("ClassLoader.php", needs_loader),
+        ("InstalledVersions.php", needs_installed_versions),
+    ];
+    for (name, needed) in needed {
+        let path = target_dir.join(name);
+        if needed && !path.is_file() {
+            return Err(Error::new(
+                ErrorCode::NotFound,
+                format!(
+                    "{}: the autoloader needs Composer's {name}; pass runtime_dir (--runtime-dir) \
+                     to copy it from Composer's src/Composer directory",
+                    path.display()
+                ),
+            )
+            .with_path(&path));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn root() -> RootPackage {
+        RootPackage {
+            name: "acme/app".to_string(),
+            pretty_version: "dev-main".to_string(),
+            version: "dev-main".to_string(),
+            reference: Some("abc123".to_string()),
+            package_type: "project".to_string(),
+            aliases: Vec::new(),
+            require: BTreeMap::new(),
+            replace: BTreeMap::from([("acme/legacy".to_string(), "self.version".to_string())]),
+            provide: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn installed_file_matches_composer_layout() {
+        let tmp = TempDir::new().unwrap();
+        let vendor = tmp.path().join("vendor");
+        fs::create_dir_all(vendor.join("composer")).unwrap();
+        fs::write(
+            vendor.join("composer/installed.json"),
+            r#"{
+                "packages": [
+                    {
+                        "name": "psr/log",
+                        "version": "3.0.0",
+                        "version_normalized": "3.0.0.0",
+                        "source": {"reference": "src-ref"},
+                        "dist": {"reference": "dist-ref"},
+                        "installation-source": "dist",
+                        "type": "library",
+                        "install-path": "../psr/log",
+                        "provide": {"psr/log-implementation": "3.0", "php": "*"}
+                    },
+                    {
+                        "name": "phpunit/phpunit",
+                        "version": "dev-main",
+                        "version_normalized": "dev-main",
+                        "installation-source": "source",
+                        "source": {"reference": "p-ref"},
+                        "type": "library",
+                        "install-path": "../phpunit/phpunit",
+                        "extra": {"branch-alias": {"dev-main": "11.x-dev"}}
+                    }
+                ],
+                "dev": true,
+                "dev-package-names": ["phpunit/phpunit"]
+            }"#,
+        )
+        .unwrap();
+        let vendor = vendor.to_string_lossy();
+        let project = tmp.path().to_string_lossy();
+        let installed = InstalledJson::load(&vendor).unwrap();
+        let content =
+            generate_installed_file(&root(), &installed, &project, &format!("{vendor}/composer"));
+
+        assert_eq!(
+            content,
+            "<?php return array(
+    'root' => array(
+        'name' => 'acme/app',
+        'pretty_version' => 'dev-main',
+        'version' => 'dev-main',
+        'reference' => 'abc123',
+        'type' => 'project',
+        'install_path' => __DIR__ . '/../../',
+        'aliases' => array(),
+        'dev' => true,
+    ),
+    'versions' => array(
+        'acme/app' => array(
+            'pretty_version' => 'dev-main',
+            'version' => 'dev-main',
+            'reference' => 'abc123',
+            'type' => 'project',
+            'install_path' => __DIR__ . '/../../',
+            'aliases' => array(),
+            'dev_requirement' => false,
+        ),
+        'acme/legacy' => array(
+            'dev_requirement' => false,
+            'replaced' => array(
+                0 => 'dev-main',
+            ),
+        ),
+        'phpunit/phpunit' => array(
+            'pretty_version' => 'dev-main',
+            'version' => 'dev-main',
+            'reference' => 'p-ref',
+            'type' => 'library',
+            'install_path' => __DIR__ . '/../phpunit/phpunit',
+            'aliases' => array(
+                0 => '11.x-dev',
+            ),
+            'dev_requirement' => true,
+        ),
+        'psr/log' => array(
+            'pretty_version' => '3.0.0',
+            'version' => '3.0.0.0',
+            'reference' => 'dist-ref',
+            'type' => 'library',
+            'install_path' => __DIR__ . '/../psr/log',
+            'aliases' => array(),
+            'dev_requirement' => false,
+        ),
+        'psr/log-implementation' => array(
+            'dev_requirement' => false,
+            'provided' => array(
+                0 => '3.0',
+            ),
+        ),
+    ),
+);
+"
+        );
+    }
+
+    #[test]
+    fn runtime_files_need_loader_sources_but_not_license() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().join("src/Composer");
+        fs::create_dir_all(dir.join("Autoload")).unwrap();
+        fs::write(dir.join("Autoload/ClassLoader.php"), "<?php // loader").unwrap();
+        let dir = dir.to_string_lossy();
+        assert!(runtime_files(&dir).is_err());
+
+        fs::write(
+            tmp.path().join("src/Composer/InstalledVersions.php"),
+            "<?php",
+        )
+        .unwrap();
+        let names: Vec<_> = runtime_files(&dir)
+            .unwrap()
+            .into_iter()
+            .map(|f| f.0)
+            .collect();
+        assert_eq!(names, ["ClassLoader.php", "InstalledVersions.php"]);
+
+        fs::write(tmp.path().join("LICENSE"), "MIT").unwrap();
+        assert_eq!(runtime_files(&dir).unwrap().len(), 3);
+    }
+
+    #[test]
+    fn existing_runtime_must_cover_what_the_loader_needs() {
+        let tmp = TempDir::new().unwrap();
+        assert!(check_existing_runtime(tmp.path(), false, false).is_ok());
+
+        let err = check_existing_runtime(tmp.path(), true, false).unwrap_err();
+        assert_eq!(err.code, ErrorCode::NotFound);
+        assert!(err.message.contains("ClassLoader.php"), "{}", err.message);
+
+        fs::write(tmp.path().join("ClassLoader.php"), "<?php").unwrap();
+        assert!(check_existing_runtime(tmp.path(), true, false).is_ok());
+        let err = check_existing_runtime(tmp.path(), true, true).unwrap_err();
+        assert!(
+            err.message.contains("InstalledVersions.php"),
+            "{}",
+            err.message
+        );
+    }
+
+    #[test]
+    fn root_version_is_normalized() {
+        assert_eq!(normalize_version("1.0.0+no-version-set"), "1.0.0.0");
+        assert_eq!(normalize_version("v2.1"), "2.1.0.0");
+        assert_eq!(
+            normalize_version("2.x-dev"),
+            "2.9999999.9999999.9999999-dev"
+        );
+        assert_eq!(normalize_version("1.2.0-RC1"), "1.2.0.0-RC1");
+        assert_eq!(normalize_version("dev-main"), "dev-main");
+    }
+}
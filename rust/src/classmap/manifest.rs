@@ -0,0 +1,959 @@
+//! Resolve autoload mappings straight from `composer.json` and
+//! `vendor/composer/installed.json`, mirroring Composer's
+//! `AutoloadGenerator::parseAutoloads()` so `dump-autoload` can run without
+//! booting Composer.
+
+use md5::{Digest, Md5};
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use crate::error::Error;
+
+use super::{
+    AutoloadMappings, ClassmapPath, FileAutoload, NamespaceMapping, PlatformCheck, RootPackage,
+};
+
+/// Everything the classmap command needs that Composer would otherwise
+/// compute in PHP.
+#[derive(Debug, Clone)]
+pub struct ComposerProject {
+    pub project_dir: String,
+    pub vendor_dir: String,
+    pub autoload: AutoloadMappings,
+    pub exclude_from_classmap: Vec<String>,
+    pub suffix: String,
+    /// `config.classmap-authoritative`.
+    pub classmap_authoritative: bool,
+    /// `config.apcu-autoloader`.
+    pub apcu_autoloader: bool,
+    /// `config.apcu-autoloader-prefix`; unset means a random prefix.
+    pub apcu_prefix: Option<String>,
+    /// `config.prepend-autoloader`.
+    pub prepend_autoloader: bool,
+    /// The root package, for `installed.php`.
+    pub root_package: RootPackage,
+    /// `vendor/composer/composer/src/Composer`, when Composer is a dependency.
+    pub runtime_dir: Option<String>,
+    /// `config.platform-check`, `php-only` by default as in Composer.
+    pub platform_check: PlatformCheck,
+}
+
+/// One package's autoload configuration plus the data needed to resolve it.
+#[derive(Debug, Default)]
+struct Package {
+    name: String,
+    install_path: String,
+    target_dir: Option<String>,
+    autoload: AutoloadSection,
+    /// The root package's `autoload-dev`; always dev.
+    dev_autoload: AutoloadSection,
+    /// Legacy top-level `include-path` entries.
+    include_paths: Vec<String>,
+    requires: Vec<String>,
+    is_root: bool,
+    /// Listed in installed.json's `dev-package-names`.
+    dev: bool,
+}
+
+impl Package {
+    /// The autoload sections in Composer's merge order, each with the dev
+    /// flag its mappings get.
+    fn sections(&self) -> [(&AutoloadSection, bool); 2] {
+        [(&self.autoload, self.dev), (&self.dev_autoload, true)]
+    }
+}
+
+#[derive(Debug, Default)]
+struct AutoloadSection {
+    psr4: Vec<(String, Vec<String>)>,
+    psr0: Vec<(String, Vec<String>)>,
+    classmap: Vec<String>,
+    files: Vec<String>,
+    exclude: Vec<String>,
+}
+
+impl AutoloadSection {
+    /// Lenient parse: Composer skips sections that are not arrays/objects
+    /// rather than failing, so we do the same.
+    fn parse(value: Option<&Value>) -> Self {
+        let Some(Value::Object(obj)) = value else {
+            return Self::default();
+        };
+
+        let namespaces = |key: &str| -> Vec<(String, Vec<String>)> {
+            match obj.get(key) {
+                Some(Value::Object(map)) => map
+                    .iter()
+                    .map(|(ns, paths)| (ns.clone(), string_list(paths)))
+                    .collect(),
+                _ => Vec::new(),
+            }
+        };
+
+        AutoloadSection {
+            psr4: namespaces("psr-4"),
+            psr0: namespaces("psr-0"),
+            classmap: obj.get("classmap").map(string_list).unwrap_or_default(),
+            files: obj.get("files").map(string_list).unwrap_or_default(),
+            exclude: obj
+                .get("exclude-from-classmap")
+                .map(string_list)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+fn string_list(value: &Value) -> Vec<String> {
+    match value {
+        Value::String(s) => vec![s.clone()],
+        Value::Array(items) => items
+            .iter()
+            .filter_map(|v| v.as_str().map(str::to_string))
+            .collect(),
+        Value::Object(map) => map
+            .values()
+            .filter_map(|v| v.as_str().map(str::to_string))
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+pub(crate) fn read_json(path: &Path) -> Result<Value, Error> {
+    let data = fs::read(path).map_err(|e| Error::io(e, path))?;
+    serde_json::from_slice(&data).map_err(|e| {
+        Error::invalid_request(format!("{}: invalid JSON: {e}", path.display())).with_path(path)
+    })
+}
+
+/// Load the root package and every installed package for `project_dir` and
+/// resolve their autoload sections into absolute mappings.
+///
+/// With `dev_mode`, the root `autoload-dev` and the dev packages are included
+/// and their mappings flagged `dev`, as for a plain `composer dump-autoload`;
+/// without it they are left out before sorting, as for `--no-dev`.
+pub fn load_project(project_dir: &str, dev_mode: bool) -> Result<ComposerProject, Error> {
+    let project_path = fs::canonicalize(project_dir)
+        .map_err(|e| Error::io(e, project_dir))?
+        .to_string_lossy()
+        .into_owned();
+
+    let composer_file = std::env::var("COMPOSER").unwrap_or_else(|_| "composer.json".to_string());
+    let root_json = read_json(&Path::new(&project_path).join(composer_file))?;
+    let config = root_json.get("config");
+
+    let vendor_setting = std::env::var("COMPOSER_VENDOR_DIR")
+        .ok()
+        .unwrap_or_else(|| {
+            config
+                .and_then(|c| c.get("vendor-dir"))
+                .and_then(Value::as_str)
+                .unwrap_or("vendor")
+                .to_string()
+        });
+    let vendor_dir = join_normalized(&project_path, &vendor_setting);
+
+    let dev_autoload = if dev_mode {
+        AutoloadSection::parse(root_json.get("autoload-dev"))
+    } else {
+        AutoloadSection::default()
+    };
+
+    let mut root_requires = require_names(&root_json, "require");
+    root_requires.extend(require_names(&root_json, "require-dev"));
+
+    let root = Package {
+        name: root_json
+            .get("name")
+            .and_then(Value::as_str)
+            .unwrap_or("__root__")
+            .to_string(),
+        install_path: project_path.clone(),
+        target_dir: root_json
+            .get("target-dir")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        autoload: AutoloadSection::parse(root_json.get("autoload")),
+        dev_autoload,
+        include_paths: root_json
+            .get("include-path")
+            .map(string_list)
+            .unwrap_or_default(),
+        requires: root_requires,
+        is_root: true,
+        dev: false,
+    };
+
+    let mut packages = load_installed(&vendor_dir)?;
+    if !dev_mode {
+        packages.retain(|pkg| !pkg.dev);
+    }
+
+    let mut project = resolve(&root, &packages);
+    project.project_dir = project_path.clone();
+    project.vendor_dir = vendor_dir.clone();
+
+    project.root_package = RootPackage::from_composer_json(&root_json);
+    let runtime_dir = format!("{vendor_dir}/composer/composer/src/Composer");
+    project.runtime_dir = Path::new(&runtime_dir).is_dir().then_some(runtime_dir);
+
+    project.suffix = resolve_suffix(&project_path, &vendor_dir, config);
+    let flag = |key: &str, default: bool| {
+        config
+            .and_then(|c| c.get(key))
+            .and_then(Value::as_bool)
+            .unwrap_or(default)
+    };
+    project.classmap_authoritative = flag("classmap-authoritative", false);
+    project.apcu_autoloader = flag("apcu-autoloader", false);
+    project.apcu_prefix = config
+        .and_then(|c| c.get("apcu-autoloader-prefix"))
+        .and_then(Value::as_str)
+        .map(str::to_string);
+    project.prepend_autoloader = flag("prepend-autoloader", true);
+    project.platform_check = config
+        .and_then(|c| c.get("platform-check"))
+        .and_then(|v| PlatformCheck::deserialize(v).ok())
+        .unwrap_or(PlatformCheck::PhpOnly);
+    Ok(project)
+}
+
+/// Read `vendor/composer/installed.json` (Composer 1 or 2 format). A missing
+/// file simply means nothing is installed yet.
+fn load_installed(vendor_dir: &str) -> Result<Vec<Package>, Error> {
+    let composer_dir = format!("{vendor_dir}/composer");
+    let installed_path = Path::new(&composer_dir).join("installed.json");
+    if !installed_path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let installed = read_json(&installed_path)?;
+    let entries = match &installed {
+        Value::Array(items) => items.as_slice(),
+        Value::Object(obj) => obj
+            .get("packages")
+            .and_then(Value::as_array)
+            .map(Vec::as_slice)
+            .unwrap_or(&[]),
+        _ => &[],
+    };
+    let dev_package_names: Vec<&str> = installed
+        .get("dev-package-names")
+        .and_then(Value::as_array)
+        .map(|names| names.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+
+    let mut packages = Vec::with_capacity(entries.len());
+    for entry in entries {
+        let Some(name) = entry.get("name").and_then(Value::as_str) else {
+            continue;
+        };
+        let target_dir = entry
+            .get("target-dir")
+            .and_then(Value::as_str)
+            .filter(|t| !t.is_empty())
+            .map(str::to_string);
+
+        // Composer 2 records the install path relative to vendor/composer;
+        // metapackages have none and are skipped. Composer 1 has no field,
+        // so fall back to the default installer location.
+        let install_path = match entry.get("install-path") {
+            Some(Value::String(p)) => join_normalized(&composer_dir, p),
+            Some(Value::Null) => continue,
+            _ => {
+                let base = join_normalized(vendor_dir, name);
+                match &target_dir {
+                    Some(t) => join_normalized(&base, t),
+                    None => base,
+                }
+            }
+        };
+
+        packages.push(Package {
+            name: name.to_string(),
+            install_path,
+            target_dir,
+            autoload: AutoloadSection::parse(entry.get("autoload")),
+            dev_autoload: AutoloadSection::default(),
+            include_paths: entry
+                .get("include-path")
+                .map(string_list)
+                .unwrap_or_default(),
+            requires: require_names(entry, "require"),
+            is_root: false,
+            dev: dev_package_names.contains(&name),
+        });
+    }
+
+    Ok(packages)
+}
+
+fn require_names(package: &Value, key: &str) -> Vec<String> {
+    package
+        .get(key)
+        .and_then(Value::as_object)
+        .map(|m| m.keys().map(|k| k.to_lowercase()).collect())
+        .unwrap_or_default()
+}
+
+/// Mirror `parseAutoloads()`: psr-0/psr-4 and include paths come from the
+/// unsorted package map with the root first; classmap, files and excludes
+/// come from the dependency-sorted map with the root last (classmap reversed).
+fn resolve(root: &Package, packages: &[Package]) -> ComposerProject {
+    let mut package_map: Vec<&Package> = Vec::with_capacity(packages.len() + 1);
+    package_map.push(root);
+    package_map.extend(packages.iter());
+
+    let mut sorted_map = sort_packages(packages);
+    sorted_map.push(root);
+
+    let mut autoload = AutoloadMappings::default();
+    let mut exclude_from_classmap = Vec::new();
+
+    for pkg in &package_map {
+        let install_path = effective_install_path(pkg);
+        for (section, dev) in pkg.sections() {
+            for (namespace, paths) in &section.psr4 {
+                for path in paths {
+                    autoload.psr4.push(NamespaceMapping {
+                        namespace: namespace.clone(),
+                        path: join_normalized(&install_path, path),
+                        dev,
+                    });
+                }
+            }
+            for (namespace, paths) in &section.psr0 {
+                for path in paths {
+                    autoload.psr0.push(NamespaceMapping {
+                        namespace: namespace.clone(),
+                        path: join_normalized(&install_path, path),
+                        dev,
+                    });
+                }
+            }
+        }
+        for path in &pkg.include_paths {
+            autoload.include_path.push(ClassmapPath {
+                path: join_normalized(&install_path, path.trim_matches('/')),
+                dev: pkg.dev,
+            });
+        }
+    }
+
+    for pkg in sorted_map.iter().rev() {
+        let install_path = effective_install_path(pkg);
+        for (section, dev) in pkg.sections() {
+            for path in &section.classmap {
+                let path = adjust_for_target_dir(pkg, &install_path, path);
+                autoload.classmap.push(ClassmapPath {
+                    path: join_normalized(&install_path, &path),
+                    dev,
+                });
+            }
+        }
+    }
+
+    for pkg in &sorted_map {
+        let install_path = effective_install_path(pkg);
+        for (section, dev) in pkg.sections() {
+            for path in &section.files {
+                let path = adjust_for_target_dir(pkg, &install_path, path);
+                autoload.files.push(FileAutoload {
+                    identifier: file_identifier(&pkg.name, &path),
+                    path: join_normalized(&install_path, &path),
+                    dev,
+                });
+            }
+            for path in &section.exclude {
+                let path = adjust_for_target_dir(pkg, &install_path, path);
+                if let Some(pattern) = exclude_pattern(&install_path, &path) {
+                    exclude_from_classmap.push(pattern);
+                }
+            }
+        }
+    }
+
+    ComposerProject {
+        project_dir: String::new(),
+        vendor_dir: String::new(),
+        autoload,
+        exclude_from_classmap,
+        suffix: String::new(),
+        classmap_authoritative: false,
+        apcu_autoloader: false,
+        apcu_prefix: None,
+        prepend_autoloader: true,
+        root_package: RootPackage::default(),
+        runtime_dir: None,
+        platform_check: PlatformCheck::PhpOnly,
+    }
+}
+
+/// The installation manager's path includes `target-dir`; Composer strips it
+/// again for non-root packages so mappings resolve against the package root.
+fn effective_install_path(pkg: &Package) -> String {
+    match &pkg.target_dir {
+        Some(target) if !pkg.is_root => pkg
+            .install_path
+            .strip_suffix(&format!("/{target}"))
+            .unwrap_or(&pkg.install_path)
+            .to_string(),
+        _ => pkg.install_path.clone(),
+    }
+}
+
+/// `files`, `classmap` and `exclude-from-classmap` entries that are not
+/// readable as written get `target-dir` added (dependencies) or removed (root).
+fn adjust_for_target_dir(pkg: &Package, install_path: &str, path: &str) -> String {
+    let Some(target) = &pkg.target_dir else {
+        return path.to_string();
+    };
+    if Path::new(install_path).join(path).exists() {
+        return path.to_string();
+    }
+
+    if pkg.is_root {
+        let target = target.replace('\\', "/");
+        let trimmed = path.trim_start_matches(['/', '\\']);
+        trimmed
+            .strip_prefix(target.as_str())
+            .unwrap_or(trimmed)
+            .trim_start_matches(['/', '\\'])
+            .to_string()
+    } else {
+        format!("{target}/{path}")
+    }
+}
+
+/// Composer's `getFileIdentifier()`: `md5($package->getName() . ':' . $path)`.
+pub(crate) fn file_identifier(package_name: &str, path: &str) -> String {
+    let mut hasher = Md5::new();
+    hasher.update(package_name.as_bytes());
+    hasher.update(b":");
+    hasher.update(path.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// Convert an `exclude-from-classmap` glob into the absolute-path-anchored
+/// regex the walker expects: `*` matches within a segment, `**` across
+/// segments, and leading `../` segments are resolved against the package.
+pub(crate) fn exclude_pattern(install_path: &str, glob: &str) -> Option<String> {
+    let mut path = glob.replace('\\', "/").trim_matches('/').to_string();
+    while path.contains("//") {
+        path = path.replace("//", "/");
+    }
+
+    let mut updir = String::new();
+    while let Some(rest) = path
+        .strip_prefix("../")
+        .map(|r| ("../", r))
+        .or_else(|| path.strip_prefix("./").map(|r| ("./", r)))
+    {
+        updir.push_str(rest.0);
+        path = rest.1.to_string();
+    }
+
+    let resolved = fs::canonicalize(Path::new(install_path).join(&updir)).ok()?;
+    let resolved = resolved.to_string_lossy().replace('\\', "/");
+
+    let mut pattern = String::with_capacity(path.len() + 16);
+    let mut chars = path.chars().peekable();
+    while let Some(ch) = chars.next() {
+        if ch == '*' {
+            if chars.peek() == Some(&'*') {
+                chars.next();
+                pattern.push_str(".+?");
+            } else {
+                pattern.push_str("[^/]+?");
+            }
+        } else {
+            pattern.push_str(&regex::escape(&ch.to_string()));
+        }
+    }
+
+    let full = format!("{}/{}($|/)", regex::escape(&resolved), pattern);
+    Regex::new(&full).ok().map(|_| full)
+}
+
+/// Resolve the autoloader suffix the way `AutoloadGenerator::dump()` does:
+/// configured `autoloader-suffix`, else the suffix of the existing
+/// `vendor/autoload.php`, else the lock file's content-hash.
+fn resolve_suffix(project_dir: &str, vendor_dir: &str, config: Option<&Value>) -> String {
+    if let Some(suffix) = config
+        .and_then(|c| c.get("autoloader-suffix"))
+        .and_then(Value::as_str)
+    {
+        return suffix.to_string();
+    }
+
+    if let Ok(content) = fs::read_to_string(format!("{vendor_dir}/autoload.php")) {
+        let re = Regex::new(r"ComposerAutoloaderInit([^:\s]+)::").unwrap();
+        if let Some(m) = re.captures(&content).and_then(|c| c.get(1)) {
+            return m.as_str().to_string();
+        }
+    }
+
+    let lock_file = std::env::var("COMPOSER")
+        .ok()
+        .and_then(|c| c.strip_suffix(".json").map(|base| format!("{base}.lock")))
+        .unwrap_or_else(|| "composer.lock".to_string());
+    if let Ok(lock) = read_json(&Path::new(project_dir).join(lock_file)) {
+        if let Some(hash) = lock.get("content-hash").and_then(Value::as_str) {
+            return hash.to_string();
+        }
+    }
+
+    // Composer falls back to random bytes; any unique value will do.
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    file_identifier(project_dir, &nanos.to_string())
+}
+
+/// Composer's `PackageSorter::sortPackages()`: packages that many others
+/// depend on come first, ties broken by natural case-insensitive name order.
+fn sort_packages(packages: &[Package]) -> Vec<&Package> {
+    let mut usage: HashMap<String, Vec<String>> = HashMap::new();
+    for pkg in packages {
+        for target in &pkg.requires {
+            usage
+                .entry(target.clone())
+                .or_default()
+                .push(pkg.name.to_lowercase());
+        }
+    }
+
+    fn importance(
+        name: &str,
+        usage: &HashMap<String, Vec<String>>,
+        computing: &mut Vec<String>,
+        computed: &mut HashMap<String, i64>,
+    ) -> i64 {
+        if let Some(&weight) = computed.get(name) {
+            return weight;
+        }
+        // Circular dependency: bail out with a neutral weight.
+        if computing.iter().any(|n| n == name) {
+            return 0;
+        }
+        computing.push(name.to_string());
+        let mut weight = 0;
+        if let Some(users) = usage.get(name) {
+            for user in users {
+                weight -= 1 - importance(user, usage, computing, computed);
+            }
+        }
+        computing.pop();
+        computed.insert(name.to_string(), weight);
+        weight
+    }
+
+    let mut computing = Vec::new();
+    let mut computed = HashMap::new();
+    let mut weighted: Vec<(i64, &Package)> = packages
+        .iter()
+        .map(|pkg| {
+            let weight = importance(
+                &pkg.name.to_lowercase(),
+                &usage,
+                &mut computing,
+                &mut computed,
+            );
+            (weight, pkg)
+        })
+        .collect();
+
+    weighted.sort_by(|(wa, a), (wb, b)| {
+        wa.cmp(wb)
+            .then_with(|| natural_cmp_ignore_case(&a.name, &b.name))
+    });
+    weighted.into_iter().map(|(_, pkg)| pkg).collect()
+}
+
+/// `strnatcasecmp()`: digit runs compare numerically, everything else
+/// compares case-insensitively.
+fn natural_cmp_ignore_case(a: &str, b: &str) -> Ordering {
+    let a = a.to_lowercase();
+    let b = b.to_lowercase();
+    let mut ai = a.chars().peekable();
+    let mut bi = b.chars().peekable();
+
+    loop {
+        match (ai.peek().copied(), bi.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(ca), Some(cb)) if ca.is_ascii_digit() && cb.is_ascii_digit() => {
+                let mut na = String::new();
+                while let Some(c) = ai.peek().copied().filter(char::is_ascii_digit) {
+                    na.push(c);
+                    ai.next();
+                }
+                let mut nb = String::new();
+                while let Some(c) = bi.peek().copied().filter(char::is_ascii_digit) {
+                    nb.push(c);
+                    bi.next();
+                }
+                let na = na.trim_start_matches('0');
+                let nb = nb.trim_start_matches('0');
+                let ord = na.len().cmp(&nb.len()).then_with(|| na.cmp(nb));
+                if ord != Ordering::Equal {
+                    return ord;
+                }
+            }
+            (Some(ca), Some(cb)) => {
+                if ca != cb {
+                    return ca.cmp(&cb);
+                }
+                ai.next();
+                bi.next();
+            }
+        }
+    }
+}
+
+/// Join `path` onto `base` (unless already absolute) and normalise `.`/`..`
+/// lexically, like Composer's `Filesystem::normalizePath()`. Trailing slashes
+/// are dropped to match the payload PHP used to build.
+pub(crate) fn join_normalized(base: &str, path: &str) -> String {
+    let path = path.replace('\\', "/");
+    let joined = if Path::new(&path).is_absolute() {
+        PathBuf::from(&path)
+    } else {
+        Path::new(base).join(&path)
+    };
+
+    let mut parts: Vec<Component> = Vec::new();
+    for component in joined.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if matches!(parts.last(), Some(Component::Normal(_))) {
+                    parts.pop();
+                } else {
+                    parts.push(component);
+                }
+            }
+            other => parts.push(other),
+        }
+    }
+
+    let normalized: PathBuf = parts.iter().collect();
+    normalized.to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn write_json(path: &Path, value: serde_json::Value) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, value.to_string()).unwrap();
+    }
+
+    #[test]
+    fn file_identifier_matches_composer() {
+        // md5('__root__:src/helpers.php')
+        assert_eq!(
+            file_identifier("__root__", "src/helpers.php"),
+            "48483d6c44b015b6d6d681c009d084a7"
+        );
+    }
+
+    #[test]
+    fn join_normalized_resolves_dot_segments() {
+        assert_eq!(join_normalized("/app", "./src/"), "/app/src");
+        assert_eq!(
+            join_normalized("/app/vendor/composer", "../monolog/monolog"),
+            "/app/vendor/monolog/monolog"
+        );
+        assert_eq!(join_normalized("/app", "/abs/lib"), "/abs/lib");
+        assert_eq!(join_normalized("/app", ""), "/app");
+    }
+
+    #[test]
+    fn natural_sort_orders_digits_numerically() {
+        assert_eq!(natural_cmp_ignore_case("pkg/a2", "pkg/a10"), Ordering::Less);
+        assert_eq!(
+            natural_cmp_ignore_case("Acme/B", "acme/a"),
+            Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn sort_puts_dependencies_first() {
+        let packages = vec![
+            Package {
+                name: "acme/app-lib".to_string(),
+                requires: vec!["acme/base".to_string()],
+                ..Default::default()
+            },
+            Package {
+                name: "acme/base".to_string(),
+                ..Default::default()
+            },
+        ];
+        let sorted: Vec<&str> = sort_packages(&packages)
+            .iter()
+            .map(|p| p.name.as_str())
+            .collect();
+        assert_eq!(sorted, vec!["acme/base", "acme/app-lib"]);
+    }
+
+    #[test]
+    fn exclude_pattern_translates_globs() {
+        let tmp = TempDir::new().unwrap();
+        let real = fs::canonicalize(tmp.path()).unwrap();
+        let real = real.to_string_lossy();
+
+        let pattern = exclude_pattern(&real, "/src/**/Tests/").unwrap();
+        let re = Regex::new(&pattern).unwrap();
+        assert!(re.is_match(&format!("{real}/src/Foo/Tests/BarTest.php")));
+        assert!(!re.is_match(&format!("{real}/src/Tests/BarTest.php")));
+
+        let pattern = exclude_pattern(&real, "lib/*.php").unwrap();
+        let re = Regex::new(&pattern).unwrap();
+        assert!(re.is_match(&format!("{real}/lib/Foo.php")));
+        assert!(!re.is_match(&format!("{real}/lib/sub/Foo.php")));
+    }
+
+    #[test]
+    fn load_project_resolves_root_and_installed_packages() {
+        let tmp = TempDir::new().unwrap();
+        let root = fs::canonicalize(tmp.path()).unwrap();
+
+        write_json(
+            &root.join("composer.json"),
+            serde_json::json!({
+                "name": "acme/app",
+                "autoload": {
+                    "psr-4": { "App\\": "src/" },
+                    "files": ["src/helpers.php"],
+                    "exclude-from-classmap": ["/src/Legacy/"]
+                },
+                "autoload-dev": { "psr-4": { "App\\Tests\\": "tests/" } },
+                "include-path": ["lib/"],
+                "config": { "autoloader-suffix": "fixed" }
+            }),
+        );
+        fs::create_dir_all(root.join("src/Legacy")).unwrap();
+
+        write_json(
+            &root.join("vendor/composer/installed.json"),
+            serde_json::json!({
+                "packages": [
+                    {
+                        "name": "acme/lib",
+                        "install-path": "../acme/lib",
+                        "include-path": ["/pear"],
+                        "autoload": {
+                            "psr-0": { "Acme_": ["lib", "compat"] },
+                            "classmap": ["legacy/"],
+                            "files": ["bootstrap.php"]
+                        }
+                    },
+                    {
+                        "name": "symfony/yaml",
+                        "target-dir": "Symfony/Component/Yaml",
+                        "install-path": "../symfony/yaml/Symfony/Component/Yaml",
+                        "autoload": { "psr-0": { "Symfony\\Component\\Yaml\\": "" } }
+                    },
+                    { "name": "acme/meta", "install-path": null }
+                ]
+            }),
+        );
+
+        let project = load_project(&root.to_string_lossy(), true).unwrap();
+        let r = root.to_string_lossy();
+
+        assert_eq!(project.vendor_dir, format!("{r}/vendor"));
+        assert_eq!(project.suffix, "fixed");
+
+        let psr4: Vec<(&str, &str)> = project
+            .autoload
+            .psr4
+            .iter()
+            .map(|m| (m.namespace.as_str(), m.path.as_str()))
+            .collect();
+        let src = format!("{r}/src");
+        let tests = format!("{r}/tests");
+        assert_eq!(
+            psr4,
+            vec![("App\\", src.as_str()), ("App\\Tests\\", tests.as_str())]
+        );
+
+        let psr0: Vec<&str> = project
+            .autoload
+            .psr0
+            .iter()
+            .map(|m| m.path.as_str())
+            .collect();
+        assert!(psr0.contains(&format!("{r}/vendor/acme/lib/lib").as_str()));
+        assert!(psr0.contains(&format!("{r}/vendor/acme/lib/compat").as_str()));
+        // target-dir is stripped from the install path for dependencies
+        assert!(psr0.contains(&format!("{r}/vendor/symfony/yaml").as_str()));
+
+        assert_eq!(
+            project.autoload.classmap,
+            vec![ClassmapPath::from(format!("{r}/vendor/acme/lib/legacy"))]
+        );
+
+        // Include paths: root first, like psr-4/psr-0
+        assert_eq!(
+            project.autoload.include_path,
+            vec![
+                ClassmapPath::from(format!("{r}/lib")),
+                ClassmapPath::from(format!("{r}/vendor/acme/lib/pear")),
+            ]
+        );
+
+        // Files: dependencies first, root last, identifiers per Composer
+        assert_eq!(project.autoload.files.len(), 2);
+        assert_eq!(
+            project.autoload.files[0].path,
+            format!("{r}/vendor/acme/lib/bootstrap.php")
+        );
+        assert_eq!(
+            project.autoload.files[0].identifier,
+            file_identifier("acme/lib", "bootstrap.php")
+        );
+        assert_eq!(
+            project.autoload.files[1].identifier,
+            file_identifier("acme/app", "src/helpers.php")
+        );
+
+        assert_eq!(project.exclude_from_classmap.len(), 1);
+        let exclude = Regex::new(&project.exclude_from_classmap[0]).unwrap();
+        assert!(exclude.is_match(&format!("{r}/src/Legacy/Old.php")));
+    }
+
+    #[test]
+    fn load_project_reads_apcu_prefix() {
+        let tmp = TempDir::new().unwrap();
+        let root = tmp.path().to_string_lossy();
+
+        write_json(
+            &tmp.path().join("composer.json"),
+            serde_json::json!({ "config": { "apcu-autoloader": true } }),
+        );
+        let project = load_project(&root, true).unwrap();
+        assert!(project.apcu_autoloader);
+        assert_eq!(project.apcu_prefix, None);
+
+        write_json(
+            &tmp.path().join("composer.json"),
+            serde_json::json!({
+                "config": { "apcu-autoloader": true, "apcu-autoloader-prefix": "acme_" }
+            }),
+        );
+        let project = load_project(&root, true).unwrap();
+        assert_eq!(project.apcu_prefix.as_deref(), Some("acme_"));
+    }
+
+    #[test]
+    fn load_project_flags_dev_mappings_and_drops_them_without_dev_mode() {
+        let tmp = TempDir::new().unwrap();
+        let root = fs::canonicalize(tmp.path()).unwrap();
+        write_json(
+            &root.join("composer.json"),
+            serde_json::json!({
+                "name": "acme/app",
+                "autoload": { "psr-4": { "App\\": "src/" } },
+                "autoload-dev": {
+                    "psr-4": { "App\\": "tests/" },
+                    "classmap": ["fixtures/"],
+                    "files": ["tests/helpers.php"],
+                    "exclude-from-classmap": ["/fixtures/skip/"]
+                }
+            }),
+        );
+        write_json(
+            &root.join("vendor/composer/installed.json"),
+            serde_json::json!({
+                "packages": [
+                    {
+                        "name": "acme/lib",
+                        "install-path": "../acme/lib",
+                        "autoload": { "psr-4": { "Acme\\": "src/" } }
+                    },
+                    {
+                        "name": "phpunit/phpunit",
+                        "install-path": "../phpunit/phpunit",
+                        "autoload": { "classmap": ["src/"], "files": ["src/Framework/Assert/Functions.php"] }
+                    }
+                ],
+                "dev": true,
+                "dev-package-names": ["phpunit/phpunit"]
+            }),
+        );
+        fs::create_dir_all(root.join("fixtures/skip")).unwrap();
+        let r = root.to_string_lossy();
+
+        let dev = load_project(&r, true).unwrap();
+        let psr4: Vec<(&str, bool)> = dev
+            .autoload
+            .psr4
+            .iter()
+            .map(|m| (m.path.as_str(), m.dev))
+            .collect();
+        let (src, tests, acme) = (
+            format!("{r}/src"),
+            format!("{r}/tests"),
+            format!("{r}/vendor/acme/lib/src"),
+        );
+        assert_eq!(
+            psr4,
+            vec![
+                (src.as_str(), false),
+                (tests.as_str(), true),
+                (acme.as_str(), false)
+            ]
+        );
+        assert!(dev.autoload.classmap.iter().all(|c| c.dev));
+        assert_eq!(dev.autoload.classmap.len(), 2);
+        assert!(dev.autoload.files.iter().all(|f| f.dev));
+        assert_eq!(dev.exclude_from_classmap.len(), 1);
+
+        let no_dev = load_project(&r, false).unwrap();
+        let paths: Vec<&str> = no_dev
+            .autoload
+            .psr4
+            .iter()
+            .map(|m| m.path.as_str())
+            .collect();
+        assert_eq!(paths, vec![src.as_str(), acme.as_str()]);
+        assert!(no_dev.autoload.classmap.is_empty());
+        assert!(no_dev.autoload.files.is_empty());
+        assert!(no_dev.exclude_from_classmap.is_empty());
+    }
+
+    #[test]
+    fn load_project_without_installed_json() {
+        let tmp = TempDir::new().unwrap();
+        write_json(
+            &tmp.path().join("composer.json"),
+            serde_json::json!({ "autoload": { "classmap": ["lib/"] } }),
+        );
+        write_json(
+            &tmp.path().join("composer.lock"),
+            serde_json::json!({ "content-hash": "abc123" }),
+        );
+
+        let project = load_project(&tmp.path().to_string_lossy(), true).unwrap();
+        assert_eq!(project.autoload.classmap.len(), 1);
+        assert!(project.autoload.psr4.is_empty());
+        assert_eq!(project.suffix, "abc123");
+    }
+
+    #[test]
+    fn load_project_missing_composer_json_fails() {
+        let tmp = TempDir::new().unwrap();
+        assert!(load_project(&tmp.path().to_string_lossy(), true).is_err());
+    }
+}
@@ -1,21 +1,42 @@
 mod cache;
 mod codegen;
+mod installed;
+mod manifest;
 mod parser;
+mod platform;
 mod walker;
+mod watch;
 
+use md5::{Digest, Md5};
 use regex::Regex;
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::error::{Error, ErrorCode};
+use crate::limits::Limiter;
+use crate::trace::Trace;
 
 use cache::{load_cache, save_cache, CacheData};
 use codegen::{
-    generate_autoload_php, generate_autoload_real_php, generate_classmap_file,
-    generate_files_file, generate_namespaces_file, generate_psr4_file, generate_static_file,
+    generate_autoload_php, generate_autoload_real_php, generate_classmap_file, generate_files_file,
+    generate_include_paths_file, generate_namespaces_file, generate_platform_check_file,
+    generate_psr4_file, generate_static_file, LoaderOptions,
+};
+use installed::{
+    check_existing_runtime, generate_installed_file, installed_versions_path, runtime_files,
+    InstalledJson, INSTALLED_VERSIONS_CLASS,
 };
 use walker::walk_and_parse;
 
+pub use cache::CacheStore;
+pub use installed::RootPackage;
+pub use manifest::{load_project, ComposerProject};
+pub use platform::PlatformCheck;
+pub use watch::watch;
+
 #[derive(Debug, Deserialize, Default, Clone)]
 pub struct AutoloadMappings {
     #[serde(default, rename = "psr-4")]
@@ -23,23 +44,81 @@ pub struct AutoloadMappings {
     #[serde(default, rename = "psr-0")]
     pub psr0: Vec<NamespaceMapping>,
     #[serde(default)]
-    pub classmap: Vec<String>,
+    pub classmap: Vec<ClassmapPath>,
     #[serde(default)]
     pub files: Vec<FileAutoload>,
+    /// Legacy `include-path` entries, prepended to PHP's include path by
+    /// `autoload_real.php`.
+    #[serde(default, rename = "include-path")]
+    pub include_path: Vec<ClassmapPath>,
 }
 
+impl AutoloadMappings {
+    /// Drop the mappings flagged `dev`, as Composer does without dev mode.
+    pub fn without_dev(mut self) -> Self {
+        self.psr4.retain(|m| !m.dev);
+        self.psr0.retain(|m| !m.dev);
+        self.classmap.retain(|c| !c.dev);
+        self.files.retain(|f| !f.dev);
+        self.include_path.retain(|p| !p.dev);
+        self
+    }
+}
+
+/// Each mapping carries a `dev` flag: set for the root's `autoload-dev` and
+/// for packages installed only as dev requirements.
 #[derive(Debug, Deserialize, Clone)]
 pub struct NamespaceMapping {
     pub namespace: String,
     pub path: String,
+    #[serde(default)]
+    pub dev: bool,
+}
+
+/// A classmap root or include path: a plain path, or
+/// `{"path": ..., "dev": true}`.
+#[derive(Debug, Deserialize, Clone, PartialEq, Eq)]
+#[serde(from = "ClassmapPathRepr")]
+pub struct ClassmapPath {
+    pub path: String,
+    pub dev: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ClassmapPathRepr {
+    Path(String),
+    Flagged {
+        path: String,
+        #[serde(default)]
+        dev: bool,
+    },
+}
+
+impl From<ClassmapPathRepr> for ClassmapPath {
+    fn from(repr: ClassmapPathRepr) -> Self {
+        match repr {
+            ClassmapPathRepr::Path(path) => ClassmapPath { path, dev: false },
+            ClassmapPathRepr::Flagged { path, dev } => ClassmapPath { path, dev },
+        }
+    }
+}
+
+impl From<String> for ClassmapPath {
+    fn from(path: String) -> Self {
+        ClassmapPath { path, dev: false }
+    }
 }
 
 #[derive(Debug, Deserialize, Clone)]
 pub struct FileAutoload {
     pub identifier: String,
     pub path: String,
+    #[serde(default)]
+    pub dev: bool,
 }
 
+#[derive(Clone)]
 pub struct ClassmapConfig {
     pub project_dir: String,
     pub vendor_dir: String,
@@ -51,40 +130,264 @@ pub struct ClassmapConfig {
     pub staging_suffix: Option<String>,
     pub has_platform_check: bool,
     pub has_files_autoload: bool,
+    /// Span recorder; disabled unless the request set `trace_file`.
+    pub trace: Arc<Trace>,
+    /// Thread, I/O and memory limits for walking and parsing.
+    pub limits: Arc<Limiter>,
+    /// Compute everything but write nothing, not even the cache; report
+    /// which autoload files would change instead.
+    pub dry_run: bool,
+    /// Fail instead of writing anything when a class is declared by more
+    /// than one file (Composer's `--strict-ambiguous`).
+    pub strict_ambiguous: bool,
+    /// Fail instead of writing anything when a class in a psr-4/psr-0 root
+    /// does not match its file's path (Composer's `--strict-psr`).
+    pub strict_psr: bool,
+    /// Make the generated loader trust the classmap alone (Composer's
+    /// `--classmap-authoritative`): classes missing from it are not looked
+    /// up in the psr-4/psr-0 directories at runtime.
+    pub authoritative: bool,
+    /// Cache class lookups in APCu (Composer's `--apcu-autoloader`).
+    pub apcu_autoloader: bool,
+    /// APCu key prefix; a random one is generated when unset. Only used with
+    /// `apcu_autoloader`.
+    pub apcu_prefix: Option<String>,
+    /// Prepend the loader to the SPL autoload stack (Composer's
+    /// `config.prepend-autoloader`, on by default).
+    pub prepend_autoloader: bool,
+    /// Also write `installed.php` for this root package and the packages in
+    /// `vendor/composer/installed.json`, and map `Composer\InstalledVersions`.
+    pub root_package: Option<RootPackage>,
+    /// Composer's `src/Composer` directory; `ClassLoader.php`,
+    /// `InstalledVersions.php` and `LICENSE` are copied from it.
+    pub runtime_dir: Option<String>,
+    /// Generate `platform_check.php` from the root's and installed packages'
+    /// requirements (Composer's `platform-check`). When unset, the file is
+    /// left alone and `has_platform_check` decides whether it is required.
+    pub platform_check: Option<PlatformCheck>,
+    /// Platform requirements left out of the check (`--ignore-platform-req`);
+    /// a trailing `*` matches a prefix.
+    pub ignore_platform_reqs: Vec<String>,
+    /// Include the mappings flagged `dev`; off for `--no-dev`.
+    pub dev_mode: bool,
+    /// Scan the psr-4/psr-0 roots into the classmap, as an optimised dump
+    /// does. Without it only `classmap` roots are scanned, like Composer's
+    /// default dump; `authoritative` always scans them.
+    pub scan_psr_packages: bool,
 }
 
-#[derive(Debug, Serialize)]
-struct Output {
-    classmap_count: usize,
+impl ClassmapConfig {
+    /// A config with the same defaults as a JSON request that only sets these
+    /// fields: files are written, nothing is traced or limited.
+    pub fn new(
+        project_dir: impl Into<String>,
+        vendor_dir: impl Into<String>,
+        autoload: AutoloadMappings,
+    ) -> Self {
+        ClassmapConfig {
+            project_dir: project_dir.into(),
+            vendor_dir: vendor_dir.into(),
+            autoload,
+            exclude_from_classmap: Vec::new(),
+            target_dir: None,
+            suffix: None,
+            write_files: true,
+            staging_suffix: None,
+            has_platform_check: false,
+            has_files_autoload: false,
+            trace: Arc::default(),
+            limits: Arc::default(),
+            dry_run: false,
+            strict_ambiguous: false,
+            strict_psr: false,
+            authoritative: false,
+            apcu_autoloader: false,
+            apcu_prefix: None,
+            prepend_autoloader: true,
+            root_package: None,
+            runtime_dir: None,
+            platform_check: None,
+            ignore_platform_reqs: Vec::new(),
+            dev_mode: true,
+            scan_psr_packages: true,
+        }
+    }
+
+    /// The roots to scan and filter against: without `scan_psr_packages`
+    /// only the classmap roots, whose classes Composer never filters.
+    pub(crate) fn scanned_roots(&self) -> ResolvedRoots {
+        if self.scan_psr_packages || self.authoritative {
+            ResolvedRoots::new(&self.autoload)
+        } else {
+            ResolvedRoots::new(&AutoloadMappings {
+                classmap: self.autoload.classmap.clone(),
+                ..AutoloadMappings::default()
+            })
+        }
+    }
+
+    /// Apply `dev_mode`: without it, dev mappings are dropped and
+    /// `has_files_autoload` follows whatever `files` remain.
+    fn resolve_dev_mode(&mut self) {
+        if !self.dev_mode {
+            self.autoload = std::mem::take(&mut self.autoload).without_dev();
+            self.has_files_autoload &= !self.autoload.files.is_empty();
+        }
+    }
+}
+
+/// The result of [`dump`]. File contents are returned unless they were
+/// written with a staging suffix.
+#[derive(Debug, Clone, Serialize)]
+pub struct Output {
+    pub classmap_count: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub classmap_file_content: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub static_file_content: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    classmap_file_content: Option<String>,
+    pub psr4_file_content: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    static_file_content: Option<String>,
+    pub namespaces_file_content: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    psr4_file_content: Option<String>,
+    pub files_file_content: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    namespaces_file_content: Option<String>,
+    pub installed_file_content: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    files_file_content: Option<String>,
-    files_written: bool,
-    stats: Stats,
+    pub platform_check_file_content: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub include_paths_file_content: Option<String>,
+    pub files_written: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub dry_run: bool,
+    /// With `dry_run`: autoload files whose content would differ from what
+    /// is on disk now (or that do not exist yet).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub would_change: Option<Vec<String>>,
+    /// Classes declared by more than one file, sorted by class name.
+    pub ambiguous: Vec<AmbiguousClass>,
+    /// Classes left out because they do not match their file's path under a
+    /// psr-4/psr-0 root, sorted by file.
+    pub psr_violations: Vec<PsrViolation>,
+    pub stats: Stats,
 }
 
-#[derive(Debug, Serialize)]
-struct Stats {
-    files_scanned: usize,
-    php_files_found: usize,
-    directories_walked: usize,
-    cache_hits: usize,
-    walk_skipped: bool,
-    elapsed_ms: u128,
-    walk_ms: u128,
-    parse_ms: u128,
-    generate_ms: u128,
+/// A class in a psr-4/psr-0 root whose name does not match its file's path.
+/// Composer skips these with "Class X located in Y does not comply with
+/// psr-4 autoloading standard".
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PsrViolation {
+    pub class: String,
+    pub file: String,
+    /// `psr-4` or `psr-0`.
+    pub standard: &'static str,
+    /// Namespace prefix of the mapping the file is under.
+    pub namespace: String,
+    /// Directory of that mapping.
+    pub base_path: String,
+    /// Where the mapping expects the class to live, if anywhere.
+    pub expected: Option<String>,
+}
+
+/// A class found in several files. Composer warns about these as "Ambiguous
+/// class resolution" and maps the class to `chosen`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct AmbiguousClass {
+    pub class: String,
+    pub chosen: String,
+    pub others: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Stats {
+    pub files_scanned: usize,
+    pub php_files_found: usize,
+    pub directories_walked: usize,
+    pub cache_hits: usize,
+    /// The directory cache was valid, so no directory was walked.
+    pub walk_skipped: bool,
+    pub elapsed_ms: u128,
+    pub walk_ms: u128,
+    pub parse_ms: u128,
+    pub generate_ms: u128,
+}
+
+pub fn run(config: ClassmapConfig) -> Result<serde_json::Value, Error> {
+    dump(config).map(|output| serde_json::to_value(output).unwrap())
+}
+
+/// Scan the autoload roots and generate (and usually write) the autoload
+/// files, using the `.turbo-cache` in `target_dir`. The typed form of [`run`].
+pub fn dump(config: ClassmapConfig) -> Result<Output, Error> {
+    let cache_path = cache_path(&config);
+    let trace = config.trace.clone();
+    let cache: CacheData = {
+        let _span = trace.span("cache", "cache_load");
+        cache_path
+            .as_ref()
+            .map(|p| load_cache(p))
+            .unwrap_or_default()
+    };
+
+    let dry_run = config.dry_run;
+    let (output, new_cache) = generate(config, &cache)?;
+
+    if let (Some(ref cp), false) = (&cache_path, dry_run) {
+        let _span = trace.span("cache", "cache_save");
+        save_cache(cp, &new_cache);
+    }
+
+    Ok(output)
 }
 
-pub fn run(config: ClassmapConfig) -> serde_json::Value {
+/// Like [`run`], but the incremental cache lives in `store` between calls
+/// instead of being read from and written back to `.turbo-cache` each time.
+/// Used by long-running processes; call [`CacheStore::flush`] to persist.
+///
+/// `store` is locked only to take this project's entry and to put it back,
+/// so requests for other projects are not held up by the walk.
+pub fn run_with_store(
+    config: ClassmapConfig,
+    store: &Mutex<CacheStore>,
+) -> Result<serde_json::Value, Error> {
+    dump_with_store(config, store).map(|output| serde_json::to_value(output).unwrap())
+}
+
+/// The typed form of [`run_with_store`].
+pub fn dump_with_store(config: ClassmapConfig, store: &Mutex<CacheStore>) -> Result<Output, Error> {
+    let Some(cache_path) = cache_path(&config) else {
+        return generate(config, &CacheData::default()).map(|(output, _)| output);
+    };
+    let lock = || store.lock().unwrap_or_else(|e| e.into_inner());
+
+    let cache = {
+        let _span = config.trace.span("cache", "cache_load");
+        lock().take(&cache_path)
+    };
+    let dry_run = config.dry_run;
+    match generate(config, &cache) {
+        Ok((output, new_cache)) => {
+            lock().put(cache_path, if dry_run { cache } else { new_cache });
+            Ok(output)
+        }
+        Err(e) => {
+            lock().put(cache_path, cache);
+            Err(e)
+        }
+    }
+}
+
+fn cache_path(config: &ClassmapConfig) -> Option<PathBuf> {
+    config
+        .target_dir
+        .as_ref()
+        .map(|td| Path::new(td).join(".turbo-cache"))
+}
+
+fn generate(mut config: ClassmapConfig, cache: &CacheData) -> Result<(Output, CacheData), Error> {
     let start = std::time::Instant::now();
+    config.resolve_dev_mode();
+    let trace = &*config.trace;
 
     let excludes: Vec<Regex> = config
         .exclude_from_classmap
@@ -96,123 +399,124 @@ pub fn run(config: ClassmapConfig) -> serde_json::Value {
         })
         .collect();
 
-    // Skip fs::canonicalize syscall for absolute paths without ".." components
-    let all_dirs: Vec<String> = config
-        .autoload
-        .psr4
-        .iter()
-        .map(|m| m.path.as_str())
-        .chain(config.autoload.psr0.iter().map(|m| m.path.as_str()))
-        .chain(config.autoload.classmap.iter().map(String::as_str))
-        .map(|d| {
-            if Path::new(d).is_absolute() && !d.contains("..") {
-                d.to_string()
-            } else {
-                fs::canonicalize(d)
-                    .map(|p| p.to_string_lossy().to_string())
-                    .unwrap_or_else(|_| d.to_string())
-            }
-        })
-        .collect();
-
-    let dir_refs: Vec<&str> = all_dirs.iter().map(String::as_str).collect();
-
-    let cache_path = config
-        .target_dir
-        .as_ref()
-        .map(|td| Path::new(td).join(".turbo-cache"));
-    let cache: CacheData = cache_path
-        .as_ref()
-        .map(|p| load_cache(p))
-        .unwrap_or_default();
+    let roots = config.scanned_roots();
+    let dir_refs = roots.dirs();
 
     let vendor_real =
         fs::canonicalize(&config.vendor_dir).unwrap_or_else(|_| PathBuf::from(&config.vendor_dir));
     let vendor_str = vendor_real.to_string_lossy().to_string();
 
     let walk_parse_start = std::time::Instant::now();
-    let walk_result = walk_and_parse(&dir_refs, &excludes, &cache, &vendor_str);
+    let walk_result = walk_and_parse(
+        &dir_refs,
+        &excludes,
+        cache,
+        &vendor_str,
+        &config.limits,
+        trace,
+    )?;
     let walk_parse_ms = walk_parse_start.elapsed().as_millis();
 
     let sort_start = std::time::Instant::now();
+    let psr_span = trace.span("classmap", "psr_filter");
 
-    // Resolve PSR-4/PSR-0/classmap base paths using the same logic as `all_dirs`
-    // so that prefix-matching against walker output is consistent.
-    let resolve_path = |d: &str| -> String {
-        if Path::new(d).is_absolute() && !d.contains("..") {
-            d.to_string()
-        } else {
-            fs::canonicalize(d)
-                .map(|p| p.to_string_lossy().to_string())
-                .unwrap_or_else(|_| d.to_string())
-        }
-    };
-
-    let psr4_resolved: Vec<(String, String)> = config
-        .autoload
-        .psr4
-        .iter()
-        .map(|m| (m.namespace.clone(), resolve_path(&m.path)))
-        .collect();
-
-    let psr0_resolved: Vec<(String, String)> = config
-        .autoload
-        .psr0
-        .iter()
-        .map(|m| (m.namespace.clone(), resolve_path(&m.path)))
-        .collect();
-
-    let classmap_resolved: Vec<String> = config
-        .autoload
-        .classmap
-        .iter()
-        .map(|d| resolve_path(d))
-        .collect();
-
-    // Use first-wins semantics to match Composer's behaviour.
     // Filter classes by PSR-4/PSR-0 compliance — Composer only includes
     // classes whose FQCN maps to the correct filename under the namespace
     // mapping. Secondary classes in a file (that don't match the filename)
     // are excluded, matching Composer's `filterByNamespace()` logic.
-    let mut classmap: BTreeMap<String, String> = BTreeMap::new();
-    for (class, path) in &walk_result.entries {
-        if is_class_valid(
-            class,
-            path,
-            &psr4_resolved,
-            &psr0_resolved,
-            &classmap_resolved,
-        ) {
-            classmap
-                .entry(class.clone())
-                .or_insert_with(|| path.clone());
+    let Resolved {
+        mut classmap,
+        ambiguous,
+        psr_violations,
+    } = resolve_classmap(&walk_result.entries, &roots);
+    drop(psr_span);
+    let sort_ms = sort_start.elapsed().as_millis();
+
+    if config.strict_psr {
+        if let Some(first) = psr_violations.first() {
+            return Err(Error::new(
+                ErrorCode::PsrViolation,
+                format!(
+                    "{} classes do not comply with their autoloading standard; class {} \
+                     located in {} does not comply with {} (rule: {} => {})",
+                    psr_violations.len(),
+                    first.class,
+                    first.file,
+                    first.standard,
+                    first.namespace,
+                    first.base_path,
+                ),
+            )
+            .with_path(&first.file));
         }
     }
-    let sort_ms = sort_start.elapsed().as_millis();
+
+    if config.strict_ambiguous {
+        if let Some(first) = ambiguous.first() {
+            return Err(Error::new(
+                ErrorCode::AmbiguousClass,
+                format!(
+                    "ambiguous class resolution for {} classes; \"{}\" was found in \"{}\" and \"{}\"",
+                    ambiguous.len(),
+                    first.class,
+                    first.chosen,
+                    first.others.join("\", \""),
+                ),
+            )
+            .with_path(&first.chosen));
+        }
+    }
+
+    // Like Composer, map InstalledVersions whenever installed.php is
+    // generated, whether or not a root contains it.
+    if config.root_package.is_some() {
+        classmap.insert(
+            INSTALLED_VERSIONS_CLASS.to_string(),
+            installed_versions_path(&vendor_str),
+        );
+    }
 
     let gen_start = std::time::Instant::now();
     let classmap_count = classmap.len();
 
-    let base_real =
-        fs::canonicalize(&config.project_dir).unwrap_or_else(|_| PathBuf::from(&config.project_dir));
+    let base_real = fs::canonicalize(&config.project_dir)
+        .unwrap_or_else(|_| PathBuf::from(&config.project_dir));
     let base_str = base_real.to_string_lossy().to_string();
 
-    let classmap_file_content = generate_classmap_file(&classmap, &vendor_str, &base_str);
-    let psr4_file_content = generate_psr4_file(&config.autoload.psr4, &vendor_str, &base_str);
-    let namespaces_file_content =
-        generate_namespaces_file(&config.autoload.psr0, &vendor_str, &base_str);
-    let files_file_content = generate_files_file(&config.autoload.files, &vendor_str, &base_str);
+    let codegen = |name: &'static str| trace.span("codegen", name);
+    let classmap_file_content = {
+        let _span = codegen("generate_classmap_file");
+        generate_classmap_file(&classmap, &vendor_str, &base_str)
+    };
+    let psr4_file_content = {
+        let _span = codegen("generate_psr4_file");
+        generate_psr4_file(&config.autoload.psr4, &vendor_str, &base_str)
+    };
+    let namespaces_file_content = {
+        let _span = codegen("generate_namespaces_file");
+        generate_namespaces_file(&config.autoload.psr0, &vendor_str, &base_str)
+    };
+    let files_file_content = {
+        let _span = codegen("generate_files_file");
+        generate_files_file(&config.autoload.files, &vendor_str, &base_str)
+    };
+    let include_paths_content = {
+        let _span = codegen("generate_include_paths_file");
+        generate_include_paths_file(&config.autoload.include_path, &vendor_str, &base_str)
+    };
+
+    let td = config.target_dir.as_deref().unwrap_or("");
+    let td_real = if !td.is_empty() {
+        fs::canonicalize(td)
+            .unwrap_or_else(|_| PathBuf::from(td))
+            .to_string_lossy()
+            .to_string()
+    } else {
+        String::new()
+    };
 
     let static_file_content = if let Some(ref sfx) = config.suffix {
-        let td = config.target_dir.as_deref().unwrap_or("");
-        let td_real = if !td.is_empty() {
-            fs::canonicalize(td)
-                .unwrap_or_else(|_| PathBuf::from(td))
-                .to_string_lossy()
-                .to_string()
-        } else {
-            String::new()
-        };
+        let _span = codegen("generate_static_file");
         generate_static_file(
             sfx,
             &config.autoload.psr4,
@@ -228,14 +532,91 @@ pub fn run(config: ClassmapConfig) -> serde_json::Value {
     };
 
     // Generate autoload.php and autoload_real.php when we have a suffix
-    let autoload_php_content = config
-        .suffix
-        .as_ref()
-        .map(|sfx| generate_autoload_php(sfx));
+    let autoload_php_content = config.suffix.as_ref().map(|sfx| {
+        let _span = codegen("generate_autoload_php");
+        generate_autoload_php(sfx)
+    });
+    let platform_mode = config
+        .platform_check
+        .filter(|mode| *mode != PlatformCheck::Disabled);
+    let installed = if config.root_package.is_some() || platform_mode.is_some() {
+        Some(InstalledJson::load(&vendor_str)?)
+    } else {
+        None
+    };
+    let platform_check_content = match (platform_mode, &installed) {
+        (Some(mode), Some(installed)) => {
+            let _span = codegen("generate_platform_check_file");
+            let requirements = platform::collect(
+                mode,
+                config.root_package.as_ref(),
+                installed,
+                &config.ignore_platform_reqs,
+            );
+            generate_platform_check_file(&requirements)
+        }
+        _ => None,
+    };
+    // Like Composer, drop an existing check once there is nothing to check.
+    let has_platform_check = match config.platform_check {
+        Some(_) => platform_check_content.is_some(),
+        None => config.has_platform_check,
+    };
+
+    // A dry run keeps the prefix already on disk, so an unchanged project
+    // does not report autoload_real.php for a fresh random one.
+    let apcu_prefix = config.apcu_autoloader.then(|| {
+        config
+            .apcu_prefix
+            .clone()
+            .or_else(|| {
+                let td = config.target_dir.as_deref().filter(|_| config.dry_run)?;
+                existing_apcu_prefix(Path::new(td))
+            })
+            .unwrap_or_else(random_apcu_prefix)
+    });
     let autoload_real_php_content = config.suffix.as_ref().map(|sfx| {
-        generate_autoload_real_php(sfx, config.has_platform_check, config.has_files_autoload)
+        let _span = codegen("generate_autoload_real_php");
+        generate_autoload_real_php(
+            sfx,
+            &LoaderOptions {
+                has_platform_check,
+                has_files_autoload: config.has_files_autoload,
+                has_include_paths: include_paths_content.is_some(),
+                authoritative: config.authoritative,
+                apcu_prefix: apcu_prefix.as_deref(),
+                prepend: config.prepend_autoloader,
+            },
+        )
     });
 
+    let installed_file_content = match (&config.root_package, &installed, &config.target_dir) {
+        (Some(root), Some(installed), Some(_)) => {
+            let _span = codegen("generate_installed_file");
+            Some(generate_installed_file(
+                root, installed, &base_str, &td_real,
+            ))
+        }
+        _ => None,
+    };
+    let runtime = match config.runtime_dir {
+        Some(ref dir) => runtime_files(dir)?,
+        None => Vec::new(),
+    };
+    // A staged run is finished by PHP, whose own dump writes the runtime
+    // files when it could not give us Composer's sources.
+    if let (true, None, Some(td)) = (
+        runtime.is_empty() && config.write_files,
+        &config.staging_suffix,
+        &config.target_dir,
+    ) {
+        check_existing_runtime(
+            Path::new(td),
+            autoload_real_php_content.is_some(),
+            config.root_package.is_some(),
+        )?;
+    }
+
     let generate_ms = gen_start.elapsed().as_millis();
 
     // Determine whether we write files directly or return contents via JSON.
@@ -244,60 +625,94 @@ pub fn run(config: ClassmapConfig) -> serde_json::Value {
     let use_staging = config.staging_suffix.is_some();
     let suffix_ext = config.staging_suffix.as_deref().unwrap_or("");
 
-    let files_written = if config.write_files || use_staging {
+    // Every file a real run writes, at its live (unstaged) path.
+    let mut outputs: Vec<(PathBuf, &str)> = Vec::new();
+    if let Some(ref td) = config.target_dir {
+        let td_path = Path::new(td);
+        outputs.push((
+            td_path.join("autoload_classmap.php"),
+            &classmap_file_content,
+        ));
+        outputs.push((td_path.join("autoload_psr4.php"), &psr4_file_content));
+        outputs.push((
+            td_path.join("autoload_namespaces.php"),
+            &namespaces_file_content,
+        ));
+        if !files_file_content.is_empty() {
+            outputs.push((td_path.join("autoload_files.php"), &files_file_content));
+        }
+        if !static_file_content.is_empty() {
+            outputs.push((td_path.join("autoload_static.php"), &static_file_content));
+        }
+        // Write autoload infrastructure files when suffix is available
+        if let Some(ref content) = autoload_php_content {
+            outputs.push((Path::new(&config.vendor_dir).join("autoload.php"), content));
+        }
+        if let Some(ref content) = autoload_real_php_content {
+            outputs.push((td_path.join("autoload_real.php"), content));
+        }
+        if let Some(ref content) = installed_file_content {
+            outputs.push((td_path.join("installed.php"), content));
+        }
+        if let Some(ref content) = platform_check_content {
+            outputs.push((td_path.join("platform_check.php"), content));
+        }
+        if let Some(ref content) = include_paths_content {
+            outputs.push((td_path.join("include_paths.php"), content));
+        }
+        for (name, content) in &runtime {
+            outputs.push((td_path.join(name), content));
+        }
+    }
+
+    // Files an earlier dump wrote that the new autoload_real.php no longer
+    // requires; Composer deletes them.
+    let stale: Vec<PathBuf> = match config.target_dir {
+        Some(ref td) => [
+            (
+                "platform_check.php",
+                config.platform_check.is_some() && platform_check_content.is_none(),
+            ),
+            (
+                "include_paths.php",
+                config.suffix.is_some() && include_paths_content.is_none(),
+            ),
+        ]
+        .into_iter()
+        .filter(|(_, is_stale)| *is_stale)
+        .map(|(name, _)| Path::new(td).join(name))
+        .filter(|path| path.exists())
+        .collect(),
+        None => Vec::new(),
+    };
+
+    let would_change = config.dry_run.then(|| {
+        outputs
+            .iter()
+            .filter(|(path, content)| fs::read(path).ok().as_deref() != Some(content.as_bytes()))
+            .map(|(path, _)| path)
+            .chain(&stale)
+            .filter_map(|path| Some(path.file_name()?.to_string_lossy().into_owned()))
+            .collect::<Vec<_>>()
+    });
+
+    let files_written = if !config.dry_run && (config.write_files || use_staging) {
         if let Some(ref td) = config.target_dir {
             let td_path = Path::new(td);
-            let vendor_path = Path::new(&config.vendor_dir);
-            let write_result = (|| -> Result<(), std::io::Error> {
-                fs::write(
-                    td_path.join(format!("autoload_classmap.php{suffix_ext}")),
-                    &classmap_file_content,
-                )?;
-                fs::write(
-                    td_path.join(format!("autoload_psr4.php{suffix_ext}")),
-                    &psr4_file_content,
-                )?;
-                fs::write(
-                    td_path.join(format!("autoload_namespaces.php{suffix_ext}")),
-                    &namespaces_file_content,
-                )?;
-
-                if !files_file_content.is_empty() {
-                    fs::write(
-                        td_path.join(format!("autoload_files.php{suffix_ext}")),
-                        &files_file_content,
-                    )?;
-                }
-
-                if !static_file_content.is_empty() {
-                    fs::write(
-                        td_path.join(format!("autoload_static.php{suffix_ext}")),
-                        &static_file_content,
-                    )?;
-                }
-
-                // Write autoload infrastructure files when suffix is available
-                if let Some(ref content) = autoload_php_content {
-                    fs::write(
-                        vendor_path.join(format!("autoload.php{suffix_ext}")),
-                        content,
-                    )?;
-                }
-                if let Some(ref content) = autoload_real_php_content {
-                    fs::write(
-                        td_path.join(format!("autoload_real.php{suffix_ext}")),
-                        content,
-                    )?;
-                }
-
-                Ok(())
-            })();
-
-            if let Err(e) = write_result {
-                eprintln!("turbo-rust: failed to write autoload files: {e}");
-                std::process::exit(1);
+            fs::create_dir_all(td_path).map_err(|e| Error::io(e, td_path))?;
+            for (path, content) in &outputs {
+                let mut staged = path.clone().into_os_string();
+                staged.push(suffix_ext);
+                let path = PathBuf::from(staged);
+                let _span = trace
+                    .span("write", "write_file")
+                    .arg("path", path.to_string_lossy())
+                    .arg("bytes", content.len());
+                fs::write(&path, content).map_err(|e| Error::io(e, &path))?;
+            }
+            for path in &stale {
+                fs::remove_file(path).map_err(|e| Error::io(e, path))?;
             }
-
             true
         } else {
             false
@@ -306,10 +721,6 @@ pub fn run(config: ClassmapConfig) -> serde_json::Value {
         false
     };
 
-    if let Some(ref cp) = cache_path {
-        save_cache(cp, &walk_result.new_cache);
-    }
-
     // When staging, skip returning file contents — they're already on disk.
     let include_contents = !use_staging;
 
@@ -340,7 +751,14 @@ pub fn run(config: ClassmapConfig) -> serde_json::Value {
         } else {
             None
         },
+        installed_file_content: installed_file_content.filter(|_| include_contents),
+        platform_check_file_content: platform_check_content.filter(|_| include_contents),
+        include_paths_file_content: include_paths_content.filter(|_| include_contents),
         files_written,
+        dry_run: config.dry_run,
+        would_change,
+        ambiguous,
+        psr_violations,
         stats: Stats {
             files_scanned: walk_result.files_scanned,
             php_files_found: walk_result.php_files_found,
@@ -354,7 +772,206 @@ pub fn run(config: ClassmapConfig) -> serde_json::Value {
         },
     };
 
-    serde_json::to_value(output).unwrap()
+    Ok((output, walk_result.new_cache))
+}
+
+/// Autoload roots resolved the same way the walker sees them, so that
+/// prefix-matching against walker output is consistent.
+pub(crate) struct ResolvedRoots {
+    psr4: Vec<(String, String)>,
+    psr0: Vec<(String, String)>,
+    classmap: Vec<String>,
+    /// Every root in the order Composer scans them; see [`Self::scan_rank`].
+    scan_order: Vec<String>,
+}
+
+impl ResolvedRoots {
+    pub(crate) fn new(autoload: &AutoloadMappings) -> Self {
+        let mut roots = Self {
+            psr4: autoload
+                .psr4
+                .iter()
+                .map(|m| (m.namespace.clone(), resolve_path(&m.path)))
+                .collect(),
+            psr0: autoload
+                .psr0
+                .iter()
+                .map(|m| (m.namespace.clone(), resolve_path(&m.path)))
+                .collect(),
+            classmap: autoload
+                .classmap
+                .iter()
+                .map(|d| resolve_path(&d.path))
+                .collect(),
+            scan_order: Vec::new(),
+        };
+
+        // Composer scans classmap roots first, then the psr-4 and psr-0 roots
+        // grouped by namespace in reverse order (`krsort`), psr-4 first.
+        let mut namespaced: Vec<(&str, u8, usize, &str)> = roots
+            .psr4
+            .iter()
+            .enumerate()
+            .map(|(i, (ns, dir))| (ns.as_str(), 0, i, dir.as_str()))
+            .chain(
+                roots
+                    .psr0
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (ns, dir))| (ns.as_str(), 1, i, dir.as_str())),
+            )
+            .collect();
+        namespaced.sort_by(|a, b| b.0.cmp(a.0).then((a.1, a.2).cmp(&(b.1, b.2))));
+        let scan_order = roots
+            .classmap
+            .iter()
+            .map(String::as_str)
+            .chain(namespaced.into_iter().map(|(_, _, _, dir)| dir))
+            .map(str::to_string)
+            .collect();
+        roots.scan_order = scan_order;
+        roots
+    }
+
+    /// Every root to walk: psr-4, then psr-0, then classmap.
+    pub(crate) fn dirs(&self) -> Vec<&str> {
+        self.psr4
+            .iter()
+            .map(|(_, d)| d.as_str())
+            .chain(self.psr0.iter().map(|(_, d)| d.as_str()))
+            .chain(self.classmap.iter().map(String::as_str))
+            .collect()
+    }
+
+    pub(crate) fn is_class_valid(&self, class: &str, file_path: &str) -> bool {
+        is_class_valid(class, file_path, &self.psr4, &self.psr0, &self.classmap)
+    }
+
+    fn psr_violation(&self, class: &str, file_path: &str) -> Option<PsrViolation> {
+        psr_violation(class, file_path, &self.psr4, &self.psr0, &self.classmap)
+    }
+
+    /// Position of the first root Composer would find `file_path` under.
+    /// Composer never scans a file twice, so this is also the root the file
+    /// is attributed to, and a lower rank wins an ambiguous class.
+    pub(crate) fn scan_rank(&self, file_path: &str) -> usize {
+        self.scan_order
+            .iter()
+            .position(|root| {
+                file_path == root
+                    || file_path
+                        .strip_prefix(root.trim_end_matches('/'))
+                        .is_some_and(|rest| rest.starts_with('/'))
+            })
+            .unwrap_or(self.scan_order.len())
+    }
+}
+
+/// The classmap built from the walker's output, with what was left out of it.
+pub(crate) struct Resolved {
+    pub classmap: BTreeMap<String, String>,
+    pub ambiguous: Vec<AmbiguousClass>,
+    pub psr_violations: Vec<PsrViolation>,
+}
+
+/// Build the classmap from the walker's `(class, file)` pairs, keeping only
+/// PSR-compliant classes. When a class is declared by several files, the one
+/// Composer would have scanned first wins (see [`ResolvedRoots::scan_rank`]),
+/// and the rest are reported as ambiguous.
+///
+/// Like Composer, a rejected class is only reported as a PSR violation when
+/// its file declares no compliant class at all; helper classes next to a
+/// file's main class are skipped silently.
+pub(crate) fn resolve_classmap(entries: &[(String, String)], roots: &ResolvedRoots) -> Resolved {
+    let mut candidates: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+    let mut psr_violations: Vec<PsrViolation> = Vec::new();
+    let mut files_with_valid: HashSet<&str> = HashSet::new();
+    for (class, path) in entries {
+        match roots.psr_violation(class, path) {
+            None => {
+                candidates.entry(class).or_default().push(path);
+                files_with_valid.insert(path);
+            }
+            Some(violation) => psr_violations.push(violation),
+        }
+    }
+    psr_violations.retain(|v| !files_with_valid.contains(v.file.as_str()));
+    psr_violations.sort_by(|a, b| (&a.file, &a.class).cmp(&(&b.file, &b.class)));
+
+    let mut classmap = BTreeMap::new();
+    let mut ambiguous = Vec::new();
+    for (class, mut paths) in candidates {
+        if paths.len() > 1 {
+            paths.sort_by_cached_key(|p| (roots.scan_rank(p), *p));
+            paths.dedup();
+        }
+        let chosen = paths[0];
+        let others: Vec<String> = paths[1..]
+            .iter()
+            .filter(|other| !is_test_path(chosen) && !is_test_path(other))
+            .map(|other| other.to_string())
+            .collect();
+        if !others.is_empty() {
+            ambiguous.push(AmbiguousClass {
+                class: class.to_string(),
+                chosen: chosen.to_string(),
+                others,
+            });
+        }
+        classmap.insert(class.to_string(), chosen.to_string());
+    }
+    Resolved {
+        classmap,
+        ambiguous,
+        psr_violations,
+    }
+}
+
+/// Whether `path` lies in a test, fixture, example or stub directory.
+/// Composer does not warn about duplicates there, since such directories
+/// routinely redeclare the classes they exercise.
+fn is_test_path(path: &str) -> bool {
+    let parent = Path::new(path).parent().unwrap_or(Path::new(""));
+    parent.components().any(|c| {
+        let name = c.as_os_str().to_string_lossy().to_ascii_lowercase();
+        let name = name.strip_suffix('s').unwrap_or(&name);
+        matches!(name, "test" | "fixture" | "example" | "stub")
+    })
+}
+
+/// Composer's default APCu prefix is `bin2hex(random_bytes(10))`; any value
+/// unique to this dump will do.
+fn random_apcu_prefix() -> String {
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos())
+        .unwrap_or_default();
+    let digest = Md5::new()
+        .chain_update(nanos.to_le_bytes())
+        .chain_update(std::process::id().to_le_bytes())
+        .finalize();
+    digest[..10].iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// The `setApcuPrefix()` argument of the `autoload_real.php` in `target_dir`.
+fn existing_apcu_prefix(target_dir: &Path) -> Option<String> {
+    let content = fs::read_to_string(target_dir.join("autoload_real.php")).ok()?;
+    let start = content.find("$loader->setApcuPrefix('")? + "$loader->setApcuPrefix('".len();
+    let len = content[start..].find("');")?;
+    let prefix = &content[start..start + len];
+    // Anything php_var_export would have escaped is not a prefix we wrote.
+    (!prefix.contains(['\\', '\''])).then(|| prefix.to_string())
+}
+
+/// Skip the fs::canonicalize syscall for absolute paths without ".." components.
+fn resolve_path(d: &str) -> String {
+    if Path::new(d).is_absolute() && !d.contains("..") {
+        d.to_string()
+    } else {
+        fs::canonicalize(d)
+            .map(|p| p.to_string_lossy().to_string())
+            .unwrap_or_else(|_| d.to_string())
+    }
 }
 
 /// Check whether a class should be included in the classmap, applying PSR-4/PSR-0
@@ -372,6 +989,17 @@ fn is_class_valid(
     psr0: &[(String, String)],
     classmap_dirs: &[String],
 ) -> bool {
+    psr_violation(class, file_path, psr4, psr0, classmap_dirs).is_none()
+}
+
+/// The reason [`is_class_valid`] rejects a class, or `None` if it does not.
+fn psr_violation(
+    class: &str,
+    file_path: &str,
+    psr4: &[(String, String)],
+    psr0: &[(String, String)],
+    classmap_dirs: &[String],
+) -> Option<PsrViolation> {
     // Classmap directories: always include all classes.
     for cm_dir in classmap_dirs {
         let prefix = if cm_dir.ends_with('/') {
@@ -380,10 +1008,23 @@ fn is_class_valid(
             format!("{cm_dir}/")
         };
         if file_path.starts_with(&prefix) || file_path == cm_dir.as_str() {
-            return true;
+            return None;
         }
     }
 
+    let violation =
+        |standard: &'static str, namespace: &str, base_path: &str, expected: Option<String>| {
+            Some(PsrViolation {
+                class: class.to_string(),
+                file: file_path.to_string(),
+                standard,
+                namespace: namespace.to_string(),
+                base_path: base_path.to_string(),
+                expected: expected
+                    .map(|rel| format!("{}/{rel}.php", base_path.trim_end_matches('/'))),
+            })
+        };
+
     // PSR-4: find the longest (most specific) matching base path.
     let mut best_psr4: Option<(&str, &str)> = None;
     for (ns, base) in psr4 {
@@ -402,7 +1043,15 @@ fn is_class_valid(
     }
 
     if let Some((ns_prefix, base_path)) = best_psr4 {
-        return is_psr4_compliant(class, ns_prefix, base_path, file_path);
+        if is_psr4_compliant(class, ns_prefix, base_path, file_path) {
+            return None;
+        }
+        return violation(
+            "psr-4",
+            ns_prefix,
+            base_path,
+            psr4_relative(class, ns_prefix),
+        );
     }
 
     // PSR-0: find the longest matching base path.
@@ -422,12 +1071,26 @@ fn is_class_valid(
         }
     }
 
-    if let Some((_, base_path)) = best_psr0 {
-        return is_psr0_compliant(class, base_path, file_path);
+    if let Some((ns_prefix, base_path)) = best_psr0 {
+        if is_psr0_compliant(class, base_path, file_path) {
+            return None;
+        }
+        return violation("psr-0", ns_prefix, base_path, Some(psr0_relative(class)));
     }
 
     // Not in any known mapping — include conservatively.
-    true
+    None
+}
+
+/// The part of `file_path` under `base_path`, without the `.php` extension.
+fn relative_stem<'a>(base_path: &str, file_path: &'a str) -> Option<&'a str> {
+    let sep = if base_path.ends_with('/') { "" } else { "/" };
+    let rel_start = base_path.len() + sep.len();
+    if file_path.len() <= rel_start {
+        return None;
+    }
+    let relative = &file_path[rel_start..];
+    Some(relative.strip_suffix(".php").unwrap_or(relative))
 }
 
 /// PSR-4: class `Foo\Bar\Baz` with prefix `Foo\` and base `/path/to/foo`
@@ -437,44 +1100,40 @@ fn is_class_valid(
 /// `substr($class, strlen($baseNamespace))` — it strips N characters from the
 /// FQCN regardless of whether the class actually starts with the prefix.
 fn is_psr4_compliant(class: &str, ns_prefix: &str, base_path: &str, file_path: &str) -> bool {
-    // Strip .php extension from the relative path
-    let sep = if base_path.ends_with('/') { "" } else { "/" };
-    let rel_start = base_path.len() + sep.len();
-    if file_path.len() <= rel_start {
+    let Some(relative) = relative_stem(base_path, file_path) else {
         return false;
-    }
-    let relative = &file_path[rel_start..];
-    let relative = relative.strip_suffix(".php").unwrap_or(relative);
+    };
+    psr4_relative(class, ns_prefix).is_some_and(|expected| expected == relative)
+}
 
+/// Where PSR-4 expects `class` relative to the mapping's base directory,
+/// without extension. `None` if the class is no longer than the prefix.
+fn psr4_relative(class: &str, ns_prefix: &str) -> Option<String> {
     // Positional strip: remove N characters where N = namespace prefix length.
     // This matches Composer's `substr($class, strlen($baseNamespace))`.
     let prefix_len = ns_prefix.len();
     let sub_class = if prefix_len > 0 && class.len() > prefix_len {
-        &class[prefix_len..]
+        class.get(prefix_len..)?
     } else if prefix_len == 0 {
         class
     } else {
-        return false;
+        return None;
     };
 
     // Convert namespace separators to path separators
-    let expected = sub_class.replace('\\', "/");
-    expected == relative
+    Some(sub_class.replace('\\', "/"))
 }
 
 /// PSR-0: class `Foo\Bar_Baz` with base `/path/to/lib` expects file at
 /// `/path/to/lib/Foo/Bar/Baz.php` (namespace `\` → `/`, classname `_` → `/`).
 fn is_psr0_compliant(class: &str, base_path: &str, file_path: &str) -> bool {
-    let sep = if base_path.ends_with('/') { "" } else { "/" };
-    let rel_start = base_path.len() + sep.len();
-    if file_path.len() <= rel_start {
-        return false;
-    }
-    let relative = &file_path[rel_start..];
-    let relative = relative.strip_suffix(".php").unwrap_or(relative);
+    relative_stem(base_path, file_path).is_some_and(|relative| psr0_relative(class) == relative)
+}
 
+/// Where PSR-0 expects `class` relative to the mapping's base directory.
+fn psr0_relative(class: &str) -> String {
     // PSR-0: split at last backslash
-    let expected = if let Some(last_bs) = class.rfind('\\') {
+    if let Some(last_bs) = class.rfind('\\') {
         let namespace_part = &class[..last_bs + 1]; // includes trailing backslash
         let class_name = &class[last_bs + 1..];
         let ns_path = namespace_part.replace('\\', "/");
@@ -482,9 +1141,7 @@ fn is_psr0_compliant(class: &str, base_path: &str, file_path: &str) -> bool {
         format!("{ns_path}{cls_path}")
     } else {
         class.replace('_', "/")
-    };
-
-    expected == relative
+    }
 }
 
 #[cfg(test)]
@@ -504,19 +1161,151 @@ mod tests {
         write_files: bool,
     ) -> ClassmapConfig {
         ClassmapConfig {
-            project_dir,
-            vendor_dir,
-            autoload,
             exclude_from_classmap,
             target_dir,
             suffix,
             write_files,
-            staging_suffix: None,
-            has_platform_check: false,
-            has_files_autoload: false,
+            ..ClassmapConfig::new(project_dir, vendor_dir, autoload)
         }
     }
 
+    #[test]
+    fn dump_returns_typed_output() {
+        let tmp = TempDir::new().unwrap();
+        let src_dir = tmp.path().join("src");
+        fs::create_dir_all(&src_dir).unwrap();
+        fs::write(
+            src_dir.join("Foo.php"),
+            "<?php\nnamespace Acme;\nclass Foo {}\n",
+        )
+        .unwrap();
+
+        let autoload = AutoloadMappings {
+            psr4: vec![NamespaceMapping {
+                namespace: "Acme\\".to_string(),
+                path: src_dir.to_string_lossy().to_string(),
+                dev: false,
+            }],
+            ..Default::default()
+        };
+        let mut config = ClassmapConfig::new(
+            tmp.path().to_string_lossy(),
+            tmp.path().join("vendor").to_string_lossy(),
+            autoload,
+        );
+        config.write_files = false;
+
+        let output = dump(config).unwrap();
+        assert_eq!(output.classmap_count, 1);
+        assert_eq!(output.stats.php_files_found, 1);
+        assert!(!output.files_written);
+        assert!(output
+            .classmap_file_content
+            .unwrap()
+            .contains("Acme\\\\Foo"));
+    }
+
+    #[test]
+    fn dump_without_dev_mode_skips_dev_mappings() {
+        let tmp = TempDir::new().unwrap();
+        let src_dir = tmp.path().join("src");
+        let tests_dir = tmp.path().join("tests");
+        fs::create_dir_all(&src_dir).unwrap();
+        fs::create_dir_all(&tests_dir).unwrap();
+        fs::write(
+            src_dir.join("Foo.php"),
+            "<?php\nnamespace Acme;\nclass Foo {}\n",
+        )
+        .unwrap();
+        fs::write(
+            tests_dir.join("FooTest.php"),
+            "<?php\nnamespace Acme\\Tests;\nclass FooTest {}\n",
+        )
+        .unwrap();
+
+        let autoload = AutoloadMappings {
+            psr4: vec![
+                NamespaceMapping {
+                    namespace: "Acme\\".to_string(),
+                    path: src_dir.to_string_lossy().to_string(),
+                    dev: false,
+                },
+                NamespaceMapping {
+                    namespace: "Acme\\Tests\\".to_string(),
+                    path: tests_dir.to_string_lossy().to_string(),
+                    dev: true,
+                },
+            ],
+            files: vec![FileAutoload {
+                identifier: "abc".to_string(),
+                path: tests_dir.join("helpers.php").to_string_lossy().to_string(),
+                dev: true,
+            }],
+            ..Default::default()
+        };
+        let mut config = ClassmapConfig::new(
+            tmp.path().to_string_lossy(),
+            tmp.path().join("vendor").to_string_lossy(),
+            autoload,
+        );
+        config.write_files = false;
+        config.suffix = Some("x".to_string());
+        config.has_files_autoload = true;
+        config.dev_mode = false;
+
+        let output = dump(config).unwrap();
+        assert_eq!(output.classmap_count, 1);
+        assert!(!output.psr4_file_content.unwrap().contains("tests"));
+        assert_eq!(output.files_file_content.as_deref(), Some(""));
+        assert!(!output.static_file_content.unwrap().contains("$files"));
+    }
+
+    #[test]
+    fn dump_without_psr_scan_maps_only_classmap_roots() {
+        let tmp = TempDir::new().unwrap();
+        let src_dir = tmp.path().join("src");
+        let lib_dir = tmp.path().join("lib");
+        fs::create_dir_all(&src_dir).unwrap();
+        fs::create_dir_all(&lib_dir).unwrap();
+        fs::write(
+            src_dir.join("Foo.php"),
+            "<?php\nnamespace Acme;\nclass Foo {}\n",
+        )
+        .unwrap();
+        // Classmap roots are not filtered by namespace.
+        fs::write(lib_dir.join("legacy.php"), "<?php\nclass Legacy_Thing {}\n").unwrap();
+
+        let autoload = AutoloadMappings {
+            psr4: vec![NamespaceMapping {
+                namespace: "Acme\\".to_string(),
+                path: src_dir.to_string_lossy().to_string(),
+                dev: false,
+            }],
+            classmap: vec![lib_dir.to_string_lossy().to_string().into()],
+            ..Default::default()
+        };
+        let mut config = ClassmapConfig::new(
+            tmp.path().to_string_lossy(),
+            tmp.path().join("vendor").to_string_lossy(),
+            autoload,
+        );
+        config.write_files = false;
+        config.suffix = Some("x".to_string());
+        config.scan_psr_packages = false;
+
+        let output = dump(config.clone()).unwrap();
+        assert_eq!(output.classmap_count, 1);
+        assert_eq!(output.stats.php_files_found, 1);
+        let classmap = output.classmap_file_content.unwrap();
+        assert!(classmap.contains("Legacy_Thing"));
+        assert!(!classmap.contains("Acme"));
+        assert!(output.psr4_file_content.unwrap().contains("Acme"));
+        assert!(output.static_file_content.is_some());
+
+        config.authoritative = true;
+        assert_eq!(dump(config).unwrap().classmap_count, 2);
+    }
+
     #[test]
     fn run_with_real_files() {
         let tmp = TempDir::new().unwrap();
@@ -536,10 +1325,12 @@ mod tests {
             psr4: vec![NamespaceMapping {
                 namespace: "Acme\\".to_string(),
                 path: src_dir.to_string_lossy().to_string(),
+                dev: false,
             }],
             psr0: vec![],
             classmap: vec![],
             files: vec![],
+            include_path: vec![],
         };
 
         let result = run(test_config(
@@ -550,7 +1341,8 @@ mod tests {
             None,
             None,
             true,
-        ));
+        ))
+        .unwrap();
 
         assert_eq!(result["classmap_count"].as_u64().unwrap(), 2);
         let content = result["classmap_file_content"].as_str().unwrap();
@@ -575,10 +1367,12 @@ mod tests {
             psr4: vec![NamespaceMapping {
                 namespace: "App\\".to_string(),
                 path: src_dir.to_string_lossy().to_string(),
+                dev: false,
             }],
             psr0: vec![],
             classmap: vec![],
             files: vec![],
+            include_path: vec![],
         };
 
         // Build exclude pattern as PHP now does: absolute-path-anchored regex.
@@ -595,7 +1389,8 @@ mod tests {
             None,
             None,
             true,
-        ));
+        ))
+        .unwrap();
 
         let content = result["classmap_file_content"].as_str().unwrap();
         assert!(content.contains("App\\\\Main"));
@@ -612,10 +1407,12 @@ mod tests {
             psr4: vec![NamespaceMapping {
                 namespace: "App\\".to_string(),
                 path: src_dir.to_string_lossy().to_string(),
+                dev: false,
             }],
             psr0: vec![],
             classmap: vec![],
             files: vec![],
+            include_path: vec![],
         };
 
         let result = run(test_config(
@@ -626,7 +1423,8 @@ mod tests {
             None,
             None,
             true,
-        ));
+        ))
+        .unwrap();
 
         assert_eq!(result["classmap_count"].as_u64().unwrap(), 0);
     }
@@ -637,10 +1435,12 @@ mod tests {
             psr4: vec![NamespaceMapping {
                 namespace: "App\\".to_string(),
                 path: "/nonexistent/path/that/does/not/exist".to_string(),
+                dev: false,
             }],
             psr0: vec![],
             classmap: vec![],
             files: vec![],
+            include_path: vec![],
         };
 
         let result = run(test_config(
@@ -651,7 +1451,8 @@ mod tests {
             None,
             None,
             true,
-        ));
+        ))
+        .unwrap();
 
         assert_eq!(result["classmap_count"].as_u64().unwrap(), 0);
     }
@@ -673,10 +1474,12 @@ mod tests {
             psr4: vec![NamespaceMapping {
                 namespace: "App\\".to_string(),
                 path: src_dir.to_string_lossy().to_string(),
+                dev: false,
             }],
             psr0: vec![],
             classmap: vec![],
             files: vec![],
+            include_path: vec![],
         };
 
         let result1 = run(test_config(
@@ -687,7 +1490,8 @@ mod tests {
             Some(target_dir.to_string_lossy().to_string()),
             None,
             true,
-        ));
+        ))
+        .unwrap();
         assert_eq!(result1["classmap_count"].as_u64().unwrap(), 2);
         assert!(!result1["stats"]["walk_skipped"].as_bool().unwrap());
 
@@ -699,7 +1503,8 @@ mod tests {
             Some(target_dir.to_string_lossy().to_string()),
             None,
             true,
-        ));
+        ))
+        .unwrap();
         assert_eq!(result2["classmap_count"].as_u64().unwrap(), 2);
         assert!(result2["stats"]["walk_skipped"].as_bool().unwrap());
         assert_eq!(result2["stats"]["directories_walked"].as_u64().unwrap(), 0);
@@ -720,10 +1525,12 @@ mod tests {
             psr4: vec![NamespaceMapping {
                 namespace: "App\\".to_string(),
                 path: src_dir.to_string_lossy().to_string(),
+                dev: false,
             }],
             psr0: vec![],
             classmap: vec![],
             files: vec![],
+            include_path: vec![],
         };
 
         let result1 = run(test_config(
@@ -734,7 +1541,8 @@ mod tests {
             Some(target_dir.to_string_lossy().to_string()),
             None,
             true,
-        ));
+        ))
+        .unwrap();
         assert_eq!(result1["classmap_count"].as_u64().unwrap(), 1);
 
         std::thread::sleep(std::time::Duration::from_secs(1));
@@ -749,7 +1557,8 @@ mod tests {
             Some(target_dir.to_string_lossy().to_string()),
             None,
             true,
-        ));
+        ))
+        .unwrap();
         assert_eq!(result2["classmap_count"].as_u64().unwrap(), 2);
         assert!(!result2["stats"]["walk_skipped"].as_bool().unwrap());
     }
@@ -769,10 +1578,12 @@ mod tests {
             psr4: vec![NamespaceMapping {
                 namespace: "App\\".to_string(),
                 path: src_dir.to_string_lossy().to_string(),
+                dev: false,
             }],
             psr0: vec![],
             classmap: vec![],
             files: vec![],
+            include_path: vec![],
         };
 
         let result1 = run(test_config(
@@ -783,7 +1594,8 @@ mod tests {
             Some(target_dir.to_string_lossy().to_string()),
             None,
             true,
-        ));
+        ))
+        .unwrap();
         assert_eq!(result1["classmap_count"].as_u64().unwrap(), 1);
         assert_eq!(result1["stats"]["cache_hits"].as_u64().unwrap(), 0);
 
@@ -809,10 +1621,61 @@ mod tests {
             Some(target_dir.to_string_lossy().to_string()),
             None,
             true,
-        ));
+        ))
+        .unwrap();
         assert_eq!(result2["classmap_count"].as_u64().unwrap(), 2);
     }
 
+    #[test]
+    fn run_with_store_defers_cache_writes_until_flush() {
+        let tmp = TempDir::new().unwrap();
+        let src_dir = tmp.path().join("src");
+        let target_dir = tmp.path().join("composer");
+        fs::create_dir_all(&src_dir).unwrap();
+        fs::create_dir_all(&target_dir).unwrap();
+
+        let mut f = fs::File::create(src_dir.join("Foo.php")).unwrap();
+        writeln!(f, "<?php\nnamespace App;\nclass Foo {{}}").unwrap();
+
+        let autoload = AutoloadMappings {
+            psr4: vec![NamespaceMapping {
+                namespace: "App\\".to_string(),
+                path: src_dir.to_string_lossy().to_string(),
+                dev: false,
+            }],
+            psr0: vec![],
+            classmap: vec![],
+            files: vec![],
+            include_path: vec![],
+        };
+        let config = || {
+            test_config(
+                tmp.path().to_string_lossy().to_string(),
+                tmp.path().join("vendor").to_string_lossy().to_string(),
+                autoload.clone(),
+                vec![],
+                Some(target_dir.to_string_lossy().to_string()),
+                None,
+                true,
+            )
+        };
+
+        let store = Mutex::new(CacheStore::new());
+        let result1 = run_with_store(config(), &store).unwrap();
+        assert!(!result1["stats"]["walk_skipped"].as_bool().unwrap());
+        assert!(!target_dir.join(".turbo-cache").exists());
+        assert!(store.lock().unwrap().is_dirty());
+
+        let result2 = run_with_store(config(), &store).unwrap();
+        assert_eq!(result2["classmap_count"].as_u64().unwrap(), 1);
+        assert!(result2["stats"]["walk_skipped"].as_bool().unwrap());
+
+        let mut store = store.into_inner().unwrap();
+        assert_eq!(store.flush(), 1);
+        assert!(!store.is_dirty());
+        assert!(target_dir.join(".turbo-cache").exists());
+    }
+
     #[test]
     fn cache_format_v2_includes_dir_mtimes() {
         let tmp = TempDir::new().unwrap();
@@ -831,10 +1694,12 @@ mod tests {
             psr4: vec![NamespaceMapping {
                 namespace: "App\\".to_string(),
                 path: src_dir.to_string_lossy().to_string(),
+                dev: false,
             }],
             psr0: vec![],
             classmap: vec![],
             files: vec![],
+            include_path: vec![],
         };
 
         let _ = run(test_config(
@@ -845,7 +1710,8 @@ mod tests {
             Some(target_dir.to_string_lossy().to_string()),
             None,
             true,
-        ));
+        ))
+        .unwrap();
 
         let cache_path = target_dir.join(".turbo-cache");
         assert!(cache_path.exists());
@@ -857,6 +1723,29 @@ mod tests {
         assert!(!data["dir_mtimes"].as_object().unwrap().is_empty());
     }
 
+    #[test]
+    fn write_failure_returns_error_with_path() {
+        let tmp = TempDir::new().unwrap();
+        let blocker = tmp.path().join("blocker");
+        fs::write(&blocker, "").unwrap();
+
+        let target_dir = blocker.join("composer").to_string_lossy().into_owned();
+
+        let err = run(test_config(
+            tmp.path().to_string_lossy().into_owned(),
+            tmp.path().to_string_lossy().into_owned(),
+            AutoloadMappings::default(),
+            vec![],
+            Some(target_dir.clone()),
+            None,
+            true,
+        ))
+        .unwrap_err();
+
+        assert_eq!(err.code, crate::ErrorCode::Io);
+        assert_eq!(err.path, Some(PathBuf::from(target_dir)));
+    }
+
     #[test]
     fn staging_suffix_writes_with_suffix_and_omits_contents() {
         let tmp = TempDir::new().unwrap();
@@ -880,10 +1769,12 @@ mod tests {
                 psr4: vec![NamespaceMapping {
                     namespace: "App\\".to_string(),
                     path: src_dir.to_string_lossy().to_string(),
+                    dev: false,
                 }],
                 psr0: vec![],
                 classmap: vec![],
                 files: vec![],
+                include_path: vec![],
             },
             exclude_from_classmap: vec![],
             target_dir: Some(target_dir.to_string_lossy().to_string()),
@@ -892,7 +1783,23 @@ mod tests {
             staging_suffix: Some(".turbo".to_string()),
             has_platform_check: true,
             has_files_autoload: false,
-        });
+            trace: Default::default(),
+            limits: Default::default(),
+            dry_run: false,
+            strict_ambiguous: false,
+            strict_psr: false,
+            authoritative: false,
+            apcu_autoloader: false,
+            apcu_prefix: None,
+            prepend_autoloader: true,
+            root_package: None,
+            runtime_dir: None,
+            platform_check: None,
+            ignore_platform_reqs: Vec::new(),
+            dev_mode: true,
+            scan_psr_packages: true,
+        })
+        .unwrap();
 
         // File contents should NOT be in the JSON response
         assert!(result.get("classmap_file_content").is_none());
@@ -1066,10 +1973,12 @@ mod tests {
             psr4: vec![NamespaceMapping {
                 namespace: "App\\".to_string(),
                 path: src_dir.to_string_lossy().to_string(),
+                dev: false,
             }],
             psr0: vec![],
             classmap: vec![],
             files: vec![],
+            include_path: vec![],
         };
 
         let result = run(test_config(
@@ -1080,7 +1989,8 @@ mod tests {
             None,
             None,
             true,
-        ));
+        ))
+        .unwrap();
 
         let content = result["classmap_file_content"].as_str().unwrap();
         // PhpFilesAdapter matches PSR-4 (class name = filename)
@@ -1091,4 +2001,226 @@ mod tests {
             "LazyValue should be excluded by PSR-4 compliance check"
         );
     }
+
+    fn ambiguous_project(tmp: &TempDir) -> AutoloadMappings {
+        let src_dir = tmp.path().join("src");
+        let legacy_dir = tmp.path().join("legacy");
+        let tests_dir = tmp.path().join("tests");
+        for dir in [&src_dir, &legacy_dir, &tests_dir] {
+            fs::create_dir_all(dir).unwrap();
+        }
+        fs::write(
+            src_dir.join("Foo.php"),
+            "<?php\nnamespace App;\nclass Foo {}\n",
+        )
+        .unwrap();
+        fs::write(
+            legacy_dir.join("Foo.php"),
+            "<?php\nnamespace App;\nclass Foo {}\n",
+        )
+        .unwrap();
+        fs::write(
+            src_dir.join("Bar.php"),
+            "<?php\nnamespace App;\nclass Bar {}\n",
+        )
+        .unwrap();
+        fs::write(
+            tests_dir.join("Bar.php"),
+            "<?php\nnamespace App;\nclass Bar {}\n",
+        )
+        .unwrap();
+
+        AutoloadMappings {
+            psr4: vec![NamespaceMapping {
+                namespace: "App\\".to_string(),
+                path: src_dir.to_string_lossy().to_string(),
+                dev: false,
+            }],
+            classmap: vec![
+                legacy_dir.to_string_lossy().to_string().into(),
+                tests_dir.to_string_lossy().to_string().into(),
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn ambiguous_classes_prefer_classmap_roots_and_are_reported() {
+        let tmp = TempDir::new().unwrap();
+        let mut config = ClassmapConfig::new(
+            tmp.path().to_string_lossy(),
+            tmp.path().join("vendor").to_string_lossy(),
+            ambiguous_project(&tmp),
+        );
+        config.write_files = false;
+
+        let output = dump(config).unwrap();
+        let legacy = tmp
+            .path()
+            .join("legacy/Foo.php")
+            .to_string_lossy()
+            .to_string();
+        let src = tmp.path().join("src/Foo.php").to_string_lossy().to_string();
+        // Duplicates involving a tests/ directory are not reported.
+        assert_eq!(
+            output.ambiguous,
+            vec![AmbiguousClass {
+                class: "App\\Foo".to_string(),
+                chosen: legacy.clone(),
+                others: vec![src],
+            }]
+        );
+        assert!(output
+            .classmap_file_content
+            .unwrap()
+            .contains("/legacy/Foo.php"));
+    }
+
+    #[test]
+    fn strict_ambiguous_fails_without_writing() {
+        let tmp = TempDir::new().unwrap();
+        let target_dir = tmp.path().join("composer");
+        let mut config = ClassmapConfig::new(
+            tmp.path().to_string_lossy(),
+            tmp.path().join("vendor").to_string_lossy(),
+            ambiguous_project(&tmp),
+        );
+        config.target_dir = Some(target_dir.to_string_lossy().to_string());
+        config.strict_ambiguous = true;
+
+        let err = dump(config).unwrap_err();
+        assert_eq!(err.code, ErrorCode::AmbiguousClass);
+        assert!(err.message.contains("App\\Foo"));
+        assert!(!target_dir.join("autoload_classmap.php").exists());
+    }
+
+    #[test]
+    fn scan_rank_follows_composer_scan_order() {
+        let roots = ResolvedRoots::new(&AutoloadMappings {
+            psr4: vec![
+                NamespaceMapping {
+                    namespace: "App\\".to_string(),
+                    path: "/p/app".to_string(),
+                    dev: false,
+                },
+                NamespaceMapping {
+                    namespace: "App\\Sub\\".to_string(),
+                    path: "/p/sub".to_string(),
+                    dev: false,
+                },
+            ],
+            classmap: vec!["/p/lib".to_string().into()],
+            ..Default::default()
+        });
+        assert_eq!(roots.scan_rank("/p/lib/A.php"), 0);
+        assert_eq!(roots.scan_rank("/p/sub/A.php"), 1);
+        assert_eq!(roots.scan_rank("/p/app/A.php"), 2);
+        assert_eq!(roots.scan_rank("/p/application/A.php"), 3);
+    }
+
+    #[test]
+    fn psr_violations_are_reported_for_files_without_a_compliant_class() {
+        let tmp = TempDir::new().unwrap();
+        let src_dir = tmp.path().join("src");
+        fs::create_dir_all(src_dir.join("Models")).unwrap();
+        // Misnamed file: reported.
+        fs::write(
+            src_dir.join("Models/Usr.php"),
+            "<?php\nnamespace App\\Models;\nclass User {}\n",
+        )
+        .unwrap();
+        // Helper class next to a compliant one: skipped silently.
+        fs::write(
+            src_dir.join("Foo.php"),
+            "<?php\nnamespace App;\nclass Foo {}\nclass FooHelper {}\n",
+        )
+        .unwrap();
+
+        let src = src_dir.to_string_lossy().to_string();
+        let autoload = AutoloadMappings {
+            psr4: vec![NamespaceMapping {
+                namespace: "App\\".to_string(),
+                path: src.clone(),
+                dev: false,
+            }],
+            ..Default::default()
+        };
+        let mut config = ClassmapConfig::new(
+            tmp.path().to_string_lossy(),
+            tmp.path().join("vendor").to_string_lossy(),
+            autoload,
+        );
+        config.write_files = false;
+
+        let output = dump(config.clone()).unwrap();
+        assert_eq!(output.classmap_count, 1);
+        assert_eq!(
+            output.psr_violations,
+            vec![PsrViolation {
+                class: "App\\Models\\User".to_string(),
+                file: format!("{src}/Models/Usr.php"),
+                standard: "psr-4",
+                namespace: "App\\".to_string(),
+                base_path: src.clone(),
+                expected: Some(format!("{src}/Models/User.php")),
+            }]
+        );
+
+        config.strict_psr = true;
+        let err = dump(config).unwrap_err();
+        assert_eq!(err.code, ErrorCode::PsrViolation);
+        assert_eq!(
+            err.path,
+            Some(PathBuf::from(format!("{src}/Models/Usr.php")))
+        );
+    }
+
+    #[test]
+    fn psr0_violation_expects_underscored_path() {
+        let psr0 = vec![("Twig_".to_string(), "/vendor/twig/lib".to_string())];
+        let violation = psr_violation(
+            "Twig_Extension_Core",
+            "/vendor/twig/lib/Twig/Core.php",
+            &[],
+            &psr0,
+            &[],
+        )
+        .unwrap();
+        assert_eq!(violation.standard, "psr-0");
+        assert_eq!(
+            violation.expected.as_deref(),
+            Some("/vendor/twig/lib/Twig/Extension/Core.php")
+        );
+    }
+
+    #[test]
+    fn dry_run_keeps_the_apcu_prefix_on_disk() {
+        let tmp = TempDir::new().unwrap();
+        let target_dir = tmp.path().join("vendor/composer");
+        fs::create_dir_all(&target_dir).unwrap();
+        fs::write(target_dir.join("ClassLoader.php"), "<?php").unwrap();
+
+        let mut config = ClassmapConfig::new(
+            tmp.path().to_string_lossy(),
+            tmp.path().join("vendor").to_string_lossy(),
+            AutoloadMappings::default(),
+        );
+        config.target_dir = Some(target_dir.to_string_lossy().into_owned());
+        config.suffix = Some("abc".to_string());
+        config.apcu_autoloader = true;
+        run(config.clone()).unwrap();
+        let written = existing_apcu_prefix(&target_dir).expect("prefix written");
+
+        config.dry_run = true;
+        let output = dump(config).unwrap();
+        assert_eq!(output.would_change, Some(Vec::new()));
+        assert_eq!(existing_apcu_prefix(&target_dir), Some(written));
+    }
+
+    #[test]
+    fn random_apcu_prefix_looks_like_composers() {
+        let prefix = random_apcu_prefix();
+        assert_eq!(prefix.len(), 20);
+        assert!(prefix.bytes().all(|b| b.is_ascii_hexdigit()));
+    }
 }
@@ -0,0 +1,326 @@
+//! Platform requirements for `platform_check.php`: the lowest PHP version
+//! and the extensions that the root and the installed non-dev packages
+//! require, collected the way Composer's `getPlatformCheck()` does.
+
+use serde::de::{self, Deserializer};
+use serde::Deserialize;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::str::FromStr;
+
+use super::installed::{links, InstalledJson, RootPackage};
+
+/// Composer's `platform-check` setting.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlatformCheck {
+    /// `true`: check the PHP version and extensions.
+    Full,
+    /// `"php-only"` (Composer's default): check the PHP version only.
+    PhpOnly,
+    /// `false`: generate no check and remove a stale one.
+    Disabled,
+}
+
+impl FromStr for PlatformCheck {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "true" | "full" => Ok(PlatformCheck::Full),
+            "php-only" => Ok(PlatformCheck::PhpOnly),
+            "false" | "none" => Ok(PlatformCheck::Disabled),
+            other => Err(format!(
+                "invalid platform check {other:?}: expected true, false or php-only"
+            )),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for PlatformCheck {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Bool(bool),
+            Str(String),
+        }
+        match Raw::deserialize(deserializer)? {
+            Raw::Bool(true) => Ok(PlatformCheck::Full),
+            Raw::Bool(false) => Ok(PlatformCheck::Disabled),
+            Raw::Str(s) => s.parse().map_err(de::Error::custom),
+        }
+    }
+}
+
+/// The lower bound of a version constraint, e.g. `>= 8.1.0.0` for `^8.1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Bound {
+    pub version: [u64; 4],
+    pub inclusive: bool,
+}
+
+impl Bound {
+    const ZERO: Bound = Bound {
+        version: [0; 4],
+        inclusive: true,
+    };
+
+    fn is_zero(&self) -> bool {
+        *self == Self::ZERO
+    }
+
+    /// Composer's `Bound::compareTo($other, '>')` for lower bounds: at the
+    /// same version an exclusive bound is the higher one.
+    fn cmp_lower(&self, other: &Bound) -> Ordering {
+        self.version
+            .cmp(&other.version)
+            .then(other.inclusive.cmp(&self.inclusive))
+    }
+
+    /// `PHP_VERSION_ID` of the bound's version.
+    pub fn php_version_id(&self) -> u64 {
+        self.version[0] * 10000 + self.version[1] * 100 + self.version[2]
+    }
+
+    /// The version as Composer prints it in the error: three components.
+    pub fn human(&self) -> String {
+        format!(
+            "{}.{}.{}",
+            self.version[0], self.version[1], self.version[2]
+        )
+    }
+
+    pub fn operator(&self) -> &'static str {
+        if self.inclusive {
+            ">="
+        } else {
+            ">"
+        }
+    }
+}
+
+/// What `platform_check.php` has to verify.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct PlatformRequirements {
+    pub php: Option<Bound>,
+    pub php_64bit: bool,
+    /// Extension names, sorted, as `extension_loaded()` expects them.
+    pub extensions: Vec<String>,
+}
+
+/// Collect the requirements of `root` and of the non-dev packages in
+/// `installed`. Requirements named in `ignored` (exact names, or prefixes
+/// ending in `*`) are skipped, as with `--ignore-platform-req`.
+pub(crate) fn collect(
+    mode: PlatformCheck,
+    root: Option<&RootPackage>,
+    installed: &InstalledJson,
+    ignored: &[String],
+) -> PlatformRequirements {
+    let mut packages: Vec<PackageLinks> = installed
+        .packages
+        .iter()
+        .map(|entry| PackageLinks {
+            name: entry.get("name").and_then(|n| n.as_str()).unwrap_or(""),
+            require: links(entry, "require"),
+            provides: links(entry, "replace")
+                .into_keys()
+                .chain(links(entry, "provide").into_keys())
+                .collect(),
+        })
+        .collect();
+    if let Some(root) = root {
+        packages.push(PackageLinks {
+            name: &root.name,
+            require: root.require.clone(),
+            provides: root
+                .replace
+                .keys()
+                .chain(root.provide.keys())
+                .cloned()
+                .collect(),
+        });
+    }
+
+    // Composer skips an extension when a provider's constraint matches the
+    // requirement; providers of extensions almost always use `*` or
+    // `self.version`, so any provider counts here.
+    let provided: HashSet<&str> = packages
+        .iter()
+        .flat_map(|p| p.provides.iter())
+        .filter_map(|target| target.strip_prefix("ext-"))
+        .collect();
+
+    let is_ignored = |name: &str| {
+        ignored
+            .iter()
+            .any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => name.starts_with(prefix),
+                None => name == pattern,
+            })
+    };
+
+    let mut lowest_php = Bound::ZERO;
+    let mut requirements = PlatformRequirements::default();
+    let mut extensions = BTreeSet::new();
+    for package in &packages {
+        // Platform checks are a production safeguard: dev packages are skipped.
+        if installed.dev_package_names.contains(package.name) {
+            continue;
+        }
+        for (target, constraint) in &package.require {
+            if is_ignored(target) {
+                continue;
+            }
+            if target == "php" || target == "php-64bit" {
+                let bound = lower_bound(constraint);
+                if bound.cmp_lower(&lowest_php) == Ordering::Greater {
+                    lowest_php = bound;
+                }
+            }
+            if target == "php-64bit" {
+                requirements.php_64bit = true;
+            }
+            if mode != PlatformCheck::Full {
+                continue;
+            }
+            if let Some(extension) = target.strip_prefix("ext-") {
+                if provided.contains(extension) {
+                    continue;
+                }
+                let extension = if extension == "zend-opcache" {
+                    "zend opcache"
+                } else {
+                    extension
+                };
+                extensions.insert(extension.to_string());
+            }
+        }
+    }
+
+    requirements.php = (!lowest_php.is_zero()).then_some(lowest_php);
+    requirements.extensions = extensions.into_iter().collect();
+    requirements
+}
+
+struct PackageLinks<'a> {
+    name: &'a str,
+    require: BTreeMap<String, String>,
+    provides: Vec<String>,
+}
+
+/// The lower bound of a Composer constraint: the lowest over `||`
+/// alternatives, the highest over `,`/space-separated conjunctions.
+fn lower_bound(constraint: &str) -> Bound {
+    constraint
+        .split("||")
+        .flat_map(|alternative| alternative.split('|'))
+        .map(|alternative| {
+            // `1.0 - 2.0` is a range whose lower bound is its left side.
+            let alternative = alternative.split(" - ").next().unwrap_or("");
+            alternative
+                .split([',', ' '])
+                .filter(|atom| !atom.is_empty())
+                .map(atom_lower_bound)
+                .max_by(Bound::cmp_lower)
+                .unwrap_or(Bound::ZERO)
+        })
+        .min_by(Bound::cmp_lower)
+        .unwrap_or(Bound::ZERO)
+}
+
+fn atom_lower_bound(atom: &str) -> Bound {
+    let atom = atom.split('@').next().unwrap_or(atom);
+    let (operator, version) = match atom.find(|c: char| c.is_ascii_alphanumeric() || c == '*') {
+        Some(i) => atom.split_at(i),
+        None => return Bound::ZERO,
+    };
+    let inclusive = match operator {
+        "" | "=" | "==" | ">=" | "^" | "~" => true,
+        ">" => false,
+        _ => return Bound::ZERO,
+    };
+
+    let version = version.trim_start_matches(['v', 'V']);
+    let version = version.split(['-', '+']).next().unwrap_or(version);
+    let mut parts = [0u64; 4];
+    for (slot, part) in parts.iter_mut().zip(version.split('.')) {
+        match part.parse() {
+            Ok(n) => *slot = n,
+            // `8.1.*`: the remaining components are already zero.
+            Err(_) => break,
+        }
+    }
+    if !version.starts_with(|c: char| c.is_ascii_digit()) {
+        return Bound::ZERO;
+    }
+    Bound {
+        version: parts,
+        inclusive,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn bound(constraint: &str) -> (String, &'static str) {
+        let b = lower_bound(constraint);
+        (b.human(), b.operator())
+    }
+
+    #[test]
+    fn lower_bounds_follow_composer_constraints() {
+        assert_eq!(bound("^8.1"), ("8.1.0".to_string(), ">="));
+        assert_eq!(bound(">=7.4 <8.3"), ("7.4.0".to_string(), ">="));
+        assert_eq!(bound("^7.4 || ^8.0"), ("7.4.0".to_string(), ">="));
+        assert_eq!(bound(">8.0.2"), ("8.0.2".to_string(), ">"));
+        assert_eq!(bound("8.2.*"), ("8.2.0".to_string(), ">="));
+        assert_eq!(bound("7.4 - 8.1"), ("7.4.0".to_string(), ">="));
+        assert!(lower_bound("*").is_zero());
+        assert!(lower_bound("<8").is_zero());
+        assert!(lower_bound("^7.4 || *").is_zero());
+    }
+
+    #[test]
+    fn collects_php_and_extensions_from_non_dev_packages() {
+        let installed = InstalledJson {
+            packages: vec![
+                json!({"name": "a/a", "require": {"php": ">=7.4", "ext-json": "*", "ext-mbstring": "*"}}),
+                json!({"name": "b/b", "require": {"php": "^8.1", "ext-zend-opcache": "*"}}),
+                json!({"name": "dev/tool", "require": {"php": "^8.3", "ext-xdebug": "*"}}),
+                json!({"name": "symfony/polyfill-mbstring", "provide": {"ext-mbstring": "*"}}),
+            ],
+            dev: true,
+            dev_package_names: HashSet::from(["dev/tool".to_string()]),
+        };
+        let root = RootPackage {
+            name: "acme/app".to_string(),
+            require: BTreeMap::from([
+                ("php-64bit".to_string(), ">8.0".to_string()),
+                ("ext-intl".to_string(), "*".to_string()),
+            ]),
+            ..RootPackage::default()
+        };
+        let ignored = vec!["ext-intl".to_string()];
+
+        let full = collect(PlatformCheck::Full, Some(&root), &installed, &ignored);
+        assert_eq!(full.php.unwrap().php_version_id(), 80100);
+        assert!(full.php_64bit);
+        assert_eq!(full.extensions, ["json", "zend opcache"]);
+
+        let php_only = collect(PlatformCheck::PhpOnly, Some(&root), &installed, &ignored);
+        assert_eq!(php_only.php, full.php);
+        assert!(php_only.extensions.is_empty());
+    }
+
+    #[test]
+    fn platform_check_setting_accepts_composer_values() {
+        let parse = |v: serde_json::Value| serde_json::from_value::<PlatformCheck>(v).unwrap();
+        assert_eq!(parse(json!(true)), PlatformCheck::Full);
+        assert_eq!(parse(json!(false)), PlatformCheck::Disabled);
+        assert_eq!(parse(json!("php-only")), PlatformCheck::PhpOnly);
+        assert!(serde_json::from_value::<PlatformCheck>(json!("maybe")).is_err());
+    }
+}
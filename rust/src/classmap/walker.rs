@@ -6,6 +6,10 @@ use std::fs;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
 
+use crate::error::Error;
+use crate::limits::Limiter;
+use crate::trace::Trace;
+
 use super::cache::{dirs_unchanged, get_mtime, CacheData, CachedFile, CACHE_VERSION};
 use super::parser::{contains_class_keyword, extract_php_symbols};
 
@@ -26,20 +30,37 @@ enum WalkEntry {
     Dir(PathBuf, u64), // path, mtime
 }
 
+/// `path` as a string. The generated PHP and the cache hold paths as UTF-8
+/// strings, so a PHP file whose path is not valid UTF-8 is rejected rather
+/// than mapped to a lossily converted path that does not exist.
+pub(crate) fn utf8_path(path: &Path) -> Result<&str, Error> {
+    path.to_str().ok_or_else(|| {
+        Error::invalid_request(format!(
+            "{}: classmap paths must be valid UTF-8",
+            path.to_string_lossy()
+        ))
+        .with_path(path)
+    })
+}
+
 pub(crate) fn walk_and_parse(
     dirs: &[&str],
     excludes: &[Regex],
     cache: &CacheData,
     vendor_dir: &str,
-) -> WalkResult {
+    limiter: &Limiter,
+    trace: &Trace,
+) -> Result<WalkResult, Error> {
     // Fast path: if all directory mtimes match cache, skip the walk entirely
     // and use cached file paths directly. This avoids readdir + stat on
     // thousands of non-PHP files in vendor/.
     if dirs_unchanged(cache, dirs) {
-        return walk_and_parse_cached(dirs, excludes, cache, vendor_dir);
+        return Ok(walk_and_parse_cached(
+            dirs, excludes, cache, vendor_dir, limiter, trace,
+        ));
     }
 
-    walk_and_parse_full(dirs, excludes, cache)
+    walk_and_parse_full(dirs, excludes, cache, limiter, trace)
 }
 
 /// Fast path: skip directory walk, trust cache for vendor files.
@@ -56,7 +77,10 @@ fn walk_and_parse_cached(
     excludes: &[Regex],
     cache: &CacheData,
     vendor_dir: &str,
+    limiter: &Limiter,
+    trace: &Trace,
 ) -> WalkResult {
+    let partition_span = trace.span("walk", "partition_cached_files");
     // Partition cached files into vendor (trust cache) and non-vendor (need stat)
     let mut vendor_entries: Vec<(String, String)> = Vec::new();
     let mut vendor_files: HashMap<String, CachedFile> = HashMap::new();
@@ -110,14 +134,21 @@ fn walk_and_parse_cached(
         }
     }
 
+    drop(partition_span);
+
     // Stat + parse non-vendor files in parallel (typically a small set)
+    let _parse_span = trace.span("parse", "parse_files");
     let files_scanned = AtomicUsize::new(0);
     let cache_hit_count = AtomicUsize::new(0);
 
-    let non_vendor_results: Vec<ParseResult> = non_vendor_paths
-        .par_iter()
-        .map(|path| parse_one_file(path, &cache.files, &files_scanned, &cache_hit_count))
-        .collect();
+    let non_vendor_results = parse_files(
+        &non_vendor_paths,
+        &cache.files,
+        &files_scanned,
+        &cache_hit_count,
+        limiter,
+        trace,
+    );
 
     let mut all_entries = vendor_entries;
     let mut new_files = vendor_files;
@@ -147,7 +178,14 @@ fn walk_and_parse_cached(
 }
 
 /// Full path: walk all directories, parse PHP files, collect dir mtimes.
-fn walk_and_parse_full(dirs: &[&str], excludes: &[Regex], cache: &CacheData) -> WalkResult {
+fn walk_and_parse_full(
+    dirs: &[&str],
+    excludes: &[Regex],
+    cache: &CacheData,
+    limiter: &Limiter,
+    trace: &Trace,
+) -> Result<WalkResult, Error> {
+    let walk_span = trace.span("walk", "walk_directories");
     let mut paths: Vec<PathBuf> = Vec::new();
     let mut walk_dirs: Vec<&str> = Vec::new();
 
@@ -170,7 +208,10 @@ fn walk_and_parse_full(dirs: &[&str], excludes: &[Regex], cache: &CacheData) ->
 
     if !walk_dirs.is_empty() {
         let mut builder = WalkBuilder::new(walk_dirs[0]);
-        builder.hidden(false).git_ignore(false).threads(num_cpus());
+        builder
+            .hidden(false)
+            .git_ignore(false)
+            .threads(limiter.threads().unwrap_or_else(num_cpus));
 
         for dir in &walk_dirs[1..] {
             builder.add(dir);
@@ -223,7 +264,10 @@ fn walk_and_parse_full(dirs: &[&str], excludes: &[Regex], cache: &CacheData) ->
         drop(tx);
         for entry in rx {
             match entry {
-                WalkEntry::File(p) => paths.push(p),
+                WalkEntry::File(p) => {
+                    utf8_path(&p)?;
+                    paths.push(p);
+                }
                 WalkEntry::Dir(p, mtime) => {
                     dir_mtimes.insert(p.to_string_lossy().into_owned(), mtime);
                 }
@@ -231,14 +275,21 @@ fn walk_and_parse_full(dirs: &[&str], excludes: &[Regex], cache: &CacheData) ->
         }
     }
 
+    drop(walk_span);
+
+    let _parse_span = trace.span("parse", "parse_files");
     let php_files_found = paths.len();
     let files_scanned = AtomicUsize::new(0);
     let cache_hit_count = AtomicUsize::new(0);
 
-    let results: Vec<ParseResult> = paths
-        .par_iter()
-        .map(|path| parse_one_file(path, &cache.files, &files_scanned, &cache_hit_count))
-        .collect();
+    let results = parse_files(
+        &paths,
+        &cache.files,
+        &files_scanned,
+        &cache_hit_count,
+        limiter,
+        trace,
+    );
 
     let mut entries: Vec<(String, String)> = Vec::new();
     let mut new_files: HashMap<String, CachedFile> = HashMap::with_capacity(results.len());
@@ -248,7 +299,7 @@ fn walk_and_parse_full(dirs: &[&str], excludes: &[Regex], cache: &CacheData) ->
         new_files.insert(path_str, cache_entry);
     }
 
-    WalkResult {
+    Ok(WalkResult {
         entries,
         files_scanned: files_scanned.load(Ordering::Relaxed),
         php_files_found,
@@ -260,7 +311,37 @@ fn walk_and_parse_full(dirs: &[&str], excludes: &[Regex], cache: &CacheData) ->
             dir_mtimes,
         },
         walk_skipped: false,
-    }
+    })
+}
+
+/// Parse `paths` in parallel, in chunks that fit the memory budget.
+fn parse_files(
+    paths: &[PathBuf],
+    file_cache: &HashMap<String, CachedFile>,
+    files_scanned: &AtomicUsize,
+    cache_hit_count: &AtomicUsize,
+    limiter: &Limiter,
+    trace: &Trace,
+) -> Vec<ParseResult> {
+    let chunks = limiter.budget_chunks(paths, |p| fs::metadata(p).map(|m| m.len()).unwrap_or(0));
+    chunks
+        .into_iter()
+        .flat_map(|chunk| {
+            chunk
+                .par_iter()
+                .map(|path| {
+                    parse_one_file(
+                        path,
+                        file_cache,
+                        files_scanned,
+                        cache_hit_count,
+                        limiter,
+                        trace,
+                    )
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect()
 }
 
 /// Parse a single PHP file, using cache if mtime matches.
@@ -269,6 +350,8 @@ fn parse_one_file(
     file_cache: &HashMap<String, CachedFile>,
     files_scanned: &AtomicUsize,
     cache_hit_count: &AtomicUsize,
+    limiter: &Limiter,
+    trace: &Trace,
 ) -> ParseResult {
     let path_str = path.to_string_lossy().into_owned();
     let mtime = get_mtime(path);
@@ -288,24 +371,12 @@ fn parse_one_file(
         }
     }
 
-    let contents = match fs::read(path) {
-        Ok(c) => c,
-        Err(_) => return None,
+    let symbols = {
+        let _span = trace
+            .span("parse", "parse_file")
+            .arg("path", path_str.as_str());
+        read_symbols(path, limiter)?
     };
-
-    if !contains_class_keyword(&contents) {
-        return Some((
-            vec![],
-            path_str,
-            CachedFile {
-                mtime,
-                symbols: vec![],
-            },
-        ));
-    }
-
-    let text = String::from_utf8_lossy(&contents);
-    let symbols = extract_php_symbols(&text);
     let cache_entry = CachedFile {
         mtime,
         symbols: symbols.clone(),
@@ -320,6 +391,16 @@ fn parse_one_file(
     Some((entries, path_str, cache_entry))
 }
 
+/// Read and parse one PHP file, skipping the parser entirely when the file
+/// cannot declare a class. `None` if the file cannot be read.
+pub(crate) fn read_symbols(path: &Path, limiter: &Limiter) -> Option<Vec<String>> {
+    let contents = limiter.io(|| fs::read(path)).ok()?;
+    if !contains_class_keyword(&contents) {
+        return Some(vec![]);
+    }
+    Some(extract_php_symbols(&String::from_utf8_lossy(&contents)))
+}
+
 fn num_cpus() -> usize {
     std::thread::available_parallelism()
         .map(|n| n.get().max(2) - 1)
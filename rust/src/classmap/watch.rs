@@ -0,0 +1,481 @@
+//! Watch mode: keep the classmap up to date as PHP files change.
+//!
+//! After an initial [`run`](super::run), every psr-4/psr-0/classmap root is
+//! watched with the platform notifier (inotify on Linux). Changed files are
+//! re-parsed individually, only the classmap entries they contribute are
+//! touched, and `autoload_classmap.php` / `autoload_static.php` are rewritten.
+//! The other autoload files only depend on the mappings, which cannot change
+//! while watching.
+
+use notify::{EventKind, RecursiveMode, Watcher};
+use regex::Regex;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+use crate::error::{Error, ErrorCode};
+
+use super::cache::{get_mtime, load_cache, save_cache, CacheData, CachedFile};
+use super::codegen::{generate_classmap_file, generate_static_file};
+use super::installed::{installed_versions_path, INSTALLED_VERSIONS_CLASS};
+use super::walker::{read_symbols, utf8_path};
+use super::{cache_path, ClassmapConfig, ResolvedRoots};
+
+/// Events arriving within this window of each other are applied together,
+/// so an editor's write-rename-chmod sequence triggers a single rewrite.
+const DEBOUNCE: Duration = Duration::from_millis(100);
+
+#[derive(Debug, Serialize)]
+struct Update {
+    event: &'static str,
+    changed_files: Vec<String>,
+    classes_added: Vec<String>,
+    classes_removed: Vec<String>,
+    classmap_count: usize,
+    elapsed_ms: u128,
+}
+
+/// Run the classmap once, then watch the autoload roots and keep the
+/// generated files current. `on_event` receives the initial classmap output
+/// (tagged `"event": "initial"`) and then one `"event": "update"` object per
+/// batch of changes that altered the classmap. Only returns on error.
+pub fn watch(
+    config: ClassmapConfig,
+    mut on_event: impl FnMut(serde_json::Value),
+) -> Result<(), Error> {
+    let Some(cache_path) = cache_path(&config) else {
+        return Err(Error::invalid_request(
+            "watch requires a target_dir to write autoload files to",
+        ));
+    };
+    let config = ClassmapConfig {
+        write_files: true,
+        staging_suffix: None,
+        dry_run: false,
+        ..config
+    };
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx).map_err(notify_error)?;
+    // Watches go up before the initial run so nothing changed during it is missed.
+    for root in config.scanned_roots().dirs() {
+        let path = Path::new(root);
+        let (path, mode) = if path.is_dir() {
+            (path, RecursiveMode::Recursive)
+        } else if let Some(parent) = path.parent().filter(|_| path.exists()) {
+            // Single-file classmap entries: editors replace files by rename,
+            // which a watch on the file itself would not survive.
+            (parent, RecursiveMode::NonRecursive)
+        } else {
+            continue;
+        };
+        watcher
+            .watch(path, mode)
+            .map_err(|e| notify_error(e).with_path(path))?;
+    }
+
+    let mut initial = super::run(config.clone())?;
+    initial["event"] = "initial".into();
+    on_event(initial);
+
+    let mut state = WatchState::new(config, load_cache(&cache_path));
+
+    while let Ok(event) = rx.recv() {
+        let mut changed: BTreeSet<PathBuf> = BTreeSet::new();
+        collect_paths(event, &mut changed);
+        while let Ok(event) = rx.recv_timeout(DEBOUNCE) {
+            collect_paths(event, &mut changed);
+        }
+        if changed.is_empty() {
+            continue;
+        }
+
+        let paths: Vec<PathBuf> = changed.into_iter().collect();
+        if let Some(update) = state.apply(&paths)? {
+            save_cache(&cache_path, &state.cache);
+            on_event(serde_json::to_value(update).unwrap());
+        }
+    }
+
+    Err(Error::new(
+        ErrorCode::Io,
+        "file watcher stopped unexpectedly",
+    ))
+}
+
+fn notify_error(error: notify::Error) -> Error {
+    match error.kind {
+        notify::ErrorKind::Io(e) => Error::io(e, error.paths.first().cloned().unwrap_or_default()),
+        _ => Error::new(ErrorCode::Io, error.to_string()),
+    }
+}
+
+fn collect_paths(event: notify::Result<notify::Event>, out: &mut BTreeSet<PathBuf>) {
+    let Ok(event) = event else {
+        return;
+    };
+    if matches!(event.kind, EventKind::Access(_)) {
+        return;
+    }
+    out.extend(event.paths);
+}
+
+/// Everything needed to patch the classmap one file at a time.
+struct WatchState {
+    config: ClassmapConfig,
+    roots: ResolvedRoots,
+    excludes: Vec<Regex>,
+    vendor_str: String,
+    base_str: String,
+    target_str: String,
+    /// Parsed symbols per file, persisted back to `.turbo-cache`.
+    cache: CacheData,
+    /// Every file that validly declares each class, for when the chosen
+    /// one disappears.
+    providers: HashMap<String, BTreeSet<String>>,
+    classmap: BTreeMap<String, String>,
+}
+
+impl WatchState {
+    fn new(mut config: ClassmapConfig, cache: CacheData) -> Self {
+        config.resolve_dev_mode();
+        let roots = config.scanned_roots();
+        let excludes = config
+            .exclude_from_classmap
+            .iter()
+            .filter_map(|p| Regex::new(p).ok())
+            .collect();
+        let canonical = |p: &str| {
+            fs::canonicalize(p)
+                .unwrap_or_else(|_| PathBuf::from(p))
+                .to_string_lossy()
+                .into_owned()
+        };
+        let vendor_str = canonical(&config.vendor_dir);
+        let base_str = canonical(&config.project_dir);
+        let target_str = config
+            .target_dir
+            .as_deref()
+            .map(canonical)
+            .unwrap_or_default();
+
+        let mut classmap = BTreeMap::new();
+        if config.root_package.is_some() {
+            classmap.insert(
+                INSTALLED_VERSIONS_CLASS.to_string(),
+                installed_versions_path(&vendor_str),
+            );
+        }
+        let mut state = Self {
+            config,
+            roots,
+            excludes,
+            vendor_str,
+            base_str,
+            target_str,
+            cache: CacheData::default(),
+            providers: HashMap::new(),
+            classmap,
+        };
+
+        let mut files: Vec<(String, CachedFile)> = cache.files.into_iter().collect();
+        files.sort_by(|a, b| a.0.cmp(&b.0));
+        for (path, cached) in files {
+            if state.is_tracked(Path::new(&path)) {
+                state.add_file(path, cached);
+            }
+        }
+        state.cache.version = cache.version;
+        state.cache.dir_mtimes = cache.dir_mtimes;
+        state
+    }
+
+    /// Whether `path` is a PHP file the walker would have picked up.
+    fn is_tracked(&self, path: &Path) -> bool {
+        if path.extension().is_none_or(|e| e != "php") {
+            return false;
+        }
+        let path_str = path.to_string_lossy();
+        let under_root = self.roots.dirs().iter().any(|root| {
+            let root = Path::new(root);
+            path == root || path.starts_with(root)
+        });
+        under_root && !self.excludes.iter().any(|re| re.is_match(&path_str))
+    }
+
+    fn add_file(&mut self, path: String, cached: CachedFile) {
+        for class in &cached.symbols {
+            if !self.roots.is_class_valid(class, &path) {
+                continue;
+            }
+            let providers = self.providers.entry(class.clone()).or_default();
+            providers.insert(path.clone());
+            let chosen = preferred(&self.roots, providers).to_string();
+            self.classmap.insert(class.clone(), chosen);
+        }
+        self.cache.files.insert(path, cached);
+    }
+
+    fn remove_file(&mut self, path: &str) {
+        let Some(cached) = self.cache.files.remove(path) else {
+            return;
+        };
+        for class in cached.symbols {
+            let Some(files) = self.providers.get_mut(&class) else {
+                continue;
+            };
+            files.remove(path);
+            if files.is_empty() {
+                self.providers.remove(&class);
+                self.classmap.remove(&class);
+            } else {
+                let chosen = preferred(&self.roots, files).to_string();
+                self.classmap.insert(class, chosen);
+            }
+        }
+    }
+
+    /// Re-parse the files behind `paths` (files or directories that were
+    /// created, modified, removed or renamed) and rewrite the classmap files
+    /// if any entry changed.
+    fn apply(&mut self, paths: &[PathBuf]) -> Result<Option<Update>, Error> {
+        let start = Instant::now();
+        let before = self.classmap.clone();
+
+        let mut touched: BTreeSet<String> = BTreeSet::new();
+        for path in paths {
+            if path.is_dir() {
+                for file in php_files_under(path) {
+                    if self.is_tracked(&file) {
+                        utf8_path(&file)?;
+                    }
+                    touched.insert(file.to_string_lossy().into_owned());
+                }
+            } else if path.exists() {
+                if self.is_tracked(path) {
+                    utf8_path(path)?;
+                }
+                touched.insert(path.to_string_lossy().into_owned());
+            } else {
+                // Removed or renamed away: drop the file, or everything that
+                // used to live under a removed directory.
+                let gone = path.to_string_lossy();
+                let prefix = format!("{gone}/");
+                touched.extend(
+                    self.cache
+                        .files
+                        .keys()
+                        .filter(|f| f.as_str() == gone || f.starts_with(&prefix))
+                        .cloned(),
+                );
+            }
+        }
+
+        let mut changed_files = Vec::new();
+        for file in touched {
+            let path = Path::new(&file);
+            let parsed = if path.is_file() && self.is_tracked(path) {
+                read_symbols(path, &self.config.limits).map(|symbols| CachedFile {
+                    mtime: get_mtime(path),
+                    symbols,
+                })
+            } else {
+                None
+            };
+            if parsed.is_none() && !self.cache.files.contains_key(&file) {
+                continue;
+            }
+            self.remove_file(&file);
+            if let Some(cached) = parsed {
+                self.add_file(file.clone(), cached);
+            }
+            changed_files.push(file);
+        }
+
+        if self.classmap == before {
+            return Ok(None);
+        }
+
+        self.write()?;
+
+        let classes_added = self
+            .classmap
+            .iter()
+            .filter(|(class, path)| before.get(*class) != Some(path))
+            .map(|(class, _)| class.clone())
+            .collect();
+        let classes_removed = before
+            .keys()
+            .filter(|class| !self.classmap.contains_key(*class))
+            .cloned()
+            .collect();
+
+        Ok(Some(Update {
+            event: "update",
+            changed_files,
+            classes_added,
+            classes_removed,
+            classmap_count: self.classmap.len(),
+            elapsed_ms: start.elapsed().as_millis(),
+        }))
+    }
+
+    /// Rewrite the two classmap-bearing files. Each is written next to its
+    /// destination and renamed into place so a concurrent request never
+    /// includes a half-written file.
+    fn write(&self) -> Result<(), Error> {
+        let Some(ref target_dir) = self.config.target_dir else {
+            return Ok(());
+        };
+        let td_path = Path::new(target_dir);
+
+        let classmap_content =
+            generate_classmap_file(&self.classmap, &self.vendor_str, &self.base_str);
+        replace_file(&td_path.join("autoload_classmap.php"), &classmap_content)?;
+
+        if let Some(ref sfx) = self.config.suffix {
+            let static_content = generate_static_file(
+                sfx,
+                &self.config.autoload.psr4,
+                &self.config.autoload.psr0,
+                &self.classmap,
+                &self.config.autoload.files,
+                &self.vendor_str,
+                &self.base_str,
+                &self.target_str,
+            );
+            replace_file(&td_path.join("autoload_static.php"), &static_content)?;
+        }
+        Ok(())
+    }
+}
+
+fn replace_file(path: &Path, content: &str) -> Result<(), Error> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".turbo-watch");
+    let tmp = PathBuf::from(tmp);
+    fs::write(&tmp, content)
+        .and_then(|_| fs::rename(&tmp, path))
+        .map_err(|e| Error::io(e, path))
+}
+
+/// The file a class is mapped to when several declare it, as in a full run.
+fn preferred<'a>(roots: &ResolvedRoots, providers: &'a BTreeSet<String>) -> &'a str {
+    providers
+        .iter()
+        .min_by_key(|p| (roots.scan_rank(p), p.as_str()))
+        .map(String::as_str)
+        .unwrap_or_default()
+}
+
+fn php_files_under(dir: &Path) -> Vec<PathBuf> {
+    ignore::WalkBuilder::new(dir)
+        .hidden(false)
+        .git_ignore(false)
+        .build()
+        .filter_map(Result::ok)
+        .filter(|e| e.file_type().is_some_and(|ft| ft.is_file()))
+        .map(|e| e.into_path())
+        .filter(|p| p.extension().is_some_and(|e| e == "php"))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::classmap::{AutoloadMappings, NamespaceMapping};
+    use tempfile::TempDir;
+
+    fn setup() -> (TempDir, WatchState) {
+        let tmp = TempDir::new().unwrap();
+        let base = tmp.path().canonicalize().unwrap();
+        let src = base.join("src");
+        let target = base.join("vendor/composer");
+        fs::create_dir_all(&src).unwrap();
+        fs::create_dir_all(&target).unwrap();
+        fs::write(src.join("Foo.php"), "<?php\nnamespace App;\nclass Foo {}\n").unwrap();
+        // Left by an earlier Composer dump; there is no runtime_dir to copy from.
+        fs::write(target.join("ClassLoader.php"), "<?php\n").unwrap();
+
+        let mut config = ClassmapConfig::new(
+            base.to_string_lossy(),
+            base.join("vendor").to_string_lossy(),
+            AutoloadMappings {
+                psr4: vec![NamespaceMapping {
+                    namespace: "App\\".to_string(),
+                    path: src.to_string_lossy().into_owned(),
+                    dev: false,
+                }],
+                ..Default::default()
+            },
+        );
+        config.target_dir = Some(target.to_string_lossy().into_owned());
+        config.suffix = Some("abc".to_string());
+        super::super::run(config.clone()).unwrap();
+        let cache = load_cache(&target.join(".turbo-cache"));
+        (tmp, WatchState::new(config, cache))
+    }
+
+    fn read_target(state: &WatchState, name: &str) -> String {
+        fs::read_to_string(Path::new(state.config.target_dir.as_ref().unwrap()).join(name)).unwrap()
+    }
+
+    #[test]
+    fn seeds_classmap_from_cache() {
+        let (_tmp, state) = setup();
+        assert_eq!(state.classmap.keys().collect::<Vec<_>>(), vec!["App\\Foo"]);
+    }
+
+    #[test]
+    fn new_file_adds_entry_and_rewrites_files() {
+        let (tmp, mut state) = setup();
+        let bar = tmp.path().canonicalize().unwrap().join("src/Bar.php");
+        fs::write(&bar, "<?php\nnamespace App;\nclass Bar {}\n").unwrap();
+
+        let update = state.apply(&[bar]).unwrap().expect("classmap changed");
+        assert_eq!(update.classes_added, vec!["App\\Bar"]);
+        assert!(update.classes_removed.is_empty());
+        assert_eq!(update.classmap_count, 2);
+        assert!(read_target(&state, "autoload_classmap.php").contains("App\\\\Bar"));
+        assert!(read_target(&state, "autoload_static.php").contains("App\\\\Bar"));
+    }
+
+    #[test]
+    fn removed_directory_drops_its_classes() {
+        let (tmp, mut state) = setup();
+        let src = tmp.path().canonicalize().unwrap().join("src");
+        fs::create_dir_all(src.join("Sub")).unwrap();
+        fs::write(
+            src.join("Sub/Baz.php"),
+            "<?php\nnamespace App\\Sub;\nclass Baz {}\n",
+        )
+        .unwrap();
+        state.apply(&[src.join("Sub")]).unwrap();
+        assert!(state.classmap.contains_key("App\\Sub\\Baz"));
+
+        fs::remove_dir_all(src.join("Sub")).unwrap();
+        let update = state.apply(&[src.join("Sub")]).unwrap().unwrap();
+        assert_eq!(update.classes_removed, vec!["App\\Sub\\Baz"]);
+        assert!(!read_target(&state, "autoload_classmap.php").contains("Baz"));
+    }
+
+    #[test]
+    fn edit_without_class_change_writes_nothing() {
+        let (tmp, mut state) = setup();
+        let foo = tmp.path().canonicalize().unwrap().join("src/Foo.php");
+        fs::write(&foo, "<?php\nnamespace App;\nclass Foo { public $x; }\n").unwrap();
+        assert!(state.apply(&[foo]).unwrap().is_none());
+    }
+
+    #[test]
+    fn non_compliant_class_is_not_added() {
+        let (tmp, mut state) = setup();
+        let wrong = tmp.path().canonicalize().unwrap().join("src/Wrong.php");
+        fs::write(&wrong, "<?php\nnamespace App;\nclass Right {}\n").unwrap();
+        assert!(state.apply(&[wrong]).unwrap().is_none());
+        assert!(!state.classmap.contains_key("App\\Right"));
+    }
+}
@@ -1,29 +1,70 @@
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
 
-#[derive(Debug, Deserialize)]
+use crate::error::ErrorCode;
+use crate::limits::Limiter;
+use crate::transaction::Transaction;
+
+#[derive(Debug, Clone, Deserialize)]
 pub struct CleanTarget {
-    pub path: String,
+    #[serde(with = "crate::encoding::path")]
+    pub path: PathBuf,
     pub name: String,
 }
 
-#[derive(Debug, Serialize)]
-struct CleanResult {
-    cleaned: usize,
-    failed: Vec<FailedClean>,
-    elapsed_ms: u128,
+#[derive(Debug, Clone, Serialize)]
+pub struct CleanResult {
+    /// Targets removed, including ones that did not exist.
+    pub cleaned: usize,
+    pub failed: Vec<FailedClean>,
+    pub elapsed_ms: u128,
 }
 
-#[derive(Debug, Serialize)]
-struct FailedClean {
-    name: String,
-    error: String,
+#[derive(Debug, Clone, Serialize)]
+pub struct FailedClean {
+    pub name: String,
+    #[serde(with = "crate::encoding::path")]
+    pub path: PathBuf,
+    /// The failure class, as in the `error` response envelope.
+    pub code: ErrorCode,
+    pub error: String,
+}
+
+impl FailedClean {
+    fn new(target: &CleanTarget, error: &std::io::Error) -> Self {
+        FailedClean {
+            name: target.name.clone(),
+            path: target.path.clone(),
+            code: ErrorCode::from_io(error),
+            error: error.to_string(),
+        }
+    }
 }
 
 pub fn run(targets: Vec<CleanTarget>) -> serde_json::Value {
+    run_in(targets, None, &Limiter::default())
+}
+
+/// Like [`run`], but inside a transaction targets are moved into its trash
+/// rather than deleted, so they can be restored on rollback. Removals run
+/// under `limiter`'s I/O limit.
+pub fn run_in(
+    targets: Vec<CleanTarget>,
+    transaction: Option<&Transaction>,
+    limiter: &Limiter,
+) -> serde_json::Value {
+    serde_json::to_value(remove(&targets, transaction, limiter)).unwrap()
+}
+
+/// Remove every target directory in parallel. The typed form of [`run_in`].
+pub fn remove(
+    targets: &[CleanTarget],
+    transaction: Option<&Transaction>,
+    limiter: &Limiter,
+) -> CleanResult {
     let start = std::time::Instant::now();
     let cleaned = AtomicUsize::new(0);
 
@@ -36,26 +77,109 @@ pub fn run(targets: Vec<CleanTarget>) -> serde_json::Value {
                 return None;
             }
 
-            match fs::remove_dir_all(path) {
+            let removed = limiter.io(|| match transaction {
+                Some(tx) => tx.remove(path),
+                None => fs::remove_dir_all(path),
+            });
+            match removed {
                 Ok(()) => {
                     cleaned.fetch_add(1, Ordering::Relaxed);
                     None
                 }
-                Err(e) => Some(FailedClean {
-                    name: target.name.clone(),
-                    error: e.to_string(),
-                }),
+                Err(e) => Some(FailedClean::new(target, &e)),
             }
         })
         .collect();
 
-    let result = CleanResult {
+    CleanResult {
         cleaned: cleaned.load(Ordering::Relaxed),
         failed,
         elapsed_ms: start.elapsed().as_millis(),
-    };
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CleanPlan {
+    /// Always `true`; marks the response as a dry run.
+    pub dry_run: bool,
+    pub would_remove: Vec<PlannedRemoval>,
+    pub total_bytes: u64,
+    pub failed: Vec<FailedClean>,
+    pub elapsed_ms: u128,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlannedRemoval {
+    pub name: String,
+    #[serde(with = "crate::encoding::path")]
+    pub path: PathBuf,
+    pub files: usize,
+    pub bytes: u64,
+}
 
-    serde_json::to_value(result).unwrap()
+/// Report what [`run`] would remove, with file and byte counts, without
+/// touching anything. Targets that do not exist are left out.
+pub fn dry_run(targets: Vec<CleanTarget>) -> serde_json::Value {
+    serde_json::to_value(plan(&targets)).unwrap()
+}
+
+/// The typed form of [`dry_run`].
+pub fn plan(targets: &[CleanTarget]) -> CleanPlan {
+    let start = std::time::Instant::now();
+
+    let results: Vec<Result<Option<PlannedRemoval>, FailedClean>> = targets
+        .par_iter()
+        .map(|target| {
+            let path = Path::new(&target.path);
+            if fs::symlink_metadata(path).is_err() {
+                return Ok(None);
+            }
+            match disk_usage(path) {
+                Ok((files, bytes)) => Ok(Some(PlannedRemoval {
+                    name: target.name.clone(),
+                    path: target.path.clone(),
+                    files,
+                    bytes,
+                })),
+                Err(e) => Err(FailedClean::new(target, &e)),
+            }
+        })
+        .collect();
+
+    let mut would_remove = Vec::new();
+    let mut failed = Vec::new();
+    for result in results {
+        match result {
+            Ok(Some(removal)) => would_remove.push(removal),
+            Ok(None) => {}
+            Err(failure) => failed.push(failure),
+        }
+    }
+
+    CleanPlan {
+        dry_run: true,
+        total_bytes: would_remove.iter().map(|r| r.bytes).sum(),
+        would_remove,
+        failed,
+        elapsed_ms: start.elapsed().as_millis(),
+    }
+}
+
+/// Number of files and their total size under `path`. Symlinks are counted
+/// as files of their own size and never followed, as `remove_dir_all` does.
+fn disk_usage(path: &Path) -> std::io::Result<(usize, u64)> {
+    let meta = fs::symlink_metadata(path)?;
+    if !meta.is_dir() {
+        return Ok((1, meta.len()));
+    }
+    let mut files = 0;
+    let mut bytes = 0;
+    for entry in fs::read_dir(path)? {
+        let (f, b) = disk_usage(&entry?.path())?;
+        files += f;
+        bytes += b;
+    }
+    Ok((files, bytes))
 }
 
 #[cfg(test)]
@@ -64,6 +188,38 @@ mod tests {
     use std::io::Write;
     use tempfile::TempDir;
 
+    #[test]
+    fn remove_reports_typed_failures() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().join("pkg");
+        fs::create_dir_all(&dir).unwrap();
+        // A regular file where a package directory is expected cannot be
+        // removed with remove_dir_all, even by root.
+        let file = tmp.path().join("not-a-dir");
+        fs::write(&file, "x").unwrap();
+
+        let targets = [
+            CleanTarget {
+                path: dir.clone(),
+                name: "acme/pkg".to_string(),
+            },
+            CleanTarget {
+                path: file.clone(),
+                name: "acme/broken".to_string(),
+            },
+        ];
+        let result = remove(&targets, None, &Limiter::default());
+        assert_eq!(result.cleaned, 1);
+        assert!(!dir.exists());
+
+        assert_eq!(result.failed.len(), 1);
+        let failure = &result.failed[0];
+        assert_eq!(failure.name, "acme/broken");
+        assert_eq!(failure.path, file);
+        assert_eq!(failure.code, ErrorCode::Io);
+        assert!(file.exists());
+    }
+
     #[test]
     fn clean_single_directory() {
         let tmp = TempDir::new().unwrap();
@@ -73,7 +229,7 @@ mod tests {
         writeln!(f, "<?php class Foo {{}}").unwrap();
 
         let targets = vec![CleanTarget {
-            path: dir.to_string_lossy().to_string(),
+            path: dir.clone(),
             name: "vendor/pkg1".to_string(),
         }];
 
@@ -99,7 +255,7 @@ mod tests {
             .iter()
             .enumerate()
             .map(|(i, d)| CleanTarget {
-                path: d.to_string_lossy().to_string(),
+                path: d.clone(),
                 name: format!("vendor/pkg{i}"),
             })
             .collect();
@@ -115,7 +271,7 @@ mod tests {
     #[test]
     fn clean_nonexistent_directory_counts_as_success() {
         let targets = vec![CleanTarget {
-            path: "/nonexistent/path/that/does/not/exist".to_string(),
+            path: "/nonexistent/path/that/does/not/exist".into(),
             name: "missing/pkg".to_string(),
         }];
 
@@ -131,6 +287,56 @@ mod tests {
         assert!(result["failed"].as_array().unwrap().is_empty());
     }
 
+    #[test]
+    fn clean_in_transaction_is_undone_by_rollback() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().join("pkg");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("file.txt"), b"content").unwrap();
+
+        let tx = Transaction::begin(tmp.path()).unwrap();
+        let result = run_in(
+            vec![CleanTarget {
+                path: dir.clone(),
+                name: "vendor/pkg".to_string(),
+            }],
+            Some(&tx),
+            &Limiter::default(),
+        );
+        assert_eq!(result["cleaned"].as_u64().unwrap(), 1);
+        assert!(!dir.exists());
+
+        tx.rollback().unwrap();
+        assert!(dir.join("file.txt").exists());
+    }
+
+    #[test]
+    fn dry_run_reports_sizes_and_removes_nothing() {
+        let tmp = TempDir::new().unwrap();
+        let dir = tmp.path().join("pkg");
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(dir.join("a.txt"), b"12345").unwrap();
+        fs::write(dir.join("src/b.txt"), b"123").unwrap();
+
+        let result = dry_run(vec![
+            CleanTarget {
+                path: dir.clone(),
+                name: "vendor/pkg".to_string(),
+            },
+            CleanTarget {
+                path: "/nonexistent/dir".into(),
+                name: "fake/pkg".to_string(),
+            },
+        ]);
+        assert_eq!(result["dry_run"], true);
+        let planned = result["would_remove"].as_array().unwrap();
+        assert_eq!(planned.len(), 1);
+        assert_eq!(planned[0]["files"], 2);
+        assert_eq!(planned[0]["bytes"], 8);
+        assert_eq!(result["total_bytes"], 8);
+        assert!(dir.join("src/b.txt").exists());
+    }
+
     #[test]
     fn clean_mixed_existing_and_nonexistent() {
         let tmp = TempDir::new().unwrap();
@@ -140,11 +346,11 @@ mod tests {
 
         let targets = vec![
             CleanTarget {
-                path: existing.to_string_lossy().to_string(),
+                path: existing.clone(),
                 name: "real/pkg".to_string(),
             },
             CleanTarget {
-                path: "/nonexistent/dir".to_string(),
+                path: "/nonexistent/dir".into(),
                 name: "fake/pkg".to_string(),
             },
         ];
@@ -0,0 +1,987 @@
+//! Command-line front end.
+//!
+//! The PHP plugin talks to the binary by piping a JSON `Input` on stdin. For
+//! humans (Makefiles, shell sessions, CI steps) the same commands are exposed
+//! as subcommands whose flags map one-to-one onto `Input` fields.
+
+use std::fmt::Write;
+use std::fs;
+use std::path::Path;
+
+use turbo_composer::dispatch::Input;
+use turbo_composer::encoding::Encoding;
+use turbo_composer::limits::ResourceLimits;
+use turbo_composer::{classmap, clean, extract, vendor_state, verify};
+
+use crate::daemon::{default_socket_path, ServeOptions};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum OutputFormat {
+    Json,
+    Human,
+    Msgpack,
+}
+
+impl OutputFormat {
+    /// The wire encoding for machine-readable formats.
+    pub(crate) fn encoding(self) -> Option<Encoding> {
+        match self {
+            OutputFormat::Json => Some(Encoding::Json),
+            OutputFormat::Msgpack => Some(Encoding::Msgpack),
+            OutputFormat::Human => None,
+        }
+    }
+}
+
+pub(crate) enum Invocation {
+    /// No command: read an `Input` from stdin (the plugin protocol), in the
+    /// given encoding or, if none was forced with `--format`, whichever the
+    /// request turns out to use.
+    Stdin(Option<Encoding>),
+    Version,
+    Help(Option<String>),
+    Command {
+        input: Box<Input>,
+        format: OutputFormat,
+    },
+    Serve(ServeOptions),
+    /// `watch`: a classmap request that keeps running and streams updates.
+    Watch {
+        input: Box<Input>,
+        format: OutputFormat,
+    },
+}
+
+const USAGE: &str = "\
+turbo-composer — Rust-powered Composer acceleration
+
+USAGE:
+    turbo-composer [COMMAND] [OPTIONS]
+
+With no command, a JSON request is read from stdin.
+
+COMMANDS:
+    classmap       Scan autoload roots and generate the autoload files
+    extract        Extract package archives in parallel
+    verify         Verify archive checksums
+    clean          Remove package directories
+    vendor-check   Check that packages are present in vendor/
+    batch          Run several JSON operations in one invocation
+    recover        Roll back a transactional batch that was interrupted
+    watch          Generate the classmap, then keep it updated as files change
+    capabilities   List supported commands, formats and options
+    serve          Serve requests over a Unix socket, keeping caches warm
+
+GLOBAL OPTIONS:
+    --format <FORMAT>       Output format: json (default), human or msgpack;
+                            without a command, the request encoding on stdin
+    --threads <N>           Worker threads (default: one per core)
+    --io-concurrency <N>    Maximum file reads/writes in flight at once
+    --memory-budget <MB>    Soft cap on archive/source bytes held in memory
+    --dry-run               Report what would change without changing anything
+    -h, --help              Print help (use `<COMMAND> --help` for details)
+    -V, --version           Print version
+";
+
+const CLASSMAP_HELP: &str = "\
+turbo-composer classmap — scan autoload roots and generate the autoload files
+
+USAGE:
+    turbo-composer classmap [OPTIONS]
+
+OPTIONS:
+    --from-composer            Read mappings from composer.json and
+                               vendor/composer/installed.json
+    --no-dev                   Leave out autoload-dev and dev packages
+    --no-optimize              Scan only classmap roots, like a dump without -o
+    --project-dir <DIR>        Project root (default: current directory)
+    --vendor-dir <DIR>         Vendor directory (default: <project-dir>/vendor)
+    --target-dir <DIR>         Where autoload files are written
+                               (default: <vendor-dir>/composer)
+    --psr4 <NS=PATH>           PSR-4 mapping, repeatable
+    --psr0 <NS=PATH>           PSR-0 mapping, repeatable
+    --classmap <PATH>          Classmap directory or file, repeatable
+    --file <ID=PATH>           `files` autoload entry, repeatable
+    --include-path <PATH>      Legacy include-path entry, repeatable
+    --exclude <REGEX>          exclude-from-classmap pattern, repeatable
+    --suffix <SUFFIX>          Autoloader suffix; enables autoload_static.php,
+                               autoload_real.php and autoload.php
+    --staging-suffix <EXT>     Write files as `<name><EXT>` for atomic promotion
+    --platform-check           Require platform_check.php from autoload_real.php
+    --check-platform <MODE>    Generate platform_check.php: true, false or php-only
+    --ignore-platform-req <REQ>
+                               Leave REQ out of the platform check, repeatable
+    --classmap-authoritative   Generate a loader that only uses the classmap
+    --apcu-autoloader          Cache class lookups in APCu
+    --apcu-autoloader-prefix <PREFIX>
+                               APCu key prefix; implies --apcu-autoloader
+    --no-prepend-autoloader    Append the loader to the SPL autoload stack
+    --runtime-dir <DIR>        Composer's src/Composer directory; copies
+                               ClassLoader.php and InstalledVersions.php from it
+                               (needed unless vendor/composer already has them)
+    --strict-ambiguous         Fail if a class is declared by more than one file
+    --strict-psr               Fail if a class does not match its psr-4/psr-0 path
+    --no-write                 Generate but do not write files
+    --trace <FILE>             Write a Chrome trace (Perfetto) of the run to FILE
+";
+
+const WATCH_HELP: &str = "\
+turbo-composer watch — keep the autoload files up to date as PHP files change
+
+USAGE:
+    turbo-composer watch [CLASSMAP OPTIONS]
+
+Runs `classmap` once, then watches every psr-4, psr-0 and classmap root.
+When PHP files are added, removed, renamed or edited, only those files are
+re-parsed and autoload_classmap.php / autoload_static.php are rewritten.
+Prints one line per update until interrupted. Accepts the same options as
+`turbo-composer classmap --help`, except --staging-suffix, --no-write,
+--trace and --dry-run.
+";
+
+const EXTRACT_HELP: &str = "\
+turbo-composer extract — extract package archives in parallel
+
+USAGE:
+    turbo-composer extract --package <NAME> <ARCHIVE> <DEST> [...]
+
+OPTIONS:
+    --package <NAME> <ARCHIVE> <DEST>   Package to extract, repeatable.
+                                        Supports .zip, .tar, .tar.gz and .tgz
+";
+
+const VERIFY_HELP: &str = "\
+turbo-composer verify — verify archive checksums
+
+USAGE:
+    turbo-composer verify --target <NAME> <PATH> <ALGORITHM> <HASH> [...]
+
+OPTIONS:
+    --target <NAME> <PATH> <ALGORITHM> <HASH>
+                        File to verify, repeatable. ALGORITHM is sha256 or sha1
+";
+
+const CLEAN_HELP: &str = "\
+turbo-composer clean — remove package directories
+
+USAGE:
+    turbo-composer clean [PATH...] [--target <NAME> <PATH>...]
+
+OPTIONS:
+    --target <NAME> <PATH>   Directory to remove, repeatable
+    PATH                     Directory to remove, named after its path
+";
+
+const VENDOR_CHECK_HELP: &str = "\
+turbo-composer vendor-check — check that packages are present in vendor/
+
+USAGE:
+    turbo-composer vendor-check --package <NAME> <PATH> [...]
+
+OPTIONS:
+    --package <NAME> <PATH>   Package name and install path, repeatable
+";
+
+const BATCH_HELP: &str = "\
+turbo-composer batch — run several operations in one invocation
+
+USAGE:
+    turbo-composer batch [--on-error <POLICY>] [--transactional <VENDOR_DIR>] [FILE]
+
+Reads a JSON array of operations (or an object with an `operations` array
+and optional `on_error`) from FILE, or from stdin when FILE is omitted or `-`.
+Each operation uses the same fields as a stdin request, plus an optional `id`
+and `depends_on` list of ids. Operations whose dependencies are done run
+concurrently; without `depends_on` an operation waits for the one before it.
+
+With --transactional, cleaned directories are moved into
+VENDOR_DIR/.turbo-trash and archives are extracted into staging directories;
+they replace the live vendor/ only if every operation succeeds, and are
+rolled back otherwise.
+
+OPTIONS:
+    --on-error <POLICY>              continue (default), abort, or skip_dependents
+    --transactional <VENDOR_DIR>     Apply all changes atomically under VENDOR_DIR
+";
+
+const RECOVER_HELP: &str = "\
+turbo-composer recover — roll back an interrupted transactional batch
+
+USAGE:
+    turbo-composer recover <VENDOR_DIR>
+
+Replays VENDOR_DIR/.turbo-trash/journal.json, restoring vendor/ to its state
+before the interrupted batch. Does nothing if there is no journal.
+";
+
+const SERVE_HELP: &str = "\
+turbo-composer serve — serve requests over a Unix domain socket
+
+USAGE:
+    turbo-composer serve [--socket <PATH>] [--idle-timeout <SECONDS>]
+
+Accepts the same JSON requests as stdin, one per line, and answers each with
+one line of JSON. Classmap caches are kept in memory between requests and
+written back to .turbo-cache when the server is idle. Send
+{\"command\":\"ping\"} to check liveness and {\"command\":\"shutdown\"} to stop.
+
+OPTIONS:
+    --socket <PATH>            Socket path (default: $XDG_RUNTIME_DIR/turbo-composer.sock,
+                               or $TMPDIR/turbo-composer-$UID/turbo-composer.sock)
+    --idle-timeout <SECONDS>   Exit after this many seconds without requests
+";
+
+pub(crate) fn help_text(topic: Option<&str>) -> &'static str {
+    match topic {
+        Some("classmap") => CLASSMAP_HELP,
+        Some("extract") => EXTRACT_HELP,
+        Some("verify") => VERIFY_HELP,
+        Some("clean") => CLEAN_HELP,
+        Some("vendor-check") => VENDOR_CHECK_HELP,
+        Some("batch") => BATCH_HELP,
+        Some("recover") => RECOVER_HELP,
+        Some("serve") => SERVE_HELP,
+        Some("watch") => WATCH_HELP,
+        _ => USAGE,
+    }
+}
+
+/// Cursor over the argument list that knows how to pull flag values.
+struct Args<'a> {
+    args: &'a [String],
+    pos: usize,
+}
+
+impl<'a> Args<'a> {
+    fn next(&mut self) -> Option<&'a str> {
+        let arg = self.args.get(self.pos)?;
+        self.pos += 1;
+        Some(arg.as_str())
+    }
+
+    fn value(&mut self, flag: &str) -> Result<String, String> {
+        match self.next() {
+            Some(v) => Ok(v.to_string()),
+            None => Err(format!("{flag} requires a value")),
+        }
+    }
+
+    fn values<const N: usize>(&mut self, flag: &str) -> Result<[String; N], String> {
+        let mut out: [String; N] = std::array::from_fn(|_| String::new());
+        for slot in &mut out {
+            *slot = self
+                .next()
+                .map(str::to_string)
+                .ok_or_else(|| format!("{flag} requires {N} values"))?;
+        }
+        Ok(out)
+    }
+}
+
+pub(crate) fn parse(args: &[String]) -> Result<Invocation, String> {
+    if args.is_empty() {
+        return Ok(Invocation::Stdin(None));
+    }
+
+    let mut cursor = Args { args, pos: 0 };
+    let mut format: Option<OutputFormat> = None;
+    let mut command: Option<String> = None;
+    let mut rest: Vec<String> = Vec::new();
+    let mut limits = ResourceLimits::default();
+    let mut dry_run = false;
+
+    // Global flags may appear before or after the command name.
+    while let Some(arg) = cursor.next() {
+        match arg {
+            "-V" | "--version" => return Ok(Invocation::Version),
+            "-h" | "--help" => return Ok(Invocation::Help(command)),
+            "help" if command.is_none() => {
+                return Ok(Invocation::Help(cursor.next().map(str::to_string)));
+            }
+            "--format" => format = Some(parse_format(&cursor.value("--format")?)?),
+            _ if arg.starts_with("--format=") => format = Some(parse_format(&arg[9..])?),
+            "--threads" => limits.threads = Some(parse_count(arg, &cursor.value(arg)?)?),
+            "--io-concurrency" => {
+                limits.io_concurrency = Some(parse_count(arg, &cursor.value(arg)?)?)
+            }
+            "--memory-budget" => {
+                limits.memory_budget_mb = Some(parse_count(arg, &cursor.value(arg)?)? as u64)
+            }
+            "--dry-run" => dry_run = true,
+            _ if command.is_none() && !arg.starts_with('-') => command = Some(arg.to_string()),
+            _ => rest.push(arg.to_string()),
+        }
+    }
+
+    let Some(command) = command else {
+        if rest.is_empty() && format != Some(OutputFormat::Human) {
+            return Ok(Invocation::Stdin(format.and_then(OutputFormat::encoding)));
+        }
+        return Err("no command given".to_string());
+    };
+    let format = format.unwrap_or(OutputFormat::Json);
+
+    let mut cursor = Args {
+        args: &rest,
+        pos: 0,
+    };
+    let mut input = match command.as_str() {
+        "classmap" => parse_classmap(&mut cursor)?,
+        "extract" => parse_extract(&mut cursor)?,
+        "verify" => parse_verify(&mut cursor)?,
+        "clean" => parse_clean(&mut cursor)?,
+        "vendor-check" => parse_vendor_check(&mut cursor)?,
+        "batch" => parse_batch(&mut cursor)?,
+        "recover" => {
+            let mut input = Input::new("recover");
+            match (cursor.next(), cursor.next()) {
+                (Some(dir), None) if !dir.starts_with('-') => {
+                    input.vendor_dir = Some(absolute(dir))
+                }
+                (None, _) => return Err("recover requires a vendor directory".to_string()),
+                (Some(arg), None) | (Some(_), Some(arg)) => return Err(unexpected(arg)),
+            }
+            input
+        }
+        "capabilities" => match cursor.next() {
+            Some(arg) => return Err(unexpected(arg)),
+            None => Input::new("capabilities"),
+        },
+        "serve" => return parse_serve(&mut cursor).map(Invocation::Serve),
+        "watch" => {
+            let mut input = parse_classmap(&mut cursor)?;
+            if input.staging_suffix.is_some() || !input.write_files {
+                return Err("watch always writes autoload files in place".to_string());
+            }
+            if input.trace_file.is_some() {
+                return Err("--trace is not supported by watch".to_string());
+            }
+            if dry_run {
+                return Err("--dry-run is not supported by watch".to_string());
+            }
+            input.command = "watch".to_string();
+            input.limits = limits;
+            return Ok(Invocation::Watch {
+                input: Box::new(input),
+                format,
+            });
+        }
+        other => return Err(format!("unknown command: {other}")),
+    };
+
+    input.limits = limits;
+    input.dry_run |= dry_run;
+    Ok(Invocation::Command {
+        input: Box::new(input),
+        format,
+    })
+}
+
+/// A positive integer flag value.
+fn parse_count(flag: &str, value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!("{flag} expects a positive integer, got: {value}")),
+    }
+}
+
+fn parse_format(value: &str) -> Result<OutputFormat, String> {
+    match value {
+        "json" => Ok(OutputFormat::Json),
+        "human" => Ok(OutputFormat::Human),
+        "msgpack" => Ok(OutputFormat::Msgpack),
+        other => Err(format!(
+            "unknown format: {other} (expected json, human or msgpack)"
+        )),
+    }
+}
+
+/// Resolve a user-supplied path to an absolute one. Generated PHP code embeds
+/// paths relative to the vendor/project dirs, so everything must be absolute
+/// before it reaches the classmap engine.
+fn absolute(path: &str) -> String {
+    let p = Path::new(path);
+    fs::canonicalize(p)
+        .or_else(|_| std::path::absolute(p))
+        .map(|p| p.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| path.to_string())
+}
+
+/// Like [`absolute`], but relative paths are taken from `base` (the project
+/// dir) rather than the working directory, as they would be in composer.json.
+fn absolute_from(base: &str, path: &str) -> String {
+    if Path::new(path).is_absolute() {
+        absolute(path)
+    } else {
+        absolute(&Path::new(base).join(path).to_string_lossy())
+    }
+}
+
+fn split_pair<'a>(flag: &str, value: &'a str) -> Result<(&'a str, &'a str), String> {
+    value
+        .split_once('=')
+        .ok_or_else(|| format!("{flag} expects <KEY=PATH>, got: {value}"))
+}
+
+fn unexpected(arg: &str) -> String {
+    format!("unexpected argument: {arg}")
+}
+
+fn parse_classmap(args: &mut Args) -> Result<Input, String> {
+    let mut input = Input::new("classmap");
+    let mut project_dir: Option<String> = None;
+    let mut vendor_dir: Option<String> = None;
+    let mut target_dir: Option<String> = None;
+    let mut psr4: Vec<(String, String)> = Vec::new();
+    let mut psr0: Vec<(String, String)> = Vec::new();
+    let mut classmap_paths: Vec<String> = Vec::new();
+    let mut files: Vec<(String, String)> = Vec::new();
+    let mut include_paths: Vec<String> = Vec::new();
+
+    while let Some(arg) = args.next() {
+        match arg {
+            "--project-dir" => project_dir = Some(args.value(arg)?),
+            "--vendor-dir" => vendor_dir = Some(args.value(arg)?),
+            "--target-dir" => target_dir = Some(args.value(arg)?),
+            "--psr4" | "--psr0" | "--file" => {
+                let value = args.value(arg)?;
+                let (key, path) = split_pair(arg, &value)?;
+                let pair = (key.to_string(), path.to_string());
+                match arg {
+                    "--psr4" => psr4.push(pair),
+                    "--psr0" => psr0.push(pair),
+                    _ => files.push(pair),
+                }
+            }
+            "--classmap" => classmap_paths.push(args.value(arg)?),
+            "--include-path" => include_paths.push(args.value(arg)?),
+            "--exclude" => input.exclude_from_classmap.push(args.value(arg)?),
+            "--suffix" => input.suffix = Some(args.value(arg)?),
+            "--staging-suffix" => input.staging_suffix = Some(args.value(arg)?),
+            "--platform-check" => input.has_platform_check = true,
+            "--check-platform" => input.platform_check = Some(args.value(arg)?.parse()?),
+            "--ignore-platform-req" => input.ignore_platform_reqs.push(args.value(arg)?),
+            "--strict-ambiguous" => input.strict_ambiguous = true,
+            "--strict-psr" => input.strict_psr = true,
+            "--classmap-authoritative" => input.authoritative = true,
+            "--apcu-autoloader" => input.apcu_autoloader = true,
+            "--apcu-autoloader-prefix" => {
+                input.apcu_autoloader = true;
+                input.apcu_prefix = Some(args.value(arg)?);
+            }
+            "--no-prepend-autoloader" => input.prepend_autoloader = Some(false),
+            "--runtime-dir" => input.runtime_dir = Some(absolute(&args.value(arg)?)),
+            "--no-write" => input.write_files = false,
+            "--from-composer" => input.from_composer_json = true,
+            "--no-dev" => input.dev_mode = false,
+            "--no-optimize" => input.scan_psr_packages = false,
+            "--trace" => input.trace_file = Some(absolute(&args.value(arg)?)),
+            other => return Err(unexpected(other)),
+        }
+    }
+
+    let project_dir = absolute(project_dir.as_deref().unwrap_or("."));
+
+    if input.from_composer_json {
+        if !(psr4.is_empty()
+            && psr0.is_empty()
+            && classmap_paths.is_empty()
+            && files.is_empty()
+            && include_paths.is_empty())
+        {
+            return Err("--from-composer cannot be combined with explicit mappings".to_string());
+        }
+        input.vendor_dir = vendor_dir.map(|v| absolute_from(&project_dir, &v));
+        input.target_dir = target_dir.map(|t| absolute_from(&project_dir, &t));
+        input.project_dir = Some(project_dir);
+        return Ok(input);
+    }
+
+    let vendor_dir = absolute_from(&project_dir, vendor_dir.as_deref().unwrap_or("vendor"));
+    let target_dir = match target_dir {
+        Some(td) => absolute_from(&project_dir, &td),
+        None => format!("{vendor_dir}/composer"),
+    };
+
+    let namespace_mapping = |(namespace, path): (String, String)| classmap::NamespaceMapping {
+        namespace,
+        path: absolute_from(&project_dir, &path),
+        dev: false,
+    };
+    let autoload = classmap::AutoloadMappings {
+        psr4: psr4.into_iter().map(namespace_mapping).collect(),
+        psr0: psr0.into_iter().map(namespace_mapping).collect(),
+        classmap: classmap_paths
+            .iter()
+            .map(|p| absolute_from(&project_dir, p).into())
+            .collect(),
+        files: files
+            .into_iter()
+            .map(|(identifier, path)| classmap::FileAutoload {
+                identifier,
+                path: absolute_from(&project_dir, &path),
+                dev: false,
+            })
+            .collect(),
+        include_path: include_paths
+            .iter()
+            .map(|p| absolute_from(&project_dir, p).into())
+            .collect(),
+    };
+
+    input.has_files_autoload = !autoload.files.is_empty();
+    input.target_dir = Some(target_dir);
+    input.vendor_dir = Some(vendor_dir);
+    input.project_dir = Some(project_dir);
+    input.autoload = Some(autoload);
+    Ok(input)
+}
+
+fn parse_extract(args: &mut Args) -> Result<Input, String> {
+    let mut input = Input::new("extract");
+    while let Some(arg) = args.next() {
+        match arg {
+            "--package" => {
+                let [name, zip, dest] = args.values(arg)?;
+                input.packages.push(extract::PackageExtraction {
+                    zip: absolute(&zip).into(),
+                    dest: absolute(&dest).into(),
+                    name,
+                });
+            }
+            other => return Err(unexpected(other)),
+        }
+    }
+    Ok(input)
+}
+
+fn parse_verify(args: &mut Args) -> Result<Input, String> {
+    let mut input = Input::new("verify");
+    while let Some(arg) = args.next() {
+        match arg {
+            "--target" => {
+                let [name, path, algorithm, expected_hash] = args.values(arg)?;
+                input.verify_targets.push(verify::VerifyTarget {
+                    path: absolute(&path).into(),
+                    name,
+                    algorithm,
+                    expected_hash,
+                });
+            }
+            other => return Err(unexpected(other)),
+        }
+    }
+    Ok(input)
+}
+
+fn parse_clean(args: &mut Args) -> Result<Input, String> {
+    let mut input = Input::new("clean");
+    while let Some(arg) = args.next() {
+        match arg {
+            "--target" => {
+                let [name, path] = args.values(arg)?;
+                input.targets.push(clean::CleanTarget {
+                    path: absolute(&path).into(),
+                    name,
+                });
+            }
+            other if other.starts_with('-') => return Err(unexpected(other)),
+            path => input.targets.push(clean::CleanTarget {
+                path: absolute(path).into(),
+                name: path.to_string(),
+            }),
+        }
+    }
+    Ok(input)
+}
+
+fn parse_vendor_check(args: &mut Args) -> Result<Input, String> {
+    let mut input = Input::new("vendor-check");
+    while let Some(arg) = args.next() {
+        match arg {
+            "--package" => {
+                let [name, path] = args.values(arg)?;
+                input.check_packages.push(vendor_state::PackageCheck {
+                    name,
+                    install_path: absolute(&path).into(),
+                });
+            }
+            other => return Err(unexpected(other)),
+        }
+    }
+    Ok(input)
+}
+
+fn parse_batch(args: &mut Args) -> Result<Input, String> {
+    let mut source: Option<String> = None;
+    let mut on_error = None;
+    let mut transactional = None;
+    while let Some(arg) = args.next() {
+        match arg {
+            "--transactional" => transactional = Some(absolute(&args.value(arg)?)),
+            "--on-error" => {
+                let value = args.value(arg)?;
+                on_error = Some(
+                    serde_json::from_value(serde_json::Value::String(value.clone())).map_err(
+                        |_| {
+                            format!(
+                                "--on-error expects continue, abort or skip_dependents, got: {value}"
+                            )
+                        },
+                    )?,
+                );
+            }
+            "-" => source = None,
+            other if other.starts_with('-') => return Err(unexpected(other)),
+            file => source = Some(file.to_string()),
+        }
+    }
+
+    let raw = match source {
+        Some(file) => fs::read_to_string(&file).map_err(|e| format!("{file}: {e}"))?,
+        None => {
+            let mut buf = String::new();
+            std::io::Read::read_to_string(&mut std::io::stdin(), &mut buf)
+                .map_err(|e| format!("failed to read stdin: {e}"))?;
+            buf
+        }
+    };
+
+    let value: serde_json::Value =
+        serde_json::from_str(&raw).map_err(|e| format!("invalid batch JSON: {e}"))?;
+    let mut input = Input::new("batch");
+    match value {
+        serde_json::Value::Array(ops) => input.operations = ops,
+        serde_json::Value::Object(mut obj) => {
+            match obj.remove("operations") {
+                Some(serde_json::Value::Array(ops)) => input.operations = ops,
+                _ => return Err("batch JSON object must contain an `operations` array".to_string()),
+            }
+            if let Some(policy) = obj.remove("on_error") {
+                input.on_error = serde_json::from_value(policy)
+                    .map_err(|e| format!("invalid on_error in batch JSON: {e}"))?;
+            }
+            if let Some(flag) = obj.remove("transactional") {
+                input.transactional = flag == true;
+            }
+            if let Some(serde_json::Value::String(dir)) = obj.remove("vendor_dir") {
+                input.vendor_dir = Some(dir);
+            }
+        }
+        _ => return Err("batch JSON must be an array of operations".to_string()),
+    }
+    if let Some(policy) = on_error {
+        input.on_error = policy;
+    }
+    if let Some(dir) = transactional {
+        input.transactional = true;
+        input.vendor_dir = Some(dir);
+    }
+    Ok(input)
+}
+
+fn parse_serve(args: &mut Args) -> Result<ServeOptions, String> {
+    let mut options = ServeOptions {
+        socket: default_socket_path(),
+        idle_timeout: None,
+    };
+    while let Some(arg) = args.next() {
+        match arg {
+            "--socket" => options.socket = absolute(&args.value(arg)?).into(),
+            "--idle-timeout" => {
+                let value = args.value(arg)?;
+                let secs: u64 = value
+                    .parse()
+                    .map_err(|_| format!("--idle-timeout expects seconds, got: {value}"))?;
+                options.idle_timeout = Some(std::time::Duration::from_secs(secs));
+            }
+            other => return Err(unexpected(other)),
+        }
+    }
+    Ok(options)
+}
+
+/// Render a command result as a short human-readable summary.
+pub(crate) fn render_human(command: &str, output: &serde_json::Value) -> String {
+    let mut out = String::new();
+    let u = |key: &str| output[key].as_u64().unwrap_or(0);
+
+    match command {
+        "classmap" => {
+            let stats = &output["stats"];
+            let _ = writeln!(
+                out,
+                "classmap: {} classes mapped from {} PHP files ({} cache hits) in {}ms",
+                u("classmap_count"),
+                stats["php_files_found"].as_u64().unwrap_or(0),
+                stats["cache_hits"].as_u64().unwrap_or(0),
+                stats["elapsed_ms"].as_u64().unwrap_or(0),
+            );
+            for entry in output["ambiguous"].as_array().into_iter().flatten() {
+                let others: Vec<&str> = entry["others"]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|o| o.as_str())
+                    .collect();
+                let _ = writeln!(
+                    out,
+                    "  ambiguous: {} in {} (also in {})",
+                    entry["class"].as_str().unwrap_or("?"),
+                    entry["chosen"].as_str().unwrap_or("?"),
+                    others.join(", "),
+                );
+            }
+            for violation in output["psr_violations"].as_array().into_iter().flatten() {
+                let _ = writeln!(
+                    out,
+                    "  {} violation: {} in {} (expected {})",
+                    violation["standard"].as_str().unwrap_or("psr"),
+                    violation["class"].as_str().unwrap_or("?"),
+                    violation["file"].as_str().unwrap_or("?"),
+                    violation["expected"]
+                        .as_str()
+                        .unwrap_or("no file under this mapping"),
+                );
+            }
+            if let Some(changes) = output["would_change"].as_array() {
+                let _ = writeln!(
+                    out,
+                    "  dry run: {} autoload files would change",
+                    changes.len()
+                );
+                for name in changes {
+                    let _ = writeln!(out, "    {}", name.as_str().unwrap_or("?"));
+                }
+            } else {
+                let written = output["files_written"].as_bool().unwrap_or(false);
+                let _ = writeln!(
+                    out,
+                    "  autoload files {}",
+                    if written { "written" } else { "not written" }
+                );
+            }
+        }
+        "watch" if output["event"] == "update" => {
+            let _ = writeln!(
+                out,
+                "watch: +{} -{} classes ({} total) in {}ms",
+                output["classes_added"].as_array().map_or(0, Vec::len),
+                output["classes_removed"].as_array().map_or(0, Vec::len),
+                u("classmap_count"),
+                u("elapsed_ms"),
+            );
+        }
+        "watch" => {
+            out.push_str(&render_human("classmap", output));
+            let _ = writeln!(out, "watching for changes...");
+        }
+        "extract" if output["dry_run"] == true => {
+            let packages = output["packages"]
+                .as_array()
+                .map(Vec::as_slice)
+                .unwrap_or(&[]);
+            let _ = writeln!(
+                out,
+                "extract (dry run): {} packages, {} files",
+                packages.len(),
+                u("total_files"),
+            );
+            for package in packages {
+                let _ = writeln!(
+                    out,
+                    "  {} -> {}: {} created, {} overwritten, {} removed",
+                    package["name"].as_str().unwrap_or("?"),
+                    package["dest"].as_str().unwrap_or("?"),
+                    package["created"].as_u64().unwrap_or(0),
+                    package["overwritten"].as_u64().unwrap_or(0),
+                    package["removed"].as_array().map_or(0, Vec::len),
+                );
+            }
+            render_failures(&mut out, &output["failed"]);
+        }
+        "extract" => {
+            let _ = writeln!(
+                out,
+                "extract: {} packages extracted ({} files) in {}ms",
+                u("extracted"),
+                u("total_files"),
+                u("elapsed_ms"),
+            );
+            render_failures(&mut out, &output["failed"]);
+        }
+        "verify" => {
+            let _ = writeln!(
+                out,
+                "verify: {}/{} verified in {}ms",
+                u("verified"),
+                u("total"),
+                u("elapsed_ms"),
+            );
+            for failure in output["failed"].as_array().into_iter().flatten() {
+                let name = failure["name"].as_str().unwrap_or("?");
+                match failure["error"].as_str() {
+                    Some(error) => {
+                        let _ = writeln!(out, "  FAILED {name}: {error}");
+                    }
+                    None => {
+                        let _ = writeln!(
+                            out,
+                            "  FAILED {name}: expected {}, got {}",
+                            failure["expected"].as_str().unwrap_or(""),
+                            failure["actual"].as_str().unwrap_or(""),
+                        );
+                    }
+                }
+            }
+        }
+        "clean" if output["dry_run"] == true => {
+            let targets = output["would_remove"]
+                .as_array()
+                .map(Vec::as_slice)
+                .unwrap_or(&[]);
+            let _ = writeln!(
+                out,
+                "clean (dry run): {} directories, {} bytes would be removed",
+                targets.len(),
+                u("total_bytes"),
+            );
+            for target in targets {
+                let _ = writeln!(
+                    out,
+                    "  {}: {} files, {} bytes",
+                    target["path"].as_str().unwrap_or("?"),
+                    target["files"].as_u64().unwrap_or(0),
+                    target["bytes"].as_u64().unwrap_or(0),
+                );
+            }
+            render_failures(&mut out, &output["failed"]);
+        }
+        "clean" => {
+            let _ = writeln!(
+                out,
+                "clean: {} directories removed in {}ms",
+                u("cleaned"),
+                u("elapsed_ms"),
+            );
+            render_failures(&mut out, &output["failed"]);
+        }
+        "vendor-check" => {
+            let _ = writeln!(
+                out,
+                "vendor-check: {}/{} packages present in {}ms",
+                u("present"),
+                u("total"),
+                u("elapsed_ms"),
+            );
+            for name in output["missing"].as_array().into_iter().flatten() {
+                let _ = writeln!(out, "  missing: {}", name.as_str().unwrap_or("?"));
+            }
+            for name in output["incomplete"].as_array().into_iter().flatten() {
+                let _ = writeln!(out, "  incomplete: {}", name.as_str().unwrap_or("?"));
+            }
+        }
+        "batch" => {
+            let results = output["results"]
+                .as_array()
+                .map(Vec::as_slice)
+                .unwrap_or(&[]);
+            let _ = writeln!(
+                out,
+                "batch: {} operations in {}ms{}{}",
+                results.len(),
+                u("elapsed_ms"),
+                if output["aborted"] == true {
+                    " (aborted)"
+                } else {
+                    ""
+                },
+                match output["transaction"].as_str() {
+                    Some("rolled_back") => ", rolled back",
+                    Some(_) => ", committed",
+                    None => "",
+                },
+            );
+            for result in results {
+                let command = result["command"].as_str().unwrap_or("?");
+                let name = result["id"].as_str().unwrap_or(command);
+                match result["status"].as_str() {
+                    Some("skipped") => {
+                        let reason = result["reason"].as_str().unwrap_or("");
+                        let _ = writeln!(out, "{name}: skipped ({reason})");
+                    }
+                    _ if result["error"].is_object() => {
+                        let message = result["error"]["message"].as_str().unwrap_or("?");
+                        let _ = writeln!(out, "{name}: error: {message}");
+                    }
+                    _ => out.push_str(&render_human(command, &result["result"])),
+                }
+            }
+        }
+        "recover" if output["dry_run"] == true => {
+            let line = match &output["would_recover"] {
+                serde_json::Value::Null => {
+                    "recover (dry run): no interrupted transaction".to_string()
+                }
+                r if r["rolled_back"] == true => format!(
+                    "recover (dry run): would roll back {} journal entries",
+                    r["actions"].as_u64().unwrap_or(0)
+                ),
+                _ => "recover (dry run): would remove leftovers of a committed transaction"
+                    .to_string(),
+            };
+            let _ = writeln!(out, "{line}");
+        }
+        "recover" => {
+            let line = match &output["recovered"] {
+                serde_json::Value::Null => "recover: no interrupted transaction".to_string(),
+                r if r["rolled_back"] == true => format!(
+                    "recover: rolled back {} journal entries",
+                    r["actions"].as_u64().unwrap_or(0)
+                ),
+                _ => "recover: removed leftovers of a committed transaction".to_string(),
+            };
+            let _ = writeln!(out, "{line}");
+        }
+        "capabilities" => {
+            let list = |key: &str| {
+                output[key]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|v| v.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            let _ = writeln!(
+                out,
+                "turbo-composer {} (protocol {}, accepts {}-{})",
+                output["version"].as_str().unwrap_or("?"),
+                u("protocol_version"),
+                u("min_protocol_version"),
+                u("protocol_version"),
+            );
+            let _ = writeln!(out, "  commands:         {}", list("commands"));
+            let _ = writeln!(out, "  archive formats:  {}", list("archive_formats"));
+            let _ = writeln!(out, "  hash algorithms:  {}", list("hash_algorithms"));
+            let _ = writeln!(out, "  classmap options: {}", list("classmap_options"));
+            let _ = writeln!(out, "  encodings:        {}", list("encodings"));
+        }
+        _ => {
+            let _ = writeln!(out, "{output}");
+        }
+    }
+
+    out
+}
+
+fn render_failures(out: &mut String, failed: &serde_json::Value) {
+    for failure in failed.as_array().into_iter().flatten() {
+        let _ = writeln!(
+            out,
+            "  FAILED {}: {}",
+            failure["name"].as_str().unwrap_or("?"),
+            failure["error"].as_str().unwrap_or(""),
+        );
+    }
+}
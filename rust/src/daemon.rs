@@ -0,0 +1,344 @@
+//! Long-running server mode.
+//!
+//! `turbo-composer serve --socket <PATH>` accepts the same JSON requests as
+//! the stdin protocol over a Unix domain socket, one request per line and one
+//! response per line. Classmap caches stay in memory between requests and are
+//! flushed to `.turbo-cache` in the background once the server goes idle, so
+//! warm runs skip the cache load/save entirely.
+//!
+//! Requests can delete and extract files in vendor/, so the socket is only
+//! for the user running the server: it is created mode 0600, by default in a
+//! directory no one else can enter, and connections from other users are
+//! refused.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use turbo_composer::dispatch::{self, Caches, Input};
+use turbo_composer::{classmap, Error, ErrorCode};
+
+/// Flush dirty caches once no request has arrived for this long.
+const FLUSH_AFTER_IDLE: Duration = Duration::from_secs(2);
+
+pub(crate) struct ServeOptions {
+    pub socket: PathBuf,
+    /// Exit after this long without requests. `None` runs until `shutdown`.
+    pub idle_timeout: Option<Duration>,
+}
+
+struct Server {
+    caches: Caches,
+    started: Instant,
+    /// Milliseconds since `started` at which the last request finished.
+    last_activity_ms: AtomicU64,
+    in_flight: AtomicUsize,
+    socket: PathBuf,
+}
+
+impl Server {
+    fn touch(&self) {
+        self.last_activity_ms
+            .store(self.started.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+
+    fn idle_for(&self) -> Duration {
+        let now = self.started.elapsed().as_millis() as u64;
+        Duration::from_millis(now.saturating_sub(self.last_activity_ms.load(Ordering::Relaxed)))
+    }
+
+    fn flush(&self) {
+        let mut store = self.caches.lock().unwrap_or_else(|e| e.into_inner());
+        store.flush();
+    }
+
+    fn shutdown(&self) -> ! {
+        self.flush();
+        let _ = std::fs::remove_file(&self.socket);
+        std::process::exit(0);
+    }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn serve(_options: ServeOptions) -> Result<(), Error> {
+    Err(Error::invalid_request(
+        "serve is only supported on Unix platforms",
+    ))
+}
+
+#[cfg(unix)]
+pub(crate) fn serve(options: ServeOptions) -> Result<(), Error> {
+    use std::os::unix::net::{UnixListener, UnixStream};
+
+    let socket = &options.socket;
+    if let Some(dir) = private_socket_dir().filter(|dir| socket.parent() == Some(dir)) {
+        ensure_private_dir(&dir)?;
+    }
+    if socket.exists() {
+        // A live server answers; a stale socket file from a crashed one does not.
+        if UnixStream::connect(socket).is_ok() {
+            return Err(Error::new(
+                ErrorCode::Io,
+                format!("a server is already listening on {}", socket.display()),
+            )
+            .with_path(socket));
+        }
+        std::fs::remove_file(socket).map_err(|e| Error::io(e, socket))?;
+    }
+
+    let listener = UnixListener::bind(socket).map_err(|e| Error::io(e, socket))?;
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(socket, std::fs::Permissions::from_mode(0o600))
+            .map_err(|e| Error::io(e, socket))?;
+    }
+
+    let server = Arc::new(Server {
+        caches: Caches::new(classmap::CacheStore::new()),
+        started: Instant::now(),
+        last_activity_ms: AtomicU64::new(0),
+        in_flight: AtomicUsize::new(0),
+        socket: socket.clone(),
+    });
+
+    eprintln!(
+        "turbo-composer: serving on {} (pid {})",
+        socket.display(),
+        std::process::id()
+    );
+
+    spawn_housekeeping(Arc::clone(&server), options.idle_timeout);
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("turbo-composer: accept failed: {e}");
+                continue;
+            }
+        };
+        let server = Arc::clone(&server);
+        std::thread::spawn(move || handle_connection(&server, stream));
+    }
+
+    Ok(())
+}
+
+/// Background thread: persist caches once the server is idle, and exit when
+/// the optional idle timeout elapses.
+fn spawn_housekeeping(server: Arc<Server>, idle_timeout: Option<Duration>) {
+    std::thread::spawn(move || loop {
+        std::thread::sleep(Duration::from_millis(500));
+        if server.in_flight.load(Ordering::Acquire) > 0 {
+            continue;
+        }
+
+        let idle = server.idle_for();
+        if idle >= FLUSH_AFTER_IDLE {
+            let dirty = server.caches.lock().map(|s| s.is_dirty()).unwrap_or(false);
+            if dirty {
+                server.flush();
+            }
+        }
+        if idle_timeout.is_some_and(|timeout| idle >= timeout) {
+            eprintln!("turbo-composer: idle timeout reached, shutting down");
+            server.shutdown();
+        }
+    });
+}
+
+#[cfg(unix)]
+fn handle_connection(server: &Server, stream: std::os::unix::net::UnixStream) {
+    use std::io::{BufRead, BufReader, Write};
+
+    let mut writer = match stream.try_clone() {
+        Ok(w) => w,
+        Err(_) => return,
+    };
+    // SAFETY: getuid has no preconditions and cannot fail.
+    let uid = unsafe { libc::getuid() };
+    match peer_uid(&stream) {
+        Ok(peer) if peer == uid => {}
+        peer => {
+            let peer = peer.map_or_else(|e| e.to_string(), |peer| format!("uid {peer}"));
+            eprintln!("turbo-composer: refused connection from {peer}");
+            let error = Error::new(
+                ErrorCode::PermissionDenied,
+                "the server only accepts connections from the user running it",
+            );
+            let response = dispatch::with_protocol(error.to_response());
+            let _ = writeln!(writer, "{response}");
+            return;
+        }
+    }
+    let reader = BufReader::new(stream);
+
+    for line in reader.lines() {
+        let Ok(line) = line else {
+            return;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        server.in_flight.fetch_add(1, Ordering::AcqRel);
+        let (response, shutdown) = handle_request(server, &line);
+        let response = dispatch::with_protocol(response);
+        server.touch();
+        server.in_flight.fetch_sub(1, Ordering::AcqRel);
+
+        let json = serde_json::to_string(&response).expect("failed to serialise output");
+        if writeln!(writer, "{json}")
+            .and_then(|_| writer.flush())
+            .is_err()
+        {
+            return;
+        }
+        if shutdown {
+            server.shutdown();
+        }
+    }
+}
+
+/// Handle one request line. Returns the response and whether the server
+/// should shut down after sending it.
+fn handle_request(server: &Server, line: &str) -> (serde_json::Value, bool) {
+    let parse_start = Instant::now();
+    let input: Input = match serde_json::from_str(line) {
+        Ok(input) => input,
+        Err(e) => {
+            let error = Error::invalid_request(format!("failed to parse input JSON: {e}"));
+            return (error.to_response(), false);
+        }
+    };
+    let json_parse_ms = parse_start.elapsed().as_millis();
+
+    match input.command.as_str() {
+        "ping" => (
+            serde_json::json!({
+                "pong": true,
+                "version": dispatch::VERSION,
+                "pid": std::process::id(),
+                "uptime_ms": server.started.elapsed().as_millis() as u64,
+            }),
+            false,
+        ),
+        "shutdown" => (serde_json::json!({ "shutdown": true }), true),
+        _ => {
+            let command_start = Instant::now();
+            let mut output = match dispatch::dispatch(input, Some(&server.caches)) {
+                Ok(output) => output,
+                Err(e) => e.to_response(),
+            };
+            let command_ms = command_start.elapsed().as_millis();
+
+            if let Some(stats) = output.get_mut("stats").and_then(|s| s.as_object_mut()) {
+                stats.insert(
+                    "json_parse_ms".to_string(),
+                    serde_json::json!(json_parse_ms),
+                );
+                stats.insert("command_ms".to_string(), serde_json::json!(command_ms));
+                stats.insert("daemon".to_string(), true.into());
+            }
+            (output, false)
+        }
+    }
+}
+
+/// The uid of the process at the other end of `stream`.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn peer_uid(stream: &std::os::unix::net::UnixStream) -> std::io::Result<libc::uid_t> {
+    use std::os::unix::io::AsRawFd;
+
+    let mut cred = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut len = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    // SAFETY: `cred` and `len` are valid for writes and `len` is its size.
+    let rc = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            (&mut cred as *mut libc::ucred).cast(),
+            &mut len,
+        )
+    };
+    match rc {
+        0 => Ok(cred.uid),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
+
+/// The uid of the process at the other end of `stream`.
+#[cfg(all(unix, not(any(target_os = "linux", target_os = "android"))))]
+fn peer_uid(stream: &std::os::unix::net::UnixStream) -> std::io::Result<libc::uid_t> {
+    use std::os::unix::io::AsRawFd;
+
+    let (mut uid, mut gid) = (0, 0);
+    // SAFETY: `uid` and `gid` are valid for writes.
+    match unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) } {
+        0 => Ok(uid),
+        _ => Err(std::io::Error::last_os_error()),
+    }
+}
+
+/// Create `dir` mode 0700 if it is missing, and refuse it unless it is a real
+/// directory owned by this user that no one else can enter, so another user
+/// cannot plant a socket or symlink there first.
+#[cfg(unix)]
+fn ensure_private_dir(dir: &Path) -> Result<(), Error> {
+    use std::os::unix::fs::{DirBuilderExt, MetadataExt};
+
+    match std::fs::DirBuilder::new().mode(0o700).create(dir) {
+        Ok(()) => {}
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {}
+        Err(e) => return Err(Error::io(e, dir)),
+    }
+    let meta = std::fs::symlink_metadata(dir).map_err(|e| Error::io(e, dir))?;
+    // SAFETY: getuid has no preconditions and cannot fail.
+    let uid = unsafe { libc::getuid() };
+    if !meta.is_dir() || meta.uid() != uid || meta.mode() & 0o077 != 0 {
+        return Err(Error::new(
+            ErrorCode::PermissionDenied,
+            format!(
+                "{} must be a directory owned by uid {uid} with mode 0700",
+                dir.display()
+            ),
+        )
+        .with_path(dir));
+    }
+    Ok(())
+}
+
+/// The per-user directory in the temp dir that holds the default socket
+/// when `$XDG_RUNTIME_DIR` is not set. `serve` creates it mode 0700.
+#[cfg(unix)]
+fn private_socket_dir() -> Option<PathBuf> {
+    if std::env::var_os("XDG_RUNTIME_DIR").is_some_and(|dir| !dir.is_empty()) {
+        return None;
+    }
+    // SAFETY: getuid has no preconditions and cannot fail.
+    let uid = unsafe { libc::getuid() };
+    Some(std::env::temp_dir().join(format!("turbo-composer-{uid}")))
+}
+
+#[cfg(not(unix))]
+fn private_socket_dir() -> Option<PathBuf> {
+    Some(std::env::temp_dir().join("turbo-composer"))
+}
+
+/// Default socket location when none is given: `$XDG_RUNTIME_DIR`, which
+/// only its user can enter, or else a private per-user directory in the
+/// temp dir.
+pub(crate) fn default_socket_path() -> PathBuf {
+    match std::env::var_os("XDG_RUNTIME_DIR").filter(|dir| !dir.is_empty()) {
+        Some(dir) => Path::new(&dir).join("turbo-composer.sock"),
+        None => private_socket_dir()
+            .unwrap_or_else(std::env::temp_dir)
+            .join("turbo-composer.sock"),
+    }
+}
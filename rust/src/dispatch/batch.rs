@@ -0,0 +1,327 @@
+//! The `batch` command: several operations in one invocation.
+//!
+//! Each operation may carry an `id` and a `depends_on` list of ids. Operations
+//! whose dependencies have finished run concurrently. An operation without
+//! `depends_on` depends on the one before it, so a plain list keeps running
+//! strictly in order; `"depends_on": []` marks it as independent.
+//!
+//! With `"transactional": true` the batch runs inside a [`Transaction`]
+//! rooted at `vendor_dir`: cleans and extractions are staged, and only
+//! swapped into vendor/ if every operation succeeds.
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::mpsc;
+use std::time::Instant;
+
+use crate::limits::ResourceLimits;
+use crate::transaction::Transaction;
+use crate::Error;
+
+use super::{dispatch_in, Caches, Input};
+
+/// What happens to the rest of the batch when an operation fails, i.e.
+/// returns an error or reports per-item failures in its `failed` list.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OnError {
+    /// Start no further operations; those not yet started are skipped.
+    Abort,
+    /// Run everything regardless.
+    #[default]
+    Continue,
+    /// Skip operations that depend, directly or transitively, on a failed one.
+    SkipDependents,
+}
+
+struct Operation {
+    id: Option<String>,
+    command: String,
+    deps: Vec<usize>,
+    /// `Err` when the operation itself could not be parsed.
+    input: Option<Result<Input, Error>>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Pending,
+    Running,
+    Ok,
+    Failed,
+    Skipped,
+}
+
+/// Run the batch in a transaction under `root`, committing only if every
+/// operation succeeded. The result gains `"transaction": "committed"` or
+/// `"rolled_back"`.
+pub(crate) fn run_transactional(
+    operations: Vec<serde_json::Value>,
+    on_error: OnError,
+    limits: ResourceLimits,
+    root: &Path,
+    caches: Option<&Caches>,
+) -> Result<serde_json::Value, Error> {
+    let transaction = Transaction::begin(root)?;
+    let mut result = match run(
+        operations,
+        on_error,
+        limits,
+        false,
+        caches,
+        Some(&transaction),
+    ) {
+        Ok(result) => result,
+        Err(e) => {
+            transaction.rollback()?;
+            return Err(e);
+        }
+    };
+
+    let succeeded = result["results"]
+        .as_array()
+        .is_some_and(|entries| entries.iter().all(|e| e["status"] == "ok"));
+    let outcome = if succeeded {
+        transaction.commit()?;
+        "committed"
+    } else {
+        transaction.rollback()?;
+        "rolled_back"
+    };
+    result["transaction"] = outcome.into();
+    Ok(result)
+}
+
+/// Operations inherit any of `limits` they do not set themselves, and
+/// `dry_run` if it is set on the batch.
+pub(crate) fn run(
+    operations: Vec<serde_json::Value>,
+    on_error: OnError,
+    limits: ResourceLimits,
+    dry_run: bool,
+    caches: Option<&Caches>,
+    transaction: Option<&Transaction>,
+) -> Result<serde_json::Value, Error> {
+    let start = Instant::now();
+    let mut ops = plan(operations, limits, dry_run)?;
+    let n = ops.len();
+
+    let mut status = vec![Status::Pending; n];
+    let mut entries: Vec<serde_json::Value> = vec![serde_json::Value::Null; n];
+    let mut aborted = false;
+
+    std::thread::scope(|scope| {
+        let (tx, rx) = mpsc::channel::<(usize, Result<serde_json::Value, Error>, u128, u128)>();
+        let mut running = 0;
+
+        loop {
+            // Start (or skip) everything whose dependencies are settled. Skips
+            // can settle further operations, so repeat until nothing changes.
+            let mut progressed = true;
+            while progressed {
+                progressed = false;
+                for i in 0..n {
+                    if status[i] != Status::Pending {
+                        continue;
+                    }
+                    let deps = &ops[i].deps;
+                    if deps
+                        .iter()
+                        .any(|&d| matches!(status[d], Status::Pending | Status::Running))
+                    {
+                        continue;
+                    }
+                    progressed = true;
+
+                    let failed_dep = deps
+                        .iter()
+                        .find(|&&d| matches!(status[d], Status::Failed | Status::Skipped));
+                    let skip_reason = if aborted {
+                        Some("batch aborted after a failure".to_string())
+                    } else if let (OnError::SkipDependents, Some(&d)) = (on_error, failed_dep) {
+                        Some(format!("dependency {} did not succeed", label(&ops[d], d)))
+                    } else {
+                        None
+                    };
+                    if let Some(reason) = skip_reason {
+                        status[i] = Status::Skipped;
+                        entries[i] = entry(&ops[i], "skipped", |e| {
+                            e.insert("reason".to_string(), reason.into());
+                        });
+                        continue;
+                    }
+
+                    status[i] = Status::Running;
+                    running += 1;
+                    let input = ops[i].input.take().expect("operation started twice");
+                    let tx = tx.clone();
+                    scope.spawn(move || {
+                        let started_ms = start.elapsed().as_millis();
+                        let op_start = Instant::now();
+                        let result =
+                            input.and_then(|input| dispatch_in(input, caches, transaction));
+                        let _ = tx.send((i, result, started_ms, op_start.elapsed().as_millis()));
+                    });
+                }
+            }
+
+            if running == 0 {
+                break;
+            }
+
+            let Ok((i, result, started_ms, elapsed_ms)) = rx.recv() else {
+                break;
+            };
+            running -= 1;
+
+            let failed = match &result {
+                Ok(output) => output["failed"].as_array().is_some_and(|f| !f.is_empty()),
+                Err(_) => true,
+            };
+            status[i] = if failed { Status::Failed } else { Status::Ok };
+            if failed && on_error == OnError::Abort {
+                aborted = true;
+            }
+            entries[i] = entry(&ops[i], if failed { "failed" } else { "ok" }, |e| {
+                match result {
+                    Ok(output) => e.insert("result".to_string(), output),
+                    Err(error) => e.insert("error".to_string(), serde_json::json!(error)),
+                };
+                e.insert("started_ms".to_string(), serde_json::json!(started_ms));
+                e.insert("elapsed_ms".to_string(), serde_json::json!(elapsed_ms));
+            });
+        }
+    });
+
+    Ok(serde_json::json!({
+        "results": entries,
+        "aborted": aborted,
+        "elapsed_ms": start.elapsed().as_millis() as u64,
+    }))
+}
+
+/// Parse every operation and resolve `depends_on` ids to indices, rejecting
+/// duplicate ids, unknown ids and cycles up front.
+fn plan(
+    operations: Vec<serde_json::Value>,
+    limits: ResourceLimits,
+    dry_run: bool,
+) -> Result<Vec<Operation>, Error> {
+    let mut ops: Vec<Operation> = Vec::with_capacity(operations.len());
+    let mut by_id: HashMap<String, usize> = HashMap::new();
+    let mut dep_ids: Vec<Option<Vec<String>>> = Vec::with_capacity(operations.len());
+
+    for (i, mut op) in operations.into_iter().enumerate() {
+        let command = op["command"].as_str().unwrap_or("").to_string();
+        let id = match op.get("id") {
+            None | Some(serde_json::Value::Null) => None,
+            Some(serde_json::Value::String(id)) => Some(id.clone()),
+            Some(other) => {
+                return Err(Error::invalid_request(format!(
+                    "operation {i}: id must be a string, got {other}"
+                )))
+            }
+        };
+        if let Some(ref id) = id {
+            if by_id.insert(id.clone(), i).is_some() {
+                return Err(Error::invalid_request(format!(
+                    "duplicate operation id: {id}"
+                )));
+            }
+        }
+        let depends_on = match op.as_object_mut().and_then(|o| o.remove("depends_on")) {
+            None => None,
+            Some(value) => Some(serde_json::from_value::<Vec<String>>(value).map_err(|e| {
+                Error::invalid_request(format!("operation {i}: invalid depends_on: {e}"))
+            })?),
+        };
+        dep_ids.push(depends_on);
+
+        let input = serde_json::from_value::<Input>(op)
+            .map(|mut input| {
+                input.limits = input.limits.or(limits);
+                input.dry_run |= dry_run;
+                input
+            })
+            .map_err(|e| {
+                Error::invalid_request(format!("failed to parse operation: {e}"))
+                    .in_operation(&command)
+            });
+        ops.push(Operation {
+            id,
+            command,
+            deps: Vec::new(),
+            input: Some(input),
+        });
+    }
+
+    for (i, ids) in dep_ids.into_iter().enumerate() {
+        ops[i].deps = match ids {
+            None if i > 0 => vec![i - 1],
+            None => Vec::new(),
+            Some(ids) => ids
+                .iter()
+                .map(|id| {
+                    by_id.get(id).copied().ok_or_else(|| {
+                        Error::invalid_request(format!(
+                            "{} depends on unknown operation id: {id}",
+                            label(&ops[i], i)
+                        ))
+                    })
+                })
+                .collect::<Result<_, _>>()?,
+        };
+    }
+
+    if let Some(i) = find_cycle(&ops) {
+        return Err(Error::invalid_request(format!(
+            "dependency cycle involving {}",
+            label(&ops[i], i)
+        )));
+    }
+
+    Ok(ops)
+}
+
+/// Kahn's algorithm: any operation that never becomes ready is on a cycle.
+fn find_cycle(ops: &[Operation]) -> Option<usize> {
+    let mut remaining: Vec<usize> = ops.iter().map(|op| op.deps.len()).collect();
+    let mut ready: Vec<usize> = (0..ops.len()).filter(|&i| remaining[i] == 0).collect();
+    let mut done = vec![false; ops.len()];
+    while let Some(i) = ready.pop() {
+        done[i] = true;
+        for (j, op) in ops.iter().enumerate() {
+            for &d in &op.deps {
+                if d == i {
+                    remaining[j] -= 1;
+                    if remaining[j] == 0 {
+                        ready.push(j);
+                    }
+                }
+            }
+        }
+    }
+    done.iter().position(|&d| !d)
+}
+
+fn label(op: &Operation, index: usize) -> String {
+    match op.id {
+        Some(ref id) => format!("operation {id}"),
+        None => format!("operation {index}"),
+    }
+}
+
+fn entry(
+    op: &Operation,
+    status: &str,
+    fill: impl FnOnce(&mut serde_json::Map<String, serde_json::Value>),
+) -> serde_json::Value {
+    let mut e = serde_json::Map::new();
+    if let Some(ref id) = op.id {
+        e.insert("id".to_string(), id.clone().into());
+    }
+    e.insert("command".to_string(), op.command.clone().into());
+    e.insert("status".to_string(), status.into());
+    fill(&mut e);
+    serde_json::Value::Object(e)
+}
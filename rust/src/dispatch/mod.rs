@@ -0,0 +1,457 @@
+//! Request dispatch: the protocol shared by stdin, `serve`, `batch` and the
+//! C ABI. An [`Input`] is one decoded request; [`dispatch`] runs it and
+//! returns the JSON response body, or an [`Error`] for the error envelope.
+
+mod batch;
+
+use serde::Deserialize;
+use std::sync::Arc;
+
+use crate::encoding;
+use crate::limits::{Limiter, ResourceLimits};
+use crate::trace::Trace;
+use crate::transaction::{self, Transaction};
+use crate::{classmap, clean, extract, vendor_state, verify, Error, ErrorCode};
+
+pub use batch::OnError;
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Version of the request/response protocol. Bump it when a change would make
+/// an older plugin misread a response or a newer plugin's request misbehave on
+/// this binary; additive fields don't need a bump.
+pub const PROTOCOL_VERSION: u32 = 1;
+/// Oldest protocol version this binary still accepts.
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+/// Commands accepted by `dispatch`, as reported by `capabilities`.
+pub const COMMANDS: &[&str] = &[
+    "classmap",
+    "extract",
+    "verify",
+    "clean",
+    "vendor-check",
+    "batch",
+    "recover",
+    "capabilities",
+];
+
+/// Request fields the `classmap` command honours, as reported by
+/// `capabilities` so the plugin only sends options this binary understands.
+pub const CLASSMAP_OPTIONS: &[&str] = &[
+    "project_dir",
+    "vendor_dir",
+    "autoload",
+    "exclude_from_classmap",
+    "target_dir",
+    "suffix",
+    "write_files",
+    "staging_suffix",
+    "has_platform_check",
+    "has_files_autoload",
+    "from_composer_json",
+    "trace_file",
+    "dry_run",
+    "threads",
+    "io_concurrency",
+    "memory_budget_mb",
+    "strict_ambiguous",
+    "strict_psr",
+    "authoritative",
+    "apcu_autoloader",
+    "apcu_prefix",
+    "prepend_autoloader",
+    "root_package",
+    "runtime_dir",
+    "platform_check",
+    "ignore_platform_reqs",
+    "dev_mode",
+    "scan_psr_packages",
+];
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Input {
+    pub command: String,
+    /// Protocol the sender speaks. Omitted by older plugins, which are
+    /// treated as speaking the current version.
+    #[serde(default)]
+    pub protocol_version: Option<u32>,
+    /// Write a Chrome trace of this request's phases to this path.
+    #[serde(default)]
+    pub trace_file: Option<String>,
+    /// Recorder for `trace_file`; disabled when no trace was asked for.
+    #[serde(skip)]
+    pub trace: Arc<Trace>,
+    /// `threads`, `io_concurrency` and `memory_budget_mb`.
+    #[serde(flatten)]
+    pub limits: ResourceLimits,
+    /// Report what the command would do without touching the filesystem.
+    /// `verify`, `vendor-check` and `capabilities` are read-only anyway.
+    #[serde(default)]
+    pub dry_run: bool,
+
+    #[serde(default)]
+    pub packages: Vec<extract::PackageExtraction>,
+
+    #[serde(default)]
+    pub targets: Vec<clean::CleanTarget>,
+
+    #[serde(default)]
+    pub verify_targets: Vec<verify::VerifyTarget>,
+
+    #[serde(default)]
+    pub check_packages: Vec<vendor_state::PackageCheck>,
+
+    #[serde(default)]
+    pub project_dir: Option<String>,
+    #[serde(default)]
+    pub vendor_dir: Option<String>,
+    #[serde(default)]
+    pub autoload: Option<classmap::AutoloadMappings>,
+    #[serde(default)]
+    pub exclude_from_classmap: Vec<String>,
+    #[serde(default)]
+    pub target_dir: Option<String>,
+    #[serde(default)]
+    pub suffix: Option<String>,
+    #[serde(default = "default_true")]
+    pub write_files: bool,
+    #[serde(default)]
+    pub staging_suffix: Option<String>,
+    #[serde(default)]
+    pub has_platform_check: bool,
+    #[serde(default)]
+    pub has_files_autoload: bool,
+    /// Resolve `autoload` from composer.json + vendor/composer/installed.json
+    /// instead of taking it from the request.
+    #[serde(default)]
+    pub from_composer_json: bool,
+    /// Fail the classmap if any class is declared by more than one file.
+    #[serde(default)]
+    pub strict_ambiguous: bool,
+    /// Fail the classmap if any class does not match its psr-4/psr-0 path.
+    #[serde(default)]
+    pub strict_psr: bool,
+    /// Generate a loader that trusts the classmap alone.
+    #[serde(default)]
+    pub authoritative: bool,
+    /// Cache class lookups in APCu, under `apcu_prefix` if given.
+    #[serde(default)]
+    pub apcu_autoloader: bool,
+    /// Unset means a random prefix, or composer.json's
+    /// `config.apcu-autoloader-prefix` with `from_composer_json`.
+    #[serde(default)]
+    pub apcu_prefix: Option<String>,
+    /// Unset means prepend, or composer.json's `config.prepend-autoloader`
+    /// with `from_composer_json`.
+    #[serde(default)]
+    pub prepend_autoloader: Option<bool>,
+    /// Write `installed.php` for this root package. Read from composer.json
+    /// with `from_composer_json`.
+    #[serde(default)]
+    pub root_package: Option<classmap::RootPackage>,
+    /// Copy `ClassLoader.php`, `InstalledVersions.php` and `LICENSE` from
+    /// this `src/Composer` directory.
+    #[serde(default)]
+    pub runtime_dir: Option<String>,
+    /// Generate platform_check.php: `true`, `false` or `"php-only"`. Read
+    /// from composer.json's `config.platform-check` with `from_composer_json`.
+    #[serde(default)]
+    pub platform_check: Option<classmap::PlatformCheck>,
+    #[serde(default)]
+    pub ignore_platform_reqs: Vec<String>,
+    /// Include mappings flagged `dev`; `false` for `--no-dev`.
+    #[serde(default = "default_true")]
+    pub dev_mode: bool,
+    /// Scan psr-4/psr-0 roots too; `false` for a non-optimised dump.
+    #[serde(default = "default_true")]
+    pub scan_psr_packages: bool,
+
+    // Batch command: array of sub-operations
+    #[serde(default, deserialize_with = "encoding::values")]
+    pub operations: Vec<serde_json::Value>,
+    #[serde(default)]
+    pub on_error: batch::OnError,
+    /// Run the batch as one journaled transaction rooted at `vendor_dir`.
+    #[serde(default)]
+    pub transactional: bool,
+}
+
+impl Input {
+    /// An empty request for `command`, with the same defaults as a JSON
+    /// request that only sets `command`.
+    pub fn new(command: &str) -> Self {
+        Input {
+            command: command.to_string(),
+            protocol_version: None,
+            trace_file: None,
+            trace: Arc::default(),
+            limits: ResourceLimits::default(),
+            dry_run: false,
+            packages: Vec::new(),
+            targets: Vec::new(),
+            verify_targets: Vec::new(),
+            check_packages: Vec::new(),
+            project_dir: None,
+            vendor_dir: None,
+            autoload: None,
+            exclude_from_classmap: Vec::new(),
+            target_dir: None,
+            suffix: None,
+            write_files: true,
+            staging_suffix: None,
+            has_platform_check: false,
+            has_files_autoload: false,
+            from_composer_json: false,
+            strict_ambiguous: false,
+            strict_psr: false,
+            authoritative: false,
+            apcu_autoloader: false,
+            apcu_prefix: None,
+            prepend_autoloader: None,
+            root_package: None,
+            runtime_dir: None,
+            platform_check: None,
+            ignore_platform_reqs: Vec::new(),
+            dev_mode: true,
+            scan_psr_packages: true,
+            operations: Vec::new(),
+            on_error: batch::OnError::default(),
+            transactional: false,
+        }
+    }
+}
+
+/// Shared classmap caches, present when running as a daemon.
+pub type Caches = std::sync::Mutex<classmap::CacheStore>;
+
+/// Run one request. Failures come back as an [`Error`] tagged with the
+/// command; a panic inside a command is reported as an `internal` error
+/// instead of tearing down the process (or, in `serve`, the connection).
+pub fn dispatch(input: Input, caches: Option<&Caches>) -> Result<serde_json::Value, Error> {
+    dispatch_in(input, caches, None)
+}
+
+/// [`dispatch`] as part of a transactional batch: `clean` and `extract`
+/// stage their changes in `transaction` instead of applying them.
+pub(crate) fn dispatch_in(
+    input: Input,
+    caches: Option<&Caches>,
+    transaction: Option<&Transaction>,
+) -> Result<serde_json::Value, Error> {
+    let mut input = input;
+    let command = input.command.clone();
+    check_protocol(input.protocol_version).map_err(|e| e.in_operation(&command))?;
+    if input.trace_file.is_some() && !input.trace.is_enabled() {
+        input.trace = Arc::new(Trace::new());
+    }
+    let trace = input.trace.clone();
+    let trace_file = input.trace_file.clone();
+
+    let span = trace
+        .span("command", "dispatch")
+        .arg("command", command.as_str());
+    let limits = input.limits;
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        limits.install(|| dispatch_command(input, caches, transaction))
+    }))
+    .unwrap_or_else(|panic| {
+        let message = panic
+            .downcast_ref::<&str>()
+            .map(|s| s.to_string())
+            .or_else(|| panic.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "unknown panic".to_string());
+        Err(Error::new(ErrorCode::Internal, message))
+    });
+    drop(span);
+
+    if let Some(path) = trace_file {
+        // A trace is a diagnostic aid; failing to write one must not turn a
+        // successful request into a failed one.
+        if let Err(e) = trace.write(std::path::Path::new(&path)) {
+            eprintln!("turbo-composer: failed to write trace: {e}");
+        }
+    }
+    result.map_err(|e| e.in_operation(&command))
+}
+
+fn dispatch_command(
+    input: Input,
+    caches: Option<&Caches>,
+    transaction: Option<&Transaction>,
+) -> Result<serde_json::Value, Error> {
+    let limiter = Limiter::new(&input.limits);
+    match input.command.as_str() {
+        "extract" if input.dry_run => Ok(extract::dry_run(input.packages)),
+        "clean" if input.dry_run => Ok(clean::dry_run(input.targets)),
+        "extract" => Ok(extract::run_in(input.packages, transaction, &limiter)),
+        "clean" => Ok(clean::run_in(input.targets, transaction, &limiter)),
+        "verify" => Ok(verify::run_with(input.verify_targets, &limiter)),
+        "vendor-check" => Ok(vendor_state::run(input.check_packages)),
+        "classmap" => {
+            let config = classmap_config(input)?;
+            match caches {
+                Some(caches) => classmap::run_with_store(config, caches),
+                None => classmap::run(config),
+            }
+        }
+        // A dry run changes nothing, so there is nothing to journal.
+        "batch" if input.transactional && !input.dry_run => {
+            if transaction.is_some() {
+                return Err(Error::invalid_request(
+                    "transactional batches cannot be nested",
+                ));
+            }
+            let root = input.vendor_dir.ok_or_else(|| {
+                Error::invalid_request("a transactional batch requires vendor_dir")
+            })?;
+            batch::run_transactional(
+                input.operations,
+                input.on_error,
+                input.limits,
+                std::path::Path::new(&root),
+                caches,
+            )
+        }
+        "batch" => batch::run(
+            input.operations,
+            input.on_error,
+            input.limits,
+            input.dry_run,
+            caches,
+            transaction,
+        ),
+        "recover" => {
+            let root = input
+                .vendor_dir
+                .ok_or_else(|| Error::invalid_request("recover requires vendor_dir"))?;
+            let root = std::path::Path::new(&root);
+            if input.dry_run {
+                let pending = transaction::inspect(root)?;
+                return Ok(serde_json::json!({ "dry_run": true, "would_recover": pending }));
+            }
+            let recovery = transaction::recover(root)?;
+            Ok(serde_json::json!({ "recovered": recovery }))
+        }
+        "capabilities" => Ok(capabilities()),
+        other => Err(Error::new(
+            ErrorCode::UnknownCommand,
+            format!("unknown command: {other}"),
+        )),
+    }
+}
+
+fn check_protocol(requested: Option<u32>) -> Result<(), Error> {
+    match requested {
+        Some(v) if !(MIN_PROTOCOL_VERSION..=PROTOCOL_VERSION).contains(&v) => Err(Error::new(
+            ErrorCode::IncompatibleProtocol,
+            format!(
+                "request uses protocol version {v}, but turbo-composer {VERSION} supports \
+                 versions {MIN_PROTOCOL_VERSION} to {PROTOCOL_VERSION}"
+            ),
+        )),
+        _ => Ok(()),
+    }
+}
+
+fn capabilities() -> serde_json::Value {
+    serde_json::json!({
+        "version": VERSION,
+        "protocol_version": PROTOCOL_VERSION,
+        "min_protocol_version": MIN_PROTOCOL_VERSION,
+        "commands": COMMANDS,
+        "archive_formats": extract::ARCHIVE_FORMATS,
+        "hash_algorithms": verify::HASH_ALGORITHMS,
+        "classmap_options": CLASSMAP_OPTIONS,
+        "encodings": encoding::ENCODINGS,
+    })
+}
+
+/// Tag a top-level response with the protocol version it was written in.
+pub fn with_protocol(mut response: serde_json::Value) -> serde_json::Value {
+    if let Some(obj) = response.as_object_mut() {
+        obj.insert("protocol_version".to_string(), PROTOCOL_VERSION.into());
+    }
+    response
+}
+
+pub fn classmap_config(input: Input) -> Result<classmap::ClassmapConfig, Error> {
+    if !input.from_composer_json {
+        return Ok(classmap::ClassmapConfig {
+            project_dir: input.project_dir.unwrap_or_default(),
+            vendor_dir: input.vendor_dir.unwrap_or_default(),
+            autoload: input.autoload.unwrap_or_default(),
+            exclude_from_classmap: input.exclude_from_classmap,
+            target_dir: input.target_dir,
+            suffix: input.suffix,
+            write_files: input.write_files,
+            staging_suffix: input.staging_suffix,
+            has_platform_check: input.has_platform_check,
+            has_files_autoload: input.has_files_autoload,
+            trace: input.trace,
+            limits: Arc::new(Limiter::new(&input.limits)),
+            dry_run: input.dry_run,
+            strict_ambiguous: input.strict_ambiguous,
+            strict_psr: input.strict_psr,
+            authoritative: input.authoritative,
+            apcu_autoloader: input.apcu_autoloader,
+            apcu_prefix: input.apcu_prefix,
+            prepend_autoloader: input.prepend_autoloader.unwrap_or(true),
+            root_package: input.root_package,
+            runtime_dir: input.runtime_dir,
+            platform_check: input.platform_check,
+            ignore_platform_reqs: input.ignore_platform_reqs,
+            dev_mode: input.dev_mode,
+            scan_psr_packages: input.scan_psr_packages,
+        });
+    }
+
+    // Everything PHP's buildPayload() would send is derived from the project;
+    // fields set explicitly on the request still take precedence.
+    let project =
+        classmap::load_project(input.project_dir.as_deref().unwrap_or("."), input.dev_mode)?;
+    let vendor_dir = input.vendor_dir.unwrap_or(project.vendor_dir);
+    let target_dir = input
+        .target_dir
+        .unwrap_or_else(|| format!("{vendor_dir}/composer"));
+    let has_platform_check = input.has_platform_check
+        || std::path::Path::new(&target_dir)
+            .join("platform_check.php")
+            .exists();
+    let mut exclude_from_classmap = project.exclude_from_classmap;
+    exclude_from_classmap.extend(input.exclude_from_classmap);
+
+    Ok(classmap::ClassmapConfig {
+        project_dir: project.project_dir,
+        vendor_dir,
+        has_files_autoload: !project.autoload.files.is_empty(),
+        autoload: project.autoload,
+        exclude_from_classmap,
+        target_dir: Some(target_dir),
+        suffix: input.suffix.or(Some(project.suffix)),
+        write_files: input.write_files,
+        staging_suffix: input.staging_suffix,
+        has_platform_check,
+        trace: input.trace,
+        limits: Arc::new(Limiter::new(&input.limits)),
+        dry_run: input.dry_run,
+        strict_ambiguous: input.strict_ambiguous,
+        strict_psr: input.strict_psr,
+        authoritative: input.authoritative || project.classmap_authoritative,
+        apcu_autoloader: input.apcu_autoloader || project.apcu_autoloader,
+        apcu_prefix: input.apcu_prefix.or(project.apcu_prefix),
+        prepend_autoloader: input
+            .prepend_autoloader
+            .unwrap_or(project.prepend_autoloader),
+        root_package: input.root_package.or(Some(project.root_package)),
+        runtime_dir: input.runtime_dir.or(project.runtime_dir),
+        platform_check: input.platform_check.or(Some(project.platform_check)),
+        ignore_platform_reqs: input.ignore_platform_reqs,
+        dev_mode: input.dev_mode,
+        scan_psr_packages: input.scan_psr_packages,
+    })
+}
@@ -0,0 +1,349 @@
+//! Wire encodings for requests and responses.
+//!
+//! JSON is the default. MessagePack carries the same document without JSON's
+//! string escaping, which matters when a classmap response holds several
+//! megabytes of PHP source or a batch lists thousands of archives, and a
+//! request can carry raw byte strings.
+//!
+//! A request's encoding is recognised from its first byte: a JSON request
+//! starts with `{` (after optional whitespace), a MessagePack request with a
+//! map marker (`0x80`–`0x8f`, `0xde` or `0xdf`), which can never start a JSON
+//! document. The response is written in the request's encoding.
+//!
+//! Filesystem paths in requests go through [`path`], which accepts a string,
+//! a MessagePack `bin` value or an array of bytes, so paths that are not valid
+//! UTF-8 survive the round trip on Unix. Responses are built as a JSON
+//! document before they are encoded, so such paths come back as an array of
+//! byte values in either encoding, never as a `bin`.
+
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+use crate::error::Error;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Encoding {
+    #[default]
+    Json,
+    #[serde(alias = "messagepack")]
+    Msgpack,
+}
+
+/// Encodings this binary reads and writes, as reported by `capabilities`.
+pub const ENCODINGS: &[&str] = &["json", "msgpack"];
+
+impl Encoding {
+    /// Recognise the encoding of a request from its first significant byte.
+    /// Anything that is not a MessagePack map is treated as JSON, so a
+    /// malformed request still gets a JSON parse error.
+    pub fn detect(bytes: &[u8]) -> Self {
+        match bytes.iter().find(|b| !b.is_ascii_whitespace()) {
+            Some(0x80..=0x8f | 0xde | 0xdf) => Encoding::Msgpack,
+            _ => Encoding::Json,
+        }
+    }
+
+    pub fn decode<T: DeserializeOwned>(self, bytes: &[u8]) -> Result<T, Error> {
+        match self {
+            Encoding::Json => serde_json::from_slice(bytes)
+                .map_err(|e| Error::invalid_request(format!("failed to parse input JSON: {e}"))),
+            Encoding::Msgpack => rmp_serde::from_slice(bytes).map_err(|e| {
+                Error::invalid_request(format!("failed to parse input MessagePack: {e}"))
+            }),
+        }
+    }
+
+    /// Encode a response. MessagePack output mirrors the JSON document, so
+    /// it holds no `bin` values.
+    pub fn encode(self, value: &serde_json::Value) -> Vec<u8> {
+        match self {
+            Encoding::Json => serde_json::to_vec(value).expect("failed to serialise output"),
+            Encoding::Msgpack => {
+                rmp_serde::to_vec_named(value).expect("failed to serialise output")
+            }
+        }
+    }
+}
+
+/// Serde helpers for filesystem paths that may not be valid UTF-8.
+///
+/// Deserializes from a string, a byte string, or a sequence of bytes.
+/// Serializes as a string when the path is valid UTF-8, and as bytes
+/// otherwise. Responses pass through `serde_json::Value`, which turns those
+/// bytes into an array of numbers, so that is what both JSON and MessagePack
+/// responses carry.
+pub mod path {
+    use serde::de::{self, SeqAccess, Visitor};
+    use serde::{Deserializer, Serializer};
+    use std::fmt;
+    use std::path::{Path, PathBuf};
+
+    pub fn serialize<S: Serializer>(path: &Path, serializer: S) -> Result<S::Ok, S::Error> {
+        match path.to_str() {
+            Some(s) => serializer.serialize_str(s),
+            None => serializer.serialize_bytes(&to_bytes(path)),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<PathBuf, D::Error> {
+        deserializer.deserialize_any(PathVisitor)
+    }
+
+    struct PathVisitor;
+
+    impl<'de> Visitor<'de> for PathVisitor {
+        type Value = PathBuf;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a path as a string or bytes")
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<PathBuf, E> {
+            Ok(PathBuf::from(v))
+        }
+
+        fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<PathBuf, E> {
+            from_bytes(v.to_vec()).map_err(E::custom)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<PathBuf, A::Error> {
+            let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+            while let Some(b) = seq.next_element::<u8>()? {
+                bytes.push(b);
+            }
+            from_bytes(bytes).map_err(de::Error::custom)
+        }
+    }
+
+    #[cfg(unix)]
+    fn from_bytes(bytes: Vec<u8>) -> Result<PathBuf, String> {
+        use std::os::unix::ffi::OsStringExt;
+        Ok(PathBuf::from(std::ffi::OsString::from_vec(bytes)))
+    }
+
+    #[cfg(not(unix))]
+    fn from_bytes(bytes: Vec<u8>) -> Result<PathBuf, String> {
+        String::from_utf8(bytes)
+            .map(PathBuf::from)
+            .map_err(|_| "paths must be valid UTF-8 on this platform".to_string())
+    }
+
+    #[cfg(unix)]
+    fn to_bytes(path: &Path) -> Vec<u8> {
+        use std::os::unix::ffi::OsStrExt;
+        path.as_os_str().as_bytes().to_vec()
+    }
+
+    #[cfg(not(unix))]
+    fn to_bytes(path: &Path) -> Vec<u8> {
+        path.to_string_lossy().into_owned().into_bytes()
+    }
+}
+
+/// Deserialize an untyped value like `serde_json::Value` does, except that a
+/// byte string (a MessagePack `bin`) becomes an array of numbers instead of an
+/// error. Used where a request embeds further requests, e.g. batch
+/// operations, so their paths can still be deserialized with [`path`].
+pub fn values<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<serde_json::Value>, D::Error> {
+    let values = Vec::<AnyValue>::deserialize(deserializer)?;
+    Ok(values.into_iter().map(|v| v.0).collect())
+}
+
+/// Deserialize a string map, also accepting an empty sequence: PHP has no
+/// empty map distinct from an empty list, so `[]` arrives as an array in
+/// both encodings unless the caller cast it to an object.
+pub fn string_map<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<std::collections::BTreeMap<String, String>, D::Error> {
+    deserializer.deserialize_any(StringMapVisitor)
+}
+
+struct StringMapVisitor;
+
+impl<'de> serde::de::Visitor<'de> for StringMapVisitor {
+    type Value = std::collections::BTreeMap<String, String>;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("a map of strings")
+    }
+
+    fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        match seq.next_element::<serde::de::IgnoredAny>()? {
+            None => Ok(Self::Value::new()),
+            Some(_) => Err(serde::de::Error::invalid_type(
+                serde::de::Unexpected::Seq,
+                &self,
+            )),
+        }
+    }
+
+    fn visit_map<A: serde::de::MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut out = Self::Value::new();
+        while let Some((key, value)) = map.next_entry()? {
+            out.insert(key, value);
+        }
+        Ok(out)
+    }
+}
+
+struct AnyValue(serde_json::Value);
+
+impl<'de> Deserialize<'de> for AnyValue {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(AnyValueVisitor).map(AnyValue)
+    }
+}
+
+struct AnyValueVisitor;
+
+impl<'de> serde::de::Visitor<'de> for AnyValueVisitor {
+    type Value = serde_json::Value;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("any value")
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<Self::Value, E> {
+        Ok(v.into())
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E> {
+        Ok(v.into())
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E> {
+        Ok(v.into())
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<Self::Value, E> {
+        Ok(v.into())
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E> {
+        Ok(v.into())
+    }
+
+    fn visit_string<E>(self, v: String) -> Result<Self::Value, E> {
+        Ok(v.into())
+    }
+
+    fn visit_bytes<E>(self, v: &[u8]) -> Result<Self::Value, E> {
+        Ok(v.iter().copied().map(serde_json::Value::from).collect())
+    }
+
+    fn visit_none<E>(self) -> Result<Self::Value, E> {
+        Ok(serde_json::Value::Null)
+    }
+
+    fn visit_unit<E>(self) -> Result<Self::Value, E> {
+        Ok(serde_json::Value::Null)
+    }
+
+    fn visit_some<D: serde::Deserializer<'de>>(self, d: D) -> Result<Self::Value, D::Error> {
+        AnyValue::deserialize(d).map(|v| v.0)
+    }
+
+    fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut items = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(AnyValue(item)) = seq.next_element()? {
+            items.push(item);
+        }
+        Ok(serde_json::Value::Array(items))
+    }
+
+    fn visit_map<A: serde::de::MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+        let mut object = serde_json::Map::new();
+        while let Some((key, AnyValue(value))) = map.next_entry::<String, AnyValue>()? {
+            object.insert(key, value);
+        }
+        Ok(serde_json::Value::Object(object))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Serialize;
+    use std::path::PathBuf;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Target {
+        #[serde(with = "path")]
+        path: PathBuf,
+    }
+
+    #[test]
+    fn detects_encoding_from_first_byte() {
+        assert_eq!(Encoding::detect(b"  {\"command\":1}"), Encoding::Json);
+        assert_eq!(Encoding::detect(b""), Encoding::Json);
+        let msgpack = Encoding::Msgpack.encode(&serde_json::json!({"command": "clean"}));
+        assert_eq!(Encoding::detect(&msgpack), Encoding::Msgpack);
+    }
+
+    #[test]
+    fn msgpack_round_trips_json_values() {
+        let value = serde_json::json!({"a": [1, "two", null, true], "b": {"c": 1.5}});
+        let bytes = Encoding::Msgpack.encode(&value);
+        let back: serde_json::Value = Encoding::Msgpack.decode(&bytes).unwrap();
+        assert_eq!(back, value);
+    }
+
+    #[test]
+    fn string_maps_accept_an_empty_php_array() {
+        #[derive(Deserialize)]
+        struct Links {
+            #[serde(deserialize_with = "string_map")]
+            require: std::collections::BTreeMap<String, String>,
+            #[serde(deserialize_with = "string_map")]
+            provide: std::collections::BTreeMap<String, String>,
+        }
+        let value = serde_json::json!({"require": {"php": "^8.1"}, "provide": []});
+        for encoding in [Encoding::Json, Encoding::Msgpack] {
+            let links: Links = encoding.decode(&encoding.encode(&value)).unwrap();
+            assert_eq!(links.require["php"], "^8.1");
+            assert!(links.provide.is_empty());
+        }
+
+        let list = serde_json::json!({"require": ["php"], "provide": []});
+        assert!(Encoding::Msgpack
+            .decode::<Links>(&Encoding::Msgpack.encode(&list))
+            .is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn non_utf8_paths_survive_both_encodings() {
+        use std::os::unix::ffi::OsStringExt;
+        let raw = b"/tmp/caf\xe9/pkg".to_vec();
+        let target = Target {
+            path: PathBuf::from(std::ffi::OsString::from_vec(raw)),
+        };
+
+        let bytes = rmp_serde::to_vec_named(&target).unwrap();
+        assert_eq!(Encoding::Msgpack.decode::<Target>(&bytes).unwrap(), target);
+
+        let json = serde_json::to_vec(&target).unwrap();
+        assert_eq!(Encoding::Json.decode::<Target>(&json).unwrap(), target);
+
+        // Through untyped values, as batch operations are.
+        #[derive(Serialize)]
+        struct BatchOut<'a> {
+            operations: [&'a Target; 1],
+        }
+        #[derive(Deserialize)]
+        struct BatchIn {
+            #[serde(deserialize_with = "values")]
+            operations: Vec<serde_json::Value>,
+        }
+        let bytes = rmp_serde::to_vec_named(&BatchOut {
+            operations: [&target],
+        })
+        .unwrap();
+        let batch: BatchIn = Encoding::Msgpack.decode(&bytes).unwrap();
+        let op: Target = serde_json::from_value(batch.operations[0].clone()).unwrap();
+        assert_eq!(op, target);
+    }
+}
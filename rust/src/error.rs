@@ -0,0 +1,229 @@
+//! Errors that abort a whole request.
+//!
+//! Per-item failures (one archive that does not extract, one checksum that
+//! does not match) are reported inside a command's result. An [`Error`] is
+//! for everything else, and is what callers see as the `error` object of a
+//! failed response:
+//!
+//! ```json
+//! {"error": {"code": "disk_full", "message": "...", "path": "...", "operation": "classmap"}}
+//! ```
+//!
+//! Each [`ErrorCode`] has its own process exit code so callers that only see
+//! the exit status can still tell failure classes apart.
+
+use serde::{Serialize, Serializer};
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// New failure classes may be added, so matches need a wildcard arm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+#[non_exhaustive]
+pub enum ErrorCode {
+    /// The request could not be read or decoded, or a field is invalid.
+    InvalidRequest,
+    /// The request names a command this binary does not know.
+    UnknownCommand,
+    /// The request's `protocol_version` is outside the range this binary speaks.
+    IncompatibleProtocol,
+    /// A file or directory the request depends on does not exist.
+    NotFound,
+    /// The OS refused access, or the filesystem is read-only.
+    PermissionDenied,
+    /// No space left on the device, or a disk quota was exceeded.
+    DiskFull,
+    /// Any other I/O failure.
+    Io,
+    /// A bug: the command panicked.
+    Internal,
+    /// `strict_ambiguous` was set and a class is declared by more than one file.
+    AmbiguousClass,
+    /// `strict_psr` was set and a class does not match its psr-4/psr-0 path.
+    PsrViolation,
+}
+
+impl ErrorCode {
+    /// Process exit status for this failure class. 0 is success, 1 is left
+    /// for unclassified failures and 2 for command-line usage errors.
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorCode::InvalidRequest => 3,
+            ErrorCode::UnknownCommand => 4,
+            ErrorCode::NotFound => 5,
+            ErrorCode::PermissionDenied => 6,
+            ErrorCode::DiskFull => 7,
+            ErrorCode::Io => 8,
+            ErrorCode::Internal => 9,
+            ErrorCode::IncompatibleProtocol => 10,
+            ErrorCode::AmbiguousClass => 11,
+            ErrorCode::PsrViolation => 12,
+        }
+    }
+
+    pub(crate) fn from_io(error: &io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::NotFound => ErrorCode::NotFound,
+            io::ErrorKind::PermissionDenied | io::ErrorKind::ReadOnlyFilesystem => {
+                ErrorCode::PermissionDenied
+            }
+            io::ErrorKind::StorageFull | io::ErrorKind::QuotaExceeded => ErrorCode::DiskFull,
+            _ => ErrorCode::Io,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Error {
+    pub code: ErrorCode,
+    pub message: String,
+    /// The file or directory involved, when there is one. The response
+    /// envelope carries it as a (lossy) UTF-8 string.
+    #[serde(serialize_with = "lossy_path")]
+    pub path: Option<PathBuf>,
+    /// The command that failed, e.g. `classmap`.
+    pub operation: Option<String>,
+    /// The I/O error this was classified from, exposed as `source()`.
+    #[serde(skip)]
+    source: Option<Arc<io::Error>>,
+}
+
+fn lossy_path<S: Serializer>(path: &Option<PathBuf>, serializer: S) -> Result<S::Ok, S::Error> {
+    path.as_deref()
+        .map(Path::to_string_lossy)
+        .serialize(serializer)
+}
+
+impl Error {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        Error {
+            code,
+            message: message.into(),
+            path: None,
+            operation: None,
+            source: None,
+        }
+    }
+
+    pub fn invalid_request(message: impl Into<String>) -> Self {
+        Self::new(ErrorCode::InvalidRequest, message)
+    }
+
+    /// Classify an I/O error on `path`.
+    pub fn io(error: io::Error, path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        Error {
+            code: ErrorCode::from_io(&error),
+            message: format!("{}: {error}", path.display()),
+            path: Some(path.to_path_buf()),
+            operation: None,
+            source: Some(Arc::new(error)),
+        }
+    }
+
+    pub fn with_path(mut self, path: impl AsRef<Path>) -> Self {
+        self.path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Record the failing command, unless a more specific one is already set.
+    pub fn in_operation(mut self, operation: &str) -> Self {
+        self.operation.get_or_insert_with(|| operation.to_string());
+        self
+    }
+
+    /// The `{"error": {...}}` response object.
+    pub fn to_response(&self) -> serde_json::Value {
+        serde_json::json!({ "error": self })
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.source.as_deref().map(|e| e as _)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn io_errors_are_classified_by_kind() {
+        let cases = [
+            (io::ErrorKind::NotFound, ErrorCode::NotFound),
+            (io::ErrorKind::PermissionDenied, ErrorCode::PermissionDenied),
+            (
+                io::ErrorKind::ReadOnlyFilesystem,
+                ErrorCode::PermissionDenied,
+            ),
+            (io::ErrorKind::StorageFull, ErrorCode::DiskFull),
+            (io::ErrorKind::QuotaExceeded, ErrorCode::DiskFull),
+            (io::ErrorKind::InvalidData, ErrorCode::Io),
+        ];
+        for (kind, code) in cases {
+            let error = Error::io(io::Error::from(kind), "/tmp/x");
+            assert_eq!(error.code, code, "{kind:?}");
+            assert_eq!(error.path.as_deref(), Some(Path::new("/tmp/x")));
+        }
+    }
+
+    #[test]
+    fn io_errors_keep_their_source() {
+        use std::error::Error as _;
+
+        let error = Error::io(io::Error::other("disk on fire"), "/tmp/x");
+        let source = error.source().expect("source");
+        assert_eq!(source.to_string(), "disk on fire");
+        assert!(Error::invalid_request("bad").source().is_none());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn response_path_is_lossy_utf8() {
+        use std::ffi::OsStr;
+        use std::os::unix::ffi::OsStrExt;
+
+        let error = Error::invalid_request("bad").with_path(OsStr::from_bytes(b"/tmp/caf\xe9"));
+        assert_eq!(error.to_response()["error"]["path"], "/tmp/caf\u{fffd}");
+    }
+
+    #[test]
+    fn exit_codes_are_distinct() {
+        let codes = [
+            ErrorCode::InvalidRequest,
+            ErrorCode::UnknownCommand,
+            ErrorCode::IncompatibleProtocol,
+            ErrorCode::NotFound,
+            ErrorCode::PermissionDenied,
+            ErrorCode::DiskFull,
+            ErrorCode::Io,
+            ErrorCode::Internal,
+            ErrorCode::AmbiguousClass,
+            ErrorCode::PsrViolation,
+        ];
+        let mut exits: Vec<i32> = codes.iter().map(|c| c.exit_code()).collect();
+        exits.sort_unstable();
+        exits.dedup();
+        assert_eq!(exits.len(), codes.len());
+        assert!(exits.iter().all(|&e| e > 2));
+    }
+
+    #[test]
+    fn response_envelope_has_all_fields() {
+        let error = Error::invalid_request("bad").in_operation("classmap");
+        let response = error.to_response();
+        assert_eq!(response["error"]["code"], "invalid_request");
+        assert_eq!(response["error"]["message"], "bad");
+        assert!(response["error"]["path"].is_null());
+        assert_eq!(response["error"]["operation"], "classmap");
+    }
+}
@@ -3,65 +3,276 @@ use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::io::Cursor;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
 
-#[derive(Debug, Deserialize)]
+use crate::limits::Limiter;
+use crate::transaction::Transaction;
+
+/// Archive types `extract` understands, by file extension.
+pub const ARCHIVE_FORMATS: &[&str] = &["zip", "tar", "tar.gz", "tgz"];
+
+#[derive(Debug, Clone, Deserialize)]
 pub struct PackageExtraction {
-    pub zip: String,
-    pub dest: String,
+    /// The archive; its extension selects the format (see [`ARCHIVE_FORMATS`]).
+    #[serde(with = "crate::encoding::path")]
+    pub zip: PathBuf,
+    #[serde(with = "crate::encoding::path")]
+    pub dest: PathBuf,
     pub name: String,
 }
 
-#[derive(Debug, Serialize)]
-struct ExtractionResult {
-    extracted: usize,
-    failed: Vec<FailedExtraction>,
-    total_files: usize,
-    elapsed_ms: u128,
+#[derive(Debug, Clone, Serialize)]
+pub struct ExtractionResult {
+    pub extracted: usize,
+    pub failed: Vec<FailedExtraction>,
+    pub total_files: usize,
+    pub elapsed_ms: u128,
 }
 
-#[derive(Debug, Serialize)]
-struct FailedExtraction {
-    name: String,
-    error: String,
+#[derive(Debug, Clone, Serialize)]
+pub struct FailedExtraction {
+    pub name: String,
+    pub error: String,
 }
 
 pub fn run(packages: Vec<PackageExtraction>) -> serde_json::Value {
+    run_in(packages, None, &Limiter::default())
+}
+
+/// Like [`run`], but inside a transaction each package is unpacked into a
+/// staging directory and only replaces `dest` when the transaction commits.
+/// Archives are read and files written under `limiter`'s limits.
+pub fn run_in(
+    packages: Vec<PackageExtraction>,
+    transaction: Option<&Transaction>,
+    limiter: &Limiter,
+) -> serde_json::Value {
+    serde_json::to_value(extract(&packages, transaction, limiter)).unwrap()
+}
+
+/// Extract every package in parallel. The typed form of [`run_in`].
+pub fn extract(
+    packages: &[PackageExtraction],
+    transaction: Option<&Transaction>,
+    limiter: &Limiter,
+) -> ExtractionResult {
     let start = std::time::Instant::now();
     let total_files = AtomicUsize::new(0);
     let extracted = AtomicUsize::new(0);
 
-    let failed: Vec<FailedExtraction> = packages
+    // Archives are mapped whole, so their size is what a package costs.
+    let chunks = limiter.budget_chunks(packages, |pkg| {
+        fs::metadata(&pkg.zip).map(|m| m.len()).unwrap_or(0)
+    });
+    let failed: Vec<FailedExtraction> = chunks
+        .into_iter()
+        .flat_map(|chunk| {
+            chunk
+                .par_iter()
+                .filter_map(
+                    |pkg| match extract_one(pkg, transaction, limiter, &total_files) {
+                        Ok(()) => {
+                            extracted.fetch_add(1, Ordering::Relaxed);
+                            None
+                        }
+                        Err(e) => Some(FailedExtraction {
+                            name: pkg.name.clone(),
+                            error: e.to_string(),
+                        }),
+                    },
+                )
+                .collect::<Vec<_>>()
+        })
+        .collect();
+
+    ExtractionResult {
+        extracted: extracted.load(Ordering::Relaxed),
+        failed,
+        total_files: total_files.load(Ordering::Relaxed),
+        elapsed_ms: start.elapsed().as_millis(),
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ExtractionPlan {
+    /// Always `true`; marks the response as a dry run.
+    pub dry_run: bool,
+    pub packages: Vec<PackagePlan>,
+    pub failed: Vec<FailedExtraction>,
+    pub total_files: usize,
+    pub elapsed_ms: u128,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PackagePlan {
+    pub name: String,
+    #[serde(with = "crate::encoding::path")]
+    pub dest: PathBuf,
+    /// Files the archive would write, relative to `dest`.
+    pub files: Vec<PlannedFile>,
+    pub created: usize,
+    pub overwritten: usize,
+    /// Files now in `dest` that the archive does not contain. Extraction
+    /// replaces `dest` as a whole, so these would be deleted.
+    pub removed: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PlannedFile {
+    pub path: String,
+    pub action: FileAction,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileAction {
+    Create,
+    Overwrite,
+}
+
+/// Report, per package, which files [`run`] would create, overwrite and
+/// delete, without writing anything.
+pub fn dry_run(packages: Vec<PackageExtraction>) -> serde_json::Value {
+    serde_json::to_value(plan(&packages)).unwrap()
+}
+
+/// The typed form of [`dry_run`].
+pub fn plan(packages: &[PackageExtraction]) -> ExtractionPlan {
+    let start = std::time::Instant::now();
+
+    let results: Vec<Result<PackagePlan, FailedExtraction>> = packages
         .par_iter()
-        .filter_map(|pkg| match extract_one(pkg, &total_files) {
-            Ok(()) => {
-                extracted.fetch_add(1, Ordering::Relaxed);
-                None
-            }
-            Err(e) => Some(FailedExtraction {
+        .map(|pkg| {
+            plan_one(pkg).map_err(|e| FailedExtraction {
                 name: pkg.name.clone(),
                 error: e.to_string(),
-            }),
+            })
         })
         .collect();
 
-    let result = ExtractionResult {
-        extracted: extracted.load(Ordering::Relaxed),
+    let mut planned = Vec::new();
+    let mut failed = Vec::new();
+    for result in results {
+        match result {
+            Ok(plan) => planned.push(plan),
+            Err(failure) => failed.push(failure),
+        }
+    }
+
+    ExtractionPlan {
+        dry_run: true,
+        total_files: planned.iter().map(|p| p.files.len()).sum(),
+        packages: planned,
         failed,
-        total_files: total_files.load(Ordering::Relaxed),
         elapsed_ms: start.elapsed().as_millis(),
+    }
+}
+
+fn plan_one(
+    pkg: &PackageExtraction,
+) -> Result<PackagePlan, Box<dyn std::error::Error + Send + Sync>> {
+    let zip_path = Path::new(&pkg.zip);
+    let entries: Vec<String> = match zip_path.extension().and_then(|e| e.to_str()).unwrap_or("") {
+        "zip" => {
+            let file = fs::File::open(zip_path)?;
+            let mmap = unsafe { Mmap::map(&file)? };
+            let mut archive = zip::ZipArchive::new(Cursor::new(&mmap[..]))?;
+            zip_layout(&mut archive)?
+                .files
+                .into_iter()
+                .map(|(relative, _)| relative)
+                .collect()
+        }
+        "gz" | "tgz" => {
+            let decoder = flate2::read::GzDecoder::new(fs::File::open(zip_path)?);
+            tar_file_names(decoder)?
+        }
+        "tar" => tar_file_names(fs::File::open(zip_path)?)?,
+        other => return Err(format!("unsupported archive format: {other}").into()),
     };
 
-    serde_json::to_value(result).unwrap()
+    let mut existing = std::collections::BTreeSet::new();
+    let dest = Path::new(&pkg.dest);
+    if dest.is_dir() {
+        collect_relative_files(dest, "", &mut existing)?;
+    }
+
+    let mut files = Vec::with_capacity(entries.len());
+    let (mut created, mut overwritten) = (0, 0);
+    for path in entries {
+        let action = if existing.remove(&path) {
+            overwritten += 1;
+            FileAction::Overwrite
+        } else {
+            created += 1;
+            FileAction::Create
+        };
+        files.push(PlannedFile { path, action });
+    }
+
+    Ok(PackagePlan {
+        name: pkg.name.clone(),
+        dest: pkg.dest.clone(),
+        files,
+        created,
+        overwritten,
+        removed: existing.into_iter().collect(),
+    })
+}
+
+/// Paths of the regular entries of a tar stream, as `extract_tar_archive`
+/// would write them relative to `dest`.
+fn tar_file_names<R: std::io::Read>(
+    reader: R,
+) -> Result<Vec<String>, Box<dyn std::error::Error + Send + Sync>> {
+    let mut names = Vec::new();
+    let mut seen = std::collections::HashSet::new();
+    for entry in tar::Archive::new(reader).entries()? {
+        let entry = entry?;
+        if entry.header().entry_type().is_dir() {
+            continue;
+        }
+        let name = entry.path()?.to_string_lossy().into_owned();
+        if seen.insert(name.clone()) {
+            names.push(name);
+        }
+    }
+    Ok(names)
+}
+
+fn collect_relative_files(
+    dir: &Path,
+    prefix: &str,
+    out: &mut std::collections::BTreeSet<String>,
+) -> std::io::Result<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = format!("{prefix}{}", entry.file_name().to_string_lossy());
+        if entry.file_type()?.is_dir() {
+            collect_relative_files(&entry.path(), &format!("{name}/"), out)?;
+        } else {
+            out.insert(name);
+        }
+    }
+    Ok(())
 }
 
 fn extract_one(
     pkg: &PackageExtraction,
+    transaction: Option<&Transaction>,
+    limiter: &Limiter,
     total_files: &AtomicUsize,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let zip_path = Path::new(&pkg.zip);
-    let dest = Path::new(&pkg.dest);
+    let staged;
+    let dest = match transaction {
+        Some(tx) => {
+            staged = tx.stage(Path::new(&pkg.dest))?;
+            staged.as_path()
+        }
+        None => Path::new(&pkg.dest),
+    };
 
     if dest.exists() {
         fs::remove_dir_all(dest)?;
@@ -69,9 +280,9 @@ fn extract_one(
     fs::create_dir_all(dest)?;
 
     match zip_path.extension().and_then(|e| e.to_str()).unwrap_or("") {
-        "zip" => extract_zip(zip_path, dest, total_files),
-        "gz" | "tgz" => extract_tar_gz(zip_path, dest, total_files),
-        "tar" => extract_tar(zip_path, dest, total_files),
+        "zip" => extract_zip(zip_path, dest, limiter, total_files),
+        "gz" | "tgz" => extract_tar_gz(zip_path, dest, limiter, total_files),
+        "tar" => extract_tar(zip_path, dest, limiter, total_files),
         other => Err(format!("unsupported archive format: {other}").into()),
     }
 }
@@ -79,9 +290,10 @@ fn extract_one(
 fn extract_zip(
     zip_path: &Path,
     dest: &Path,
+    limiter: &Limiter,
     total_files: &AtomicUsize,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let file = fs::File::open(zip_path)?;
+    let file = limiter.io(|| fs::File::open(zip_path))?;
     let mmap = unsafe { Mmap::map(&file)? };
     let reader = Cursor::new(&mmap[..]);
     let mut archive = zip::ZipArchive::new(reader)?;
@@ -89,8 +301,67 @@ fn extract_zip(
     let count = archive.len();
     total_files.fetch_add(count, Ordering::Relaxed);
 
-    let strip = detect_strip_prefix(&mut archive);
+    let ZipLayout {
+        files: file_entries,
+        dirs: dirs_to_create,
+    } = zip_layout(&mut archive)?;
+    for dir in &dirs_to_create {
+        fs::create_dir_all(dest.join(dir))?;
+    }
+
+    let mmap_ref: &[u8] = &mmap;
+
+    let num_threads = rayon::current_num_threads().max(1);
+    let chunk_size = file_entries.len().div_ceil(num_threads).max(1);
+
+    file_entries.par_chunks(chunk_size).try_for_each(
+        |chunk| -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+            let cursor = Cursor::new(mmap_ref);
+            let mut arch = zip::ZipArchive::new(cursor)?;
 
+            for (relative, idx) in chunk {
+                let out_path = dest.join(relative);
+
+                if let Some(parent) = out_path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+
+                let mut zip_entry = arch.by_index(*idx)?;
+                limiter.io(|| {
+                    let mut outfile = fs::File::create(&out_path)?;
+                    std::io::copy(&mut zip_entry, &mut outfile)
+                })?;
+
+                #[cfg(unix)]
+                {
+                    use std::os::unix::fs::PermissionsExt;
+                    if let Some(mode) = zip_entry.unix_mode() {
+                        fs::set_permissions(&out_path, fs::Permissions::from_mode(mode))?;
+                    }
+                }
+            }
+
+            Ok(())
+        },
+    )?;
+
+    Ok(())
+}
+
+/// Where each entry of a zip lands relative to `dest`: the common top-level
+/// directory (see [`detect_strip_prefix`]) is stripped, and for paths that
+/// occur more than once only the last entry is kept.
+struct ZipLayout {
+    /// Relative path and archive index of every file to write.
+    files: Vec<(String, usize)>,
+    /// Relative directories to create, sorted, each ending in `/`.
+    dirs: Vec<String>,
+}
+
+fn zip_layout(archive: &mut zip::ZipArchive<Cursor<&[u8]>>) -> zip::result::ZipResult<ZipLayout> {
+    let strip = detect_strip_prefix(archive);
+
+    let count = archive.len();
     let mut file_entries: Vec<(String, usize)> = Vec::with_capacity(count);
     let mut dirs_to_create: Vec<String> = Vec::new();
 
@@ -151,45 +422,11 @@ fn extract_zip(
 
     dirs_to_create.sort();
     dirs_to_create.dedup();
-    for dir in &dirs_to_create {
-        fs::create_dir_all(dest.join(dir))?;
-    }
-
-    let mmap_ref: &[u8] = &mmap;
-
-    let num_threads = rayon::current_num_threads().max(1);
-    let chunk_size = file_entries.len().div_ceil(num_threads).max(1);
-
-    file_entries.par_chunks(chunk_size).try_for_each(
-        |chunk| -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-            let cursor = Cursor::new(mmap_ref);
-            let mut arch = zip::ZipArchive::new(cursor)?;
-
-            for (relative, idx) in chunk {
-                let out_path = dest.join(relative);
-
-                if let Some(parent) = out_path.parent() {
-                    fs::create_dir_all(parent)?;
-                }
-
-                let mut zip_entry = arch.by_index(*idx)?;
-                let mut outfile = fs::File::create(&out_path)?;
-                std::io::copy(&mut zip_entry, &mut outfile)?;
-
-                #[cfg(unix)]
-                {
-                    use std::os::unix::fs::PermissionsExt;
-                    if let Some(mode) = zip_entry.unix_mode() {
-                        fs::set_permissions(&out_path, fs::Permissions::from_mode(mode))?;
-                    }
-                }
-            }
-
-            Ok(())
-        },
-    )?;
 
-    Ok(())
+    Ok(ZipLayout {
+        files: file_entries,
+        dirs: dirs_to_create,
+    })
 }
 
 fn detect_strip_prefix(archive: &mut zip::ZipArchive<Cursor<&[u8]>>) -> Option<String> {
@@ -219,25 +456,28 @@ fn detect_strip_prefix(archive: &mut zip::ZipArchive<Cursor<&[u8]>>) -> Option<S
 fn extract_tar_gz(
     path: &Path,
     dest: &Path,
+    limiter: &Limiter,
     total_files: &AtomicUsize,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let file = fs::File::open(path)?;
+    let file = limiter.io(|| fs::File::open(path))?;
     let decoder = flate2::read::GzDecoder::new(file);
-    extract_tar_archive(decoder, dest, total_files)
+    extract_tar_archive(decoder, dest, limiter, total_files)
 }
 
 fn extract_tar(
     path: &Path,
     dest: &Path,
+    limiter: &Limiter,
     total_files: &AtomicUsize,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let file = fs::File::open(path)?;
-    extract_tar_archive(file, dest, total_files)
+    let file = limiter.io(|| fs::File::open(path))?;
+    extract_tar_archive(file, dest, limiter, total_files)
 }
 
 fn extract_tar_archive<R: std::io::Read>(
     reader: R,
     dest: &Path,
+    limiter: &Limiter,
     total_files: &AtomicUsize,
 ) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
     let mut archive = tar::Archive::new(reader);
@@ -254,7 +494,7 @@ fn extract_tar_archive<R: std::io::Read>(
             if let Some(parent) = out.parent() {
                 fs::create_dir_all(parent)?;
             }
-            entry.unpack(&out)?;
+            limiter.io(|| entry.unpack(&out))?;
             count += 1;
         }
     }
@@ -269,7 +509,7 @@ mod tests {
     use std::io::Write;
     use tempfile::TempDir;
 
-    fn create_test_zip(dir: &Path, name: &str, files: &[(&str, &[u8])]) -> String {
+    fn create_test_zip(dir: &Path, name: &str, files: &[(&str, &[u8])]) -> PathBuf {
         let zip_path = dir.join(name);
         let file = fs::File::create(&zip_path).unwrap();
         let mut zip_writer = zip::ZipWriter::new(file);
@@ -282,10 +522,10 @@ mod tests {
         }
 
         zip_writer.finish().unwrap();
-        zip_path.to_string_lossy().to_string()
+        zip_path
     }
 
-    fn create_test_tar(dir: &Path, name: &str, files: &[(&str, &[u8])]) -> String {
+    fn create_test_tar(dir: &Path, name: &str, files: &[(&str, &[u8])]) -> PathBuf {
         let tar_path = dir.join(name);
         let file = fs::File::create(&tar_path).unwrap();
         let mut builder = tar::Builder::new(file);
@@ -301,10 +541,10 @@ mod tests {
         }
 
         builder.finish().unwrap();
-        tar_path.to_string_lossy().to_string()
+        tar_path
     }
 
-    fn create_test_tar_gz(dir: &Path, name: &str, files: &[(&str, &[u8])]) -> String {
+    fn create_test_tar_gz(dir: &Path, name: &str, files: &[(&str, &[u8])]) -> PathBuf {
         let tar_gz_path = dir.join(name);
         let file = fs::File::create(&tar_gz_path).unwrap();
         let encoder = flate2::write::GzEncoder::new(file, flate2::Compression::fast());
@@ -321,7 +561,7 @@ mod tests {
         }
 
         builder.into_inner().unwrap().finish().unwrap();
-        tar_gz_path.to_string_lossy().to_string()
+        tar_gz_path
     }
 
     #[test]
@@ -342,7 +582,7 @@ mod tests {
 
         let packages = vec![PackageExtraction {
             zip: zip_path,
-            dest: dest_dir.to_string_lossy().to_string(),
+            dest: dest_dir.clone(),
             name: "test/package".to_string(),
         }];
 
@@ -379,7 +619,7 @@ mod tests {
 
         let packages = vec![PackageExtraction {
             zip: zip_path,
-            dest: dest_dir.to_string_lossy().to_string(),
+            dest: dest_dir.clone(),
             name: "vendor/pkg".to_string(),
         }];
 
@@ -399,7 +639,7 @@ mod tests {
     /// Create a zip with duplicate filenames by merging two archives.
     /// The zip crate's writer rejects duplicates, but `merge_archive`
     /// copies entries verbatim, allowing the same name to appear twice.
-    fn create_zip_with_duplicate_entries(dir: &Path, name: &str) -> String {
+    fn create_zip_with_duplicate_entries(dir: &Path, name: &str) -> PathBuf {
         let zip_path = dir.join(name);
 
         // First archive: config.txt with "first version"
@@ -427,7 +667,7 @@ mod tests {
         merged.merge_archive(archive2).unwrap();
         merged.finish().unwrap();
 
-        zip_path.to_string_lossy().to_string()
+        zip_path
     }
 
     #[test]
@@ -441,7 +681,7 @@ mod tests {
 
         let packages = vec![PackageExtraction {
             zip: zip_path,
-            dest: dest_dir.to_string_lossy().to_string(),
+            dest: dest_dir.clone(),
             name: "test/dupe-pkg".to_string(),
         }];
 
@@ -478,7 +718,7 @@ mod tests {
 
         let packages = vec![PackageExtraction {
             zip: tar_path,
-            dest: dest_dir.to_string_lossy().to_string(),
+            dest: dest_dir.clone(),
             name: "test/tar-pkg".to_string(),
         }];
 
@@ -511,7 +751,7 @@ mod tests {
 
         let packages = vec![PackageExtraction {
             zip: tar_gz_path,
-            dest: dest_dir.to_string_lossy().to_string(),
+            dest: dest_dir.clone(),
             name: "test/targz-pkg".to_string(),
         }];
 
@@ -553,12 +793,12 @@ mod tests {
         let packages = vec![
             PackageExtraction {
                 zip: zip1,
-                dest: dest1.to_string_lossy().to_string(),
+                dest: dest1.clone(),
                 name: "vendor/pkg1".to_string(),
             },
             PackageExtraction {
                 zip: zip2,
-                dest: dest2.to_string_lossy().to_string(),
+                dest: dest2.clone(),
                 name: "vendor/pkg2".to_string(),
             },
         ];
@@ -577,8 +817,8 @@ mod tests {
         let dest_dir = tmp.path().join("output");
 
         let packages = vec![PackageExtraction {
-            zip: "/nonexistent/archive.zip".to_string(),
-            dest: dest_dir.to_string_lossy().to_string(),
+            zip: "/nonexistent/archive.zip".into(),
+            dest: dest_dir.clone(),
             name: "broken/pkg".to_string(),
         }];
 
@@ -599,8 +839,8 @@ mod tests {
         let dest_dir = tmp.path().join("output");
 
         let packages = vec![PackageExtraction {
-            zip: bad_file.to_string_lossy().to_string(),
-            dest: dest_dir.to_string_lossy().to_string(),
+            zip: bad_file.clone(),
+            dest: dest_dir.clone(),
             name: "bad/format".to_string(),
         }];
 
@@ -636,7 +876,7 @@ mod tests {
 
         let packages = vec![PackageExtraction {
             zip: zip_path,
-            dest: dest_dir.to_string_lossy().to_string(),
+            dest: dest_dir.clone(),
             name: "test/overwrite".to_string(),
         }];
 
@@ -650,6 +890,80 @@ mod tests {
         );
     }
 
+    #[test]
+    fn extract_in_transaction_replaces_destination_on_commit() {
+        let tmp = TempDir::new().unwrap();
+        let archives_dir = tmp.path().join("archives");
+        fs::create_dir_all(&archives_dir).unwrap();
+
+        let dest_dir = tmp.path().join("output");
+        fs::create_dir_all(&dest_dir).unwrap();
+        fs::write(dest_dir.join("old_file.txt"), "old").unwrap();
+
+        let zip_path = create_test_zip(&archives_dir, "fresh.zip", &[("new_file.txt", b"new")]);
+        let packages = vec![PackageExtraction {
+            zip: zip_path,
+            dest: dest_dir.clone(),
+            name: "test/tx".to_string(),
+        }];
+
+        let tx = Transaction::begin(tmp.path()).unwrap();
+        let result = run_in(packages, Some(&tx), &Limiter::default());
+        assert_eq!(result["extracted"].as_u64().unwrap(), 1);
+        // Nothing visible changes until commit.
+        assert!(dest_dir.join("old_file.txt").exists());
+        assert!(!dest_dir.join("new_file.txt").exists());
+
+        tx.commit().unwrap();
+        assert!(!dest_dir.join("old_file.txt").exists());
+        assert_eq!(
+            fs::read_to_string(dest_dir.join("new_file.txt")).unwrap(),
+            "new"
+        );
+    }
+
+    #[test]
+    fn dry_run_lists_created_overwritten_and_removed_files() {
+        let tmp = TempDir::new().unwrap();
+        let archives_dir = tmp.path().join("archives");
+        fs::create_dir_all(&archives_dir).unwrap();
+
+        let dest_dir = tmp.path().join("output");
+        fs::create_dir_all(dest_dir.join("src")).unwrap();
+        fs::write(dest_dir.join("src/Foo.php"), "old").unwrap();
+        fs::write(dest_dir.join("stale.txt"), "old").unwrap();
+
+        let zip_path = create_test_zip(
+            &archives_dir,
+            "pkg.zip",
+            &[
+                ("pkg-1.0/src/Foo.php", b"new"),
+                ("pkg-1.0/README.md", b"new"),
+            ],
+        );
+
+        let result = dry_run(vec![PackageExtraction {
+            zip: zip_path,
+            dest: dest_dir.clone(),
+            name: "test/dry".to_string(),
+        }]);
+        assert_eq!(result["dry_run"], true);
+        let pkg = &result["packages"][0];
+        assert_eq!(pkg["created"], 1);
+        assert_eq!(pkg["overwritten"], 1);
+        assert_eq!(pkg["files"][0]["path"], "src/Foo.php");
+        assert_eq!(pkg["files"][0]["action"], "overwrite");
+        assert_eq!(pkg["files"][1]["path"], "README.md");
+        assert_eq!(pkg["files"][1]["action"], "create");
+        assert_eq!(pkg["removed"], serde_json::json!(["stale.txt"]));
+
+        assert_eq!(
+            fs::read_to_string(dest_dir.join("src/Foo.php")).unwrap(),
+            "old"
+        );
+        assert!(!dest_dir.join("README.md").exists());
+    }
+
     #[test]
     fn extract_empty_packages_list() {
         let result = run(vec![]);
@@ -676,12 +990,12 @@ mod tests {
         let packages = vec![
             PackageExtraction {
                 zip: good_zip,
-                dest: good_dest.to_string_lossy().to_string(),
+                dest: good_dest.clone(),
                 name: "good/pkg".to_string(),
             },
             PackageExtraction {
-                zip: "/nonexistent.zip".to_string(),
-                dest: bad_dest.to_string_lossy().to_string(),
+                zip: "/nonexistent.zip".into(),
+                dest: bad_dest.clone(),
                 name: "bad/pkg".to_string(),
             },
         ];
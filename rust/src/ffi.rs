@@ -0,0 +1,139 @@
+//! C ABI for calling the engine in-process, e.g. through PHP's FFI extension,
+//! without spawning the binary for every request.
+//!
+//! ```c
+//! typedef struct { uint8_t *ptr; size_t len; } turbo_buffer;
+//!
+//! turbo_buffer turbo_dispatch(const char *request, size_t len);
+//! void turbo_free(turbo_buffer buffer);
+//! ```
+//!
+//! `turbo_dispatch` takes one request exactly as the binary reads it on stdin
+//! (JSON or MessagePack) and returns the response the binary would write to
+//! stdout, in the same encoding: the command's result, or the `error`
+//! envelope if the request failed as a whole. The buffer is owned by the
+//! caller and must be released with `turbo_free`. It is not NUL-terminated.
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::dispatch::{self, Input};
+use crate::encoding::Encoding;
+use crate::trace::Trace;
+use crate::{Error, ErrorCode};
+
+/// A response allocated by [`turbo_dispatch`].
+#[repr(C)]
+pub struct TurboBuffer {
+    pub ptr: *mut u8,
+    pub len: usize,
+}
+
+impl TurboBuffer {
+    fn from_vec(bytes: Vec<u8>) -> Self {
+        let mut bytes = bytes.into_boxed_slice();
+        let buffer = TurboBuffer {
+            ptr: bytes.as_mut_ptr(),
+            len: bytes.len(),
+        };
+        std::mem::forget(bytes);
+        buffer
+    }
+}
+
+/// Run one request and return its encoded response.
+///
+/// # Safety
+///
+/// `request` must point to `len` readable bytes, or be null with `len` 0.
+/// The returned buffer must be passed to [`turbo_free`] exactly once.
+#[no_mangle]
+pub unsafe extern "C" fn turbo_dispatch(
+    request: *const std::os::raw::c_char,
+    len: usize,
+) -> TurboBuffer {
+    let bytes: &[u8] = if request.is_null() || len == 0 {
+        &[]
+    } else {
+        // SAFETY: the caller guarantees `request` points to `len` bytes.
+        unsafe { std::slice::from_raw_parts(request.cast::<u8>(), len) }
+    };
+    let encoding = Encoding::detect(bytes);
+
+    // `dispatch` already turns a panicking command into an error; this guards
+    // decoding and encoding, since unwinding into C is undefined behaviour.
+    let response = std::panic::catch_unwind(|| run(bytes, encoding)).unwrap_or_else(|_| {
+        let error = Error::new(ErrorCode::Internal, "panic while handling request");
+        encoding.encode(&dispatch::with_protocol(error.to_response()))
+    });
+    TurboBuffer::from_vec(response)
+}
+
+/// Release a buffer returned by [`turbo_dispatch`].
+///
+/// # Safety
+///
+/// `buffer` must come from [`turbo_dispatch`] and not have been freed yet.
+#[no_mangle]
+pub unsafe extern "C" fn turbo_free(buffer: TurboBuffer) {
+    if buffer.ptr.is_null() {
+        return;
+    }
+    // SAFETY: `ptr` and `len` describe the boxed slice leaked by `from_vec`.
+    drop(unsafe { Box::from_raw(std::ptr::slice_from_raw_parts_mut(buffer.ptr, buffer.len)) });
+}
+
+fn run(bytes: &[u8], encoding: Encoding) -> Vec<u8> {
+    let parse_start = Instant::now();
+    let response = encoding
+        .decode::<Input>(bytes)
+        .and_then(|mut input| {
+            if input.trace_file.is_some() {
+                let trace = Trace::starting_at(parse_start);
+                trace.record("json", "json_parse", parse_start, Instant::now());
+                input.trace = Arc::new(trace);
+            }
+            dispatch::dispatch(input, None)
+        })
+        .unwrap_or_else(|e| e.to_response());
+    encoding.encode(&dispatch::with_protocol(response))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn call(request: &[u8]) -> Vec<u8> {
+        unsafe {
+            let buffer = turbo_dispatch(request.as_ptr().cast(), request.len());
+            let bytes = std::slice::from_raw_parts(buffer.ptr, buffer.len).to_vec();
+            turbo_free(buffer);
+            bytes
+        }
+    }
+
+    #[test]
+    fn answers_json_requests() {
+        let response: serde_json::Value =
+            serde_json::from_slice(&call(br#"{"command":"capabilities"}"#)).unwrap();
+        assert_eq!(response["version"], dispatch::VERSION);
+        assert_eq!(response["protocol_version"], dispatch::PROTOCOL_VERSION);
+    }
+
+    #[test]
+    fn answers_msgpack_requests_in_msgpack() {
+        let request = Encoding::Msgpack.encode(&serde_json::json!({"command": "capabilities"}));
+        let response: serde_json::Value = Encoding::Msgpack.decode(&call(&request)).unwrap();
+        assert!(response["commands"].is_array());
+    }
+
+    #[test]
+    fn failures_return_the_error_envelope() {
+        let response: serde_json::Value =
+            serde_json::from_slice(&call(br#"{"command":"nope"}"#)).unwrap();
+        assert_eq!(response["error"]["code"], "unknown_command");
+
+        let response: serde_json::Value = serde_json::from_slice(&call(b"")).unwrap();
+        assert_eq!(response["error"]["code"], "invalid_request");
+    }
+}
@@ -1,5 +1,56 @@
+//! The turbo-composer engine as a library.
+//!
+//! Each command the binary accepts is a module with a typed entry point and a
+//! JSON adapter on top of it:
+//!
+//! - `classmap`: [`classmap::dump`], JSON via [`classmap::run`]
+//! - `extract`: [`extract::extract`] and [`extract::plan`], JSON via
+//!   [`extract::run_in`] and [`extract::dry_run`]
+//! - `verify`: [`verify::verify`], JSON via [`verify::run_with`]
+//! - `clean`: [`clean::remove`] and [`clean::plan`], JSON via
+//!   [`clean::run_in`] and [`clean::dry_run`]
+//! - `vendor-check`: [`vendor_state::check`], JSON via [`vendor_state::run`]
+//!
+//! [`dispatch::dispatch`] runs a whole request the way the binary does, and
+//! [`ffi`] exposes the same over a C ABI for in-process callers.
+//!
+//! Per-item failures (one archive that does not extract) are part of each
+//! result. Failures of a whole call are an [`Error`], whose [`ErrorCode`]
+//! tells the failure classes apart:
+//!
+//! ```no_run
+//! use turbo_composer::classmap::{self, AutoloadMappings, NamespaceMapping};
+//! use turbo_composer::ErrorCode;
+//!
+//! let autoload = AutoloadMappings {
+//!     psr4: vec![NamespaceMapping {
+//!         namespace: "App\\".to_string(),
+//!         path: "/app/src".to_string(),
+//!         dev: false,
+//!     }],
+//!     ..Default::default()
+//! };
+//! let mut config = classmap::ClassmapConfig::new("/app", "/app/vendor", autoload);
+//! config.target_dir = Some("/app/vendor/composer".to_string());
+//!
+//! match classmap::dump(config) {
+//!     Ok(output) => println!("{} classes", output.classmap_count),
+//!     Err(e) if e.code == ErrorCode::DiskFull => eprintln!("disk full: {e}"),
+//!     Err(e) => eprintln!("classmap failed: {e}"),
+//! }
+//! ```
+
 pub mod classmap;
 pub mod clean;
+pub mod dispatch;
+pub mod encoding;
+pub mod error;
 pub mod extract;
+pub mod ffi;
+pub mod limits;
+pub mod trace;
+pub mod transaction;
 pub mod vendor_state;
 pub mod verify;
+
+pub use error::{Error, ErrorCode};
@@ -0,0 +1,277 @@
+//! Per-request resource limits.
+//!
+//! By default every command uses rayon's global pool (one thread per core),
+//! the walker uses cores - 1 threads, and nothing bounds open files or memory.
+//! That is right on a developer machine and wrong on a shared CI runner or an
+//! NFS-mounted vendor/, so a request may set:
+//!
+//! - `threads`: worker threads for walking, parsing, hashing and extraction;
+//! - `io_concurrency`: how many file reads/writes may be in flight at once;
+//! - `memory_budget_mb`: a soft cap on archive and source bytes held in memory
+//!   at once. Work is admitted in chunks that fit the budget; a single item
+//!   larger than the budget still runs, on its own.
+//!
+//! Independently of `io_concurrency`, an operation that fails with `EMFILE` or
+//! `ENFILE` (too many open files) is retried after a short back-off, and the
+//! I/O concurrency for the rest of the request is halved.
+
+use serde::Deserialize;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::time::Duration;
+
+/// How often an operation hitting the open-file limit is retried.
+const MAX_FD_RETRIES: u32 = 8;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(5);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+pub struct ResourceLimits {
+    #[serde(default)]
+    pub threads: Option<usize>,
+    #[serde(default)]
+    pub io_concurrency: Option<usize>,
+    #[serde(default)]
+    pub memory_budget_mb: Option<u64>,
+}
+
+impl ResourceLimits {
+    /// Fill every limit not set here from `parent`, e.g. a batch operation
+    /// inheriting the batch's limits.
+    pub fn or(self, parent: ResourceLimits) -> Self {
+        ResourceLimits {
+            threads: self.threads.or(parent.threads),
+            io_concurrency: self.io_concurrency.or(parent.io_concurrency),
+            memory_budget_mb: self.memory_budget_mb.or(parent.memory_budget_mb),
+        }
+    }
+
+    /// Run `f` with rayon parallelism capped at `threads`. Without a thread
+    /// limit (or if the pool cannot be built) `f` runs on the current pool.
+    pub fn install<R: Send>(&self, f: impl FnOnce() -> R + Send) -> R {
+        let pool = self.threads.and_then(|n| {
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(n.max(1))
+                .build()
+                .ok()
+        });
+        match pool {
+            Some(pool) => pool.install(f),
+            None => f(),
+        }
+    }
+}
+
+/// The runtime side of [`ResourceLimits`] for one request: the I/O gate and
+/// the memory budget shared by all of its workers.
+#[derive(Debug, Default)]
+pub struct Limiter {
+    threads: Option<usize>,
+    memory_budget: Option<u64>,
+    io: Mutex<IoState>,
+    io_released: Condvar,
+    backoffs: AtomicUsize,
+}
+
+#[derive(Debug, Default)]
+struct IoState {
+    limit: Option<usize>,
+    in_flight: usize,
+}
+
+impl Limiter {
+    pub fn new(limits: &ResourceLimits) -> Self {
+        Limiter {
+            threads: limits.threads.map(|n| n.max(1)),
+            memory_budget: limits.memory_budget_mb.map(|mb| mb.max(1) * 1024 * 1024),
+            io: Mutex::new(IoState {
+                limit: limits.io_concurrency.map(|n| n.max(1)),
+                in_flight: 0,
+            }),
+            ..Default::default()
+        }
+    }
+
+    /// The requested thread count, if any.
+    pub fn threads(&self) -> Option<usize> {
+        self.threads
+    }
+
+    /// Number of times an operation backed off after `EMFILE`/`ENFILE`.
+    pub fn backoffs(&self) -> usize {
+        self.backoffs.load(Ordering::Relaxed)
+    }
+
+    /// Run one I/O operation under the concurrency limit. If it fails because
+    /// the process or system ran out of file descriptors, lower the limit,
+    /// wait, and try again.
+    pub fn io<T>(&self, mut op: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+        let mut delay = INITIAL_BACKOFF;
+        let mut attempt = 0;
+        loop {
+            self.acquire_io();
+            let result = op();
+            let exhausted = matches!(&result, Err(e) if is_fd_exhausted(e));
+            self.release_io(exhausted);
+
+            if !exhausted || attempt == MAX_FD_RETRIES {
+                return result;
+            }
+            self.backoffs.fetch_add(1, Ordering::Relaxed);
+            std::thread::sleep(delay);
+            delay *= 2;
+            attempt += 1;
+        }
+    }
+
+    fn acquire_io(&self) {
+        let mut state = self.io.lock().unwrap_or_else(|e| e.into_inner());
+        while state.limit.is_some_and(|limit| state.in_flight >= limit) {
+            state = self
+                .io_released
+                .wait(state)
+                .unwrap_or_else(|e| e.into_inner());
+        }
+        state.in_flight += 1;
+    }
+
+    fn release_io(&self, exhausted: bool) {
+        let mut state = self.io.lock().unwrap_or_else(|e| e.into_inner());
+        if exhausted {
+            // Whatever was in flight was too much; allow half of it from now on.
+            let current = state.limit.unwrap_or(state.in_flight).min(state.in_flight);
+            state.limit = Some((current / 2).max(1));
+        }
+        state.in_flight -= 1;
+        self.io_released.notify_all();
+    }
+
+    /// Split `items` into consecutive chunks whose total `size` stays within
+    /// the memory budget, so each chunk can be processed in parallel without
+    /// exceeding it. Without a budget the whole slice is one chunk.
+    pub fn budget_chunks<'a, T>(&self, items: &'a [T], size: impl Fn(&T) -> u64) -> Vec<&'a [T]> {
+        let Some(budget) = self.memory_budget else {
+            return vec![items];
+        };
+        let mut chunks = Vec::new();
+        let mut start = 0;
+        let mut used = 0u64;
+        for (i, item) in items.iter().enumerate() {
+            let bytes = size(item);
+            if i > start && used + bytes > budget {
+                chunks.push(&items[start..i]);
+                start = i;
+                used = 0;
+            }
+            used += bytes;
+        }
+        if start < items.len() {
+            chunks.push(&items[start..]);
+        }
+        chunks
+    }
+
+    pub fn has_memory_budget(&self) -> bool {
+        self.memory_budget.is_some()
+    }
+}
+
+/// `EMFILE` (per-process) or `ENFILE` (system-wide) descriptor exhaustion.
+fn is_fd_exhausted(error: &io::Error) -> bool {
+    #[cfg(unix)]
+    {
+        const ENFILE: i32 = 23;
+        const EMFILE: i32 = 24;
+        matches!(error.raw_os_error(), Some(EMFILE | ENFILE))
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = error;
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn install_caps_rayon_threads() {
+        let limits = ResourceLimits {
+            threads: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(limits.install(rayon::current_num_threads), 2);
+    }
+
+    #[test]
+    fn batch_limits_fill_unset_operation_limits() {
+        let op = ResourceLimits {
+            threads: Some(1),
+            ..Default::default()
+        };
+        let batch = ResourceLimits {
+            threads: Some(8),
+            io_concurrency: Some(4),
+            memory_budget_mb: None,
+        };
+        assert_eq!(
+            op.or(batch),
+            ResourceLimits {
+                threads: Some(1),
+                io_concurrency: Some(4),
+                memory_budget_mb: None,
+            }
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn emfile_is_retried_and_lowers_concurrency() {
+        let limiter = Limiter::new(&ResourceLimits {
+            io_concurrency: Some(16),
+            ..Default::default()
+        });
+        let mut calls = 0;
+        let result = limiter.io(|| {
+            calls += 1;
+            if calls < 3 {
+                Err(io::Error::from_raw_os_error(24))
+            } else {
+                Ok(calls)
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+        assert_eq!(limiter.backoffs(), 2);
+        assert_eq!(limiter.io.lock().unwrap().limit, Some(1));
+    }
+
+    #[test]
+    fn other_io_errors_are_not_retried() {
+        let limiter = Limiter::default();
+        let mut calls = 0;
+        let result: io::Result<()> = limiter.io(|| {
+            calls += 1;
+            Err(io::Error::from(io::ErrorKind::NotFound))
+        });
+        assert!(result.is_err());
+        assert_eq!(calls, 1);
+        assert_eq!(limiter.backoffs(), 0);
+    }
+
+    #[test]
+    fn budget_chunks_respect_budget_and_admit_oversized_items() {
+        let limiter = Limiter::new(&ResourceLimits {
+            memory_budget_mb: Some(1),
+            ..Default::default()
+        });
+        let mb = 1024 * 1024;
+        let sizes = [mb / 2, mb / 2, mb / 2, 3 * mb, mb / 4];
+        let chunks = limiter.budget_chunks(&sizes, |&s| s);
+        let lens: Vec<usize> = chunks.iter().map(|c| c.len()).collect();
+        assert_eq!(lens, vec![2, 1, 1, 1]);
+
+        let unlimited = Limiter::default().budget_chunks(&sizes, |&s| s).len();
+        assert_eq!(unlimited, 1);
+    }
+}
@@ -1,138 +1,116 @@
-use serde::Deserialize;
-use std::io::{self, Read};
-use turbo_composer::{classmap, clean, extract, vendor_state, verify};
+mod cli;
+mod daemon;
 
-const VERSION: &str = env!("CARGO_PKG_VERSION");
+use std::io::{self, Read, Write};
+use std::sync::Arc;
+use turbo_composer::dispatch::{classmap_config, dispatch, with_protocol, Input, VERSION};
+use turbo_composer::encoding::Encoding;
+use turbo_composer::trace::Trace;
+use turbo_composer::{classmap, Error, ErrorCode};
 
-fn default_true() -> bool {
-    true
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let invocation = match cli::parse(&args) {
+        Ok(invocation) => invocation,
+        Err(e) => {
+            eprintln!("turbo-composer: {e}");
+            eprintln!("Run `turbo-composer --help` for usage.");
+            std::process::exit(2);
+        }
+    };
+
+    match invocation {
+        cli::Invocation::Version => println!("turbo-composer {}", VERSION),
+        cli::Invocation::Help(topic) => print!("{}", cli::help_text(topic.as_deref())),
+        cli::Invocation::Stdin(encoding) => run_stdin(encoding),
+        cli::Invocation::Serve(options) => {
+            daemon::serve(options).unwrap_or_else(|e| fail(&e, cli::OutputFormat::Human))
+        }
+        cli::Invocation::Watch { input, format } => {
+            let config = classmap_config(*input).unwrap_or_else(|e| fail(&e, format));
+            let result = classmap::watch(config, |event| match format.encoding() {
+                Some(encoding) => write_response(&event, encoding),
+                None => {
+                    print!("{}", cli::render_human("watch", &event));
+                    let _ = io::stdout().flush();
+                }
+            });
+            result.unwrap_or_else(|e| fail(&e.in_operation("watch"), format));
+        }
+        cli::Invocation::Command { input, format } => {
+            let command = input.command.clone();
+            let output = dispatch(*input, None).unwrap_or_else(|e| fail(&e, format));
+            match format.encoding() {
+                Some(encoding) => write_response(&with_protocol(output), encoding),
+                None => print!("{}", cli::render_human(&command, &output)),
+            }
+        }
+    }
 }
 
-#[derive(Debug, Deserialize)]
-struct Input {
-    command: String,
-
-    #[serde(default)]
-    packages: Vec<extract::PackageExtraction>,
-
-    #[serde(default)]
-    targets: Vec<clean::CleanTarget>,
-
-    #[serde(default)]
-    verify_targets: Vec<verify::VerifyTarget>,
-
-    #[serde(default)]
-    check_packages: Vec<vendor_state::PackageCheck>,
-
-    #[serde(default)]
-    project_dir: Option<String>,
-    #[serde(default)]
-    vendor_dir: Option<String>,
-    #[serde(default)]
-    autoload: Option<classmap::AutoloadMappings>,
-    #[serde(default)]
-    exclude_from_classmap: Vec<String>,
-    #[serde(default)]
-    target_dir: Option<String>,
-    #[serde(default)]
-    suffix: Option<String>,
-    #[serde(default = "default_true")]
-    write_files: bool,
-    #[serde(default)]
-    staging_suffix: Option<String>,
-    #[serde(default)]
-    has_platform_check: bool,
-    #[serde(default)]
-    has_files_autoload: bool,
-
-    // Batch command: array of sub-operations
-    #[serde(default)]
-    operations: Vec<serde_json::Value>,
+/// Report a request that failed as a whole: the error envelope on stdout for
+/// machine consumers, a readable line on stderr, and the failure class's exit
+/// status (see [`ErrorCode::exit_code`]).
+fn fail(error: &Error, format: cli::OutputFormat) -> ! {
+    if let Some(encoding) = format.encoding() {
+        write_response(&with_protocol(error.to_response()), encoding);
+    }
+    eprintln!("turbo-composer: {error}");
+    std::process::exit(error.code.exit_code());
 }
 
-fn dispatch(input: Input) -> serde_json::Value {
-    match input.command.as_str() {
-        "extract" => extract::run(input.packages),
-        "clean" => clean::run(input.targets),
-        "verify" => verify::run(input.verify_targets),
-        "vendor-check" => vendor_state::run(input.check_packages),
-        "classmap" => classmap::run(classmap::ClassmapConfig {
-            project_dir: input.project_dir.unwrap_or_default(),
-            vendor_dir: input.vendor_dir.unwrap_or_default(),
-            autoload: input.autoload.unwrap_or_default(),
-            exclude_from_classmap: input.exclude_from_classmap,
-            target_dir: input.target_dir,
-            suffix: input.suffix,
-            write_files: input.write_files,
-            staging_suffix: input.staging_suffix,
-            has_platform_check: input.has_platform_check,
-            has_files_autoload: input.has_files_autoload,
-        }),
-        "batch" => run_batch(input.operations),
-        other => {
-            eprintln!("unknown command: {other}");
-            std::process::exit(1);
-        }
+/// Write one encoded response to stdout. JSON responses end with a newline.
+fn write_response(response: &serde_json::Value, encoding: Encoding) {
+    let mut bytes = encoding.encode(response);
+    if encoding == Encoding::Json {
+        bytes.push(b'\n');
     }
+    let mut stdout = io::stdout().lock();
+    let _ = stdout.write_all(&bytes);
+    let _ = stdout.flush();
 }
 
-fn run_batch(operations: Vec<serde_json::Value>) -> serde_json::Value {
-    let start = std::time::Instant::now();
-    let mut results = Vec::with_capacity(operations.len());
-
-    for op in operations {
-        let command = op
-            .get("command")
-            .and_then(|c| c.as_str())
-            .unwrap_or("")
-            .to_string();
-        let input: Input = match serde_json::from_value(op) {
-            Ok(i) => i,
-            Err(e) => {
-                results.push(serde_json::json!({
-                    "command": command,
-                    "error": format!("failed to parse operation: {e}"),
-                }));
-                continue;
-            }
-        };
-        let result = dispatch(input);
-        results.push(serde_json::json!({
-            "command": command,
-            "result": result,
-        }));
+fn output_format(encoding: Encoding) -> cli::OutputFormat {
+    match encoding {
+        Encoding::Json => cli::OutputFormat::Json,
+        Encoding::Msgpack => cli::OutputFormat::Msgpack,
     }
-
-    serde_json::json!({
-        "results": results,
-        "elapsed_ms": start.elapsed().as_millis() as u64,
-    })
 }
 
-fn main() {
+/// The plugin protocol: one request on stdin, one response on stdout, both
+/// in `encoding` if given and otherwise in the encoding the request is in.
+fn run_stdin(encoding: Option<Encoding>) {
     let total_start = std::time::Instant::now();
 
-    let args: Vec<String> = std::env::args().collect();
-    if args.iter().any(|a| a == "--version" || a == "-V") {
-        println!("turbo-composer {}", VERSION);
-        return;
-    }
-
     let stdin_start = std::time::Instant::now();
-    let mut buf = String::new();
-    io::stdin()
-        .read_to_string(&mut buf)
-        .expect("failed to read stdin");
-    let stdin_ms = stdin_start.elapsed().as_millis();
+    let mut buf = Vec::new();
+    if let Err(e) = io::stdin().read_to_end(&mut buf) {
+        let error = Error::new(ErrorCode::Io, format!("failed to read stdin: {e}"));
+        fail(&error, output_format(encoding.unwrap_or_default()));
+    }
+    let stdin_end = std::time::Instant::now();
+    let stdin_ms = stdin_end.duration_since(stdin_start).as_millis();
+    let encoding = encoding.unwrap_or_else(|| Encoding::detect(&buf));
+    let format = output_format(encoding);
 
     let parse_start = std::time::Instant::now();
-    let input: Input = serde_json::from_str(&buf).expect("failed to parse input JSON");
-    let json_parse_ms = parse_start.elapsed().as_millis();
+    let mut input: Input = encoding.decode(&buf).unwrap_or_else(|e| fail(&e, format));
+    let parse_end = std::time::Instant::now();
+    let json_parse_ms = parse_end.duration_since(parse_start).as_millis();
+
+    if input.trace_file.is_some() {
+        let trace = Trace::starting_at(total_start);
+        trace.record("io", "stdin_read", stdin_start, stdin_end);
+        trace.record("json", "json_parse", parse_start, parse_end);
+        input.trace = Arc::new(trace);
+    }
 
     let command_start = std::time::Instant::now();
-    let mut output = dispatch(input);
+    let mut output = dispatch(input, None).unwrap_or_else(|e| fail(&e, format));
     let command_ms = command_start.elapsed().as_millis();
 
+    // The `json_*` names predate MessagePack support and are kept for
+    // existing consumers; they time whichever encoding the request used.
     if let Some(stats) = output.get_mut("stats").and_then(|s| s.as_object_mut()) {
         stats.insert("stdin_read_ms".to_string(), serde_json::json!(stdin_ms));
         stats.insert(
@@ -143,7 +121,7 @@ fn main() {
     }
 
     let serialize_start = std::time::Instant::now();
-    let json = serde_json::to_string(&output).expect("failed to serialise output");
+    let bytes = encoding.encode(&with_protocol(output));
     let serialize_ms = serialize_start.elapsed().as_millis();
 
     eprintln!(
@@ -155,5 +133,5 @@ fn main() {
         serialize_ms
     );
 
-    print!("{json}");
+    let _ = io::stdout().lock().write_all(&bytes);
 }
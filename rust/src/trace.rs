@@ -0,0 +1,217 @@
+//! Opt-in span recording in Chrome trace event format.
+//!
+//! A request with `trace_file` set records a span for each phase of the work
+//! (stdin read, JSON parse, cache load, walk, every file parse, PSR filter,
+//! each codegen function, every file write, cache save) and writes them as a
+//! JSON trace that Perfetto or `chrome://tracing` can open.
+//!
+//! A disabled [`Trace`] (the default) records nothing and costs one branch
+//! per span, so instrumented code does not need to check whether tracing is on.
+
+use serde::Serialize;
+use std::cell::Cell;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::error::Error;
+
+#[derive(Debug, Default)]
+pub struct Trace {
+    recorder: Option<Recorder>,
+}
+
+#[derive(Debug)]
+struct Recorder {
+    start: Instant,
+    events: Mutex<Vec<Event>>,
+}
+
+/// One complete ("X") event. Timestamps are microseconds since the trace
+/// started.
+#[derive(Debug, Serialize)]
+struct Event {
+    name: &'static str,
+    cat: &'static str,
+    ph: &'static str,
+    ts: f64,
+    dur: f64,
+    pid: u32,
+    tid: u64,
+    #[serde(skip_serializing_if = "serde_json::Map::is_empty")]
+    args: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Trace {
+    /// An enabled trace whose clock starts now.
+    pub fn new() -> Self {
+        Self::starting_at(Instant::now())
+    }
+
+    /// An enabled trace whose clock started at `start`, so phases timed
+    /// before the request was known to want a trace can still be recorded.
+    pub fn starting_at(start: Instant) -> Self {
+        Trace {
+            recorder: Some(Recorder {
+                start,
+                events: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.recorder.is_some()
+    }
+
+    /// Start a span that is recorded when the returned guard is dropped.
+    pub fn span(&self, cat: &'static str, name: &'static str) -> Span<'_> {
+        Span {
+            trace: self,
+            cat,
+            name,
+            start: Instant::now(),
+            args: serde_json::Map::new(),
+        }
+    }
+
+    /// Record a span that has already finished.
+    pub fn record(&self, cat: &'static str, name: &'static str, start: Instant, end: Instant) {
+        self.push(cat, name, start, end, serde_json::Map::new());
+    }
+
+    fn push(
+        &self,
+        cat: &'static str,
+        name: &'static str,
+        start: Instant,
+        end: Instant,
+        args: serde_json::Map<String, serde_json::Value>,
+    ) {
+        let Some(ref recorder) = self.recorder else {
+            return;
+        };
+        let micros = |t: Instant| t.saturating_duration_since(recorder.start).as_secs_f64() * 1e6;
+        let event = Event {
+            name,
+            cat,
+            ph: "X",
+            ts: micros(start),
+            dur: end.saturating_duration_since(start).as_secs_f64() * 1e6,
+            pid: std::process::id(),
+            tid: thread_id(),
+            args,
+        };
+        recorder
+            .events
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .push(event);
+    }
+
+    /// The trace as a Chrome trace event JSON object.
+    pub fn to_json(&self) -> serde_json::Value {
+        let events = match self.recorder {
+            Some(ref recorder) => {
+                let mut events = recorder.events.lock().unwrap_or_else(|e| e.into_inner());
+                events.sort_by(|a, b| a.ts.total_cmp(&b.ts));
+                serde_json::to_value(&*events).unwrap()
+            }
+            None => serde_json::Value::Array(Vec::new()),
+        };
+        serde_json::json!({
+            "traceEvents": events,
+            "displayTimeUnit": "ms",
+        })
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        let data = serde_json::to_vec(&self.to_json()).unwrap();
+        std::fs::write(path, data).map_err(|e| Error::io(e, path))
+    }
+}
+
+pub struct Span<'a> {
+    trace: &'a Trace,
+    cat: &'static str,
+    name: &'static str,
+    start: Instant,
+    args: serde_json::Map<String, serde_json::Value>,
+}
+
+impl Span<'_> {
+    /// Attach an argument shown in the viewer's detail pane. Skipped
+    /// entirely when tracing is off.
+    pub fn arg(mut self, key: &str, value: impl Into<serde_json::Value>) -> Self {
+        if self.trace.is_enabled() {
+            self.args.insert(key.to_string(), value.into());
+        }
+        self
+    }
+}
+
+impl Drop for Span<'_> {
+    fn drop(&mut self) {
+        let args = std::mem::take(&mut self.args);
+        self.trace
+            .push(self.cat, self.name, self.start, Instant::now(), args);
+    }
+}
+
+/// Small, stable per-thread ids; the viewer draws one track per id.
+fn thread_id() -> u64 {
+    static NEXT: AtomicU64 = AtomicU64::new(1);
+    thread_local! {
+        static ID: Cell<u64> = const { Cell::new(0) };
+    }
+    ID.with(|id| {
+        if id.get() == 0 {
+            id.set(NEXT.fetch_add(1, Ordering::Relaxed));
+        }
+        id.get()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn spans_are_recorded_as_complete_events() {
+        let trace = Trace::new();
+        {
+            let _outer = trace.span("classmap", "walk");
+            let _inner = trace.span("parse", "parse_file").arg("path", "/a.php");
+        }
+        let json = trace.to_json();
+        let events = json["traceEvents"].as_array().unwrap();
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0]["name"], "walk");
+        assert_eq!(events[0]["ph"], "X");
+        assert_eq!(events[1]["args"]["path"], "/a.php");
+        assert!(events[0]["dur"].as_f64().unwrap() >= events[1]["dur"].as_f64().unwrap());
+    }
+
+    #[test]
+    fn disabled_trace_records_nothing() {
+        let trace = Trace::default();
+        drop(trace.span("classmap", "walk").arg("path", "/a.php"));
+        trace.record("io", "stdin_read", Instant::now(), Instant::now());
+        assert!(trace.to_json()["traceEvents"]
+            .as_array()
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn events_from_other_threads_get_their_own_tid() {
+        let trace = Trace::new();
+        drop(trace.span("test", "main"));
+        std::thread::scope(|s| {
+            s.spawn(|| drop(trace.span("test", "worker")));
+        });
+        let json = trace.to_json();
+        let events = json["traceEvents"].as_array().unwrap();
+        assert_ne!(events[0]["tid"], events[1]["tid"]);
+    }
+}
@@ -0,0 +1,409 @@
+//! Journaled vendor transactions.
+//!
+//! In a transactional batch nothing in vendor/ is deleted or overwritten until
+//! every operation has succeeded: `clean` moves targets into
+//! `<root>/.turbo-trash`, `extract` unpacks into staging directories next to
+//! them, and [`Transaction::commit`] swaps the staged trees into place.
+//!
+//! Every step is recorded in `<root>/.turbo-trash/journal.json` *before* it is
+//! performed, so a transaction interrupted at any point (including halfway
+//! through a commit) can be rolled back by replaying the journal in reverse.
+//! [`recover`] does that, and [`Transaction::begin`] calls it first so a
+//! crashed run is undone before the next one starts.
+//!
+//! All moves are renames within `root`, so `root` and every path the
+//! transaction touches must live on the same filesystem.
+
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+use crate::error::Error;
+
+const TRASH_DIR: &str = ".turbo-trash";
+const JOURNAL_FILE: &str = "journal.json";
+const JOURNAL_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum State {
+    /// Operations are running; nothing outside the trash has been replaced.
+    Pending,
+    /// Staged trees are being swapped into place.
+    Committing,
+    /// Everything is in place; only the trash remains to be deleted.
+    Committed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum Action {
+    /// `path` was moved to `moved_to` by a `clean`.
+    Removed { path: PathBuf, moved_to: PathBuf },
+    /// An extraction for `dest` is (being) unpacked into `staged`.
+    Staged { dest: PathBuf, staged: PathBuf },
+    /// During commit, the existing `dest` was moved aside to `moved_to`.
+    Replaced { dest: PathBuf, moved_to: PathBuf },
+    /// During commit, the staged tree was renamed to `dest`.
+    Installed { dest: PathBuf },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Journal {
+    version: u32,
+    state: State,
+    /// This transaction's directory inside `.turbo-trash`.
+    trash: PathBuf,
+    actions: Vec<Action>,
+}
+
+/// What [`recover`] found and did.
+#[derive(Debug, Serialize)]
+pub struct Recovery {
+    /// `true` if an unfinished transaction was undone, `false` if the journal
+    /// belonged to a committed one and only its trash had to be removed.
+    pub rolled_back: bool,
+    /// Number of journal entries replayed.
+    pub actions: usize,
+}
+
+pub struct Transaction {
+    journal_path: PathBuf,
+    journal: Mutex<Journal>,
+    next: AtomicUsize,
+}
+
+impl Transaction {
+    /// Start a transaction rooted at `root` (normally the vendor dir), first
+    /// rolling back whatever an interrupted earlier transaction left behind.
+    pub fn begin(root: &Path) -> Result<Self, Error> {
+        recover(root)?;
+
+        let trash_root = root.join(TRASH_DIR);
+        let trash = trash_root.join(format!(
+            "{}-{}",
+            std::process::id(),
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_nanos())
+                .unwrap_or(0)
+        ));
+        fs::create_dir_all(&trash).map_err(|e| Error::io(e, &trash))?;
+
+        let tx = Transaction {
+            journal_path: trash_root.join(JOURNAL_FILE),
+            journal: Mutex::new(Journal {
+                version: JOURNAL_VERSION,
+                state: State::Pending,
+                trash,
+                actions: Vec::new(),
+            }),
+            next: AtomicUsize::new(0),
+        };
+        tx.save(&tx.lock())?;
+        Ok(tx)
+    }
+
+    /// Move `path` into the trash instead of deleting it.
+    pub fn remove(&self, path: &Path) -> io::Result<()> {
+        let mut journal = self.lock();
+        let moved_to = journal.trash.join(format!("removed-{}", self.next_id()));
+        journal.actions.push(Action::Removed {
+            path: path.to_path_buf(),
+            moved_to: moved_to.clone(),
+        });
+        self.save(&journal).map_err(io::Error::other)?;
+        fs::rename(path, &moved_to)
+    }
+
+    /// Allocate the directory an extraction destined for `dest` should be
+    /// unpacked into. It is moved to `dest` on commit.
+    pub fn stage(&self, dest: &Path) -> io::Result<PathBuf> {
+        let mut journal = self.lock();
+        let staged = journal.trash.join(format!("staged-{}", self.next_id()));
+        journal.actions.push(Action::Staged {
+            dest: dest.to_path_buf(),
+            staged: staged.clone(),
+        });
+        self.save(&journal).map_err(io::Error::other)?;
+        Ok(staged)
+    }
+
+    /// Swap every staged tree into place, then delete the trash. If a swap
+    /// fails, everything done so far is rolled back and the error returned.
+    pub fn commit(self) -> Result<(), Error> {
+        let mut journal = self.lock();
+        journal.state = State::Committing;
+        self.save(&journal)?;
+
+        let staged: Vec<(PathBuf, PathBuf)> = journal
+            .actions
+            .iter()
+            .filter_map(|a| match a {
+                Action::Staged { dest, staged } => Some((dest.clone(), staged.clone())),
+                _ => None,
+            })
+            .collect();
+
+        let result = (|| -> Result<(), Error> {
+            for (dest, staged) in staged {
+                if dest.exists() {
+                    let moved_to = journal.trash.join(format!("replaced-{}", self.next_id()));
+                    journal.actions.push(Action::Replaced {
+                        dest: dest.clone(),
+                        moved_to: moved_to.clone(),
+                    });
+                    self.save(&journal)?;
+                    fs::rename(&dest, &moved_to).map_err(|e| Error::io(e, &dest))?;
+                }
+                journal
+                    .actions
+                    .push(Action::Installed { dest: dest.clone() });
+                self.save(&journal)?;
+                if let Some(parent) = dest.parent() {
+                    fs::create_dir_all(parent).map_err(|e| Error::io(e, parent))?;
+                }
+                fs::rename(&staged, &dest).map_err(|e| Error::io(e, &dest))?;
+            }
+            Ok(())
+        })();
+
+        if let Err(e) = result {
+            roll_back(&journal.actions);
+            let _ = fs::remove_dir_all(&journal.trash);
+            let _ = fs::remove_file(&self.journal_path);
+            return Err(e);
+        }
+
+        journal.state = State::Committed;
+        self.save(&journal)?;
+        finish(&journal, &self.journal_path);
+        Ok(())
+    }
+
+    /// Undo everything: put removed directories back and discard staged ones.
+    pub fn rollback(self) -> Result<(), Error> {
+        let journal = self.lock();
+        roll_back(&journal.actions);
+        finish(&journal, &self.journal_path);
+        Ok(())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Journal> {
+        self.journal.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn next_id(&self) -> usize {
+        self.next.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Persist the journal atomically: write a sibling file, then rename.
+    fn save(&self, journal: &Journal) -> Result<(), Error> {
+        let tmp = self.journal_path.with_extension("json.tmp");
+        let data = serde_json::to_vec(journal).unwrap();
+        fs::write(&tmp, data)
+            .and_then(|_| fs::File::open(&tmp)?.sync_all())
+            .and_then(|_| fs::rename(&tmp, &self.journal_path))
+            .map_err(|e| Error::io(e, &self.journal_path))
+    }
+}
+
+/// Replay the journal under `root`, if there is one: roll back an unfinished
+/// transaction, or finish cleaning up after a committed one. Returns `None`
+/// when there was nothing to do.
+pub fn recover(root: &Path) -> Result<Option<Recovery>, Error> {
+    let journal_path = root.join(TRASH_DIR).join(JOURNAL_FILE);
+    let Some(journal) = read_journal(&journal_path)? else {
+        return Ok(None);
+    };
+
+    let rolled_back = journal.state != State::Committed;
+    if rolled_back {
+        roll_back(&journal.actions);
+    }
+    finish(&journal, &journal_path);
+
+    Ok(Some(Recovery {
+        rolled_back,
+        actions: journal.actions.len(),
+    }))
+}
+
+/// What [`recover`] would do under `root`, without doing it.
+pub fn inspect(root: &Path) -> Result<Option<Recovery>, Error> {
+    let journal_path = root.join(TRASH_DIR).join(JOURNAL_FILE);
+    Ok(read_journal(&journal_path)?.map(|journal| Recovery {
+        rolled_back: journal.state != State::Committed,
+        actions: journal.actions.len(),
+    }))
+}
+
+fn read_journal(journal_path: &Path) -> Result<Option<Journal>, Error> {
+    let data = match fs::read(journal_path) {
+        Ok(data) => data,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(Error::io(e, journal_path)),
+    };
+    serde_json::from_slice(&data).map(Some).map_err(|e| {
+        Error::invalid_request(format!("corrupt transaction journal: {e}")).with_path(journal_path)
+    })
+}
+
+/// Undo `actions` newest first. Each step checks the filesystem rather than
+/// trusting the journal, since the process may have died either before or
+/// after performing the step it had just recorded.
+fn roll_back(actions: &[Action]) {
+    for action in actions.iter().rev() {
+        match action {
+            Action::Installed { dest } => {
+                let _ = fs::remove_dir_all(dest);
+            }
+            Action::Replaced { dest, moved_to }
+            | Action::Removed {
+                path: dest,
+                moved_to,
+            } => {
+                if moved_to.exists() && !dest.exists() {
+                    let _ = fs::rename(moved_to, dest);
+                }
+            }
+            Action::Staged { staged, .. } => {
+                let _ = fs::remove_dir_all(staged);
+            }
+        }
+    }
+}
+
+fn finish(journal: &Journal, journal_path: &Path) {
+    let _ = fs::remove_dir_all(&journal.trash);
+    let _ = fs::remove_file(journal_path);
+    // Leave .turbo-trash itself behind only if something else is in it.
+    if let Some(trash_root) = journal_path.parent() {
+        let _ = fs::remove_dir(trash_root);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn package(root: &Path, name: &str, file: &str) -> PathBuf {
+        let dir = root.join(name);
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("file.txt"), file).unwrap();
+        dir
+    }
+
+    fn stage_with(tx: &Transaction, dest: &Path, content: &str) {
+        let staged = tx.stage(dest).unwrap();
+        fs::create_dir_all(&staged).unwrap();
+        fs::write(staged.join("file.txt"), content).unwrap();
+    }
+
+    #[test]
+    fn commit_swaps_in_staged_trees_and_removes_trash() {
+        let tmp = TempDir::new().unwrap();
+        let old = package(tmp.path(), "old", "old");
+        let existing = package(tmp.path(), "existing", "v1");
+
+        let tx = Transaction::begin(tmp.path()).unwrap();
+        tx.remove(&old).unwrap();
+        stage_with(&tx, &existing, "v2");
+        stage_with(&tx, &tmp.path().join("new"), "new");
+        assert!(!old.exists());
+        assert_eq!(fs::read_to_string(existing.join("file.txt")).unwrap(), "v1");
+
+        tx.commit().unwrap();
+        assert!(!old.exists());
+        assert_eq!(fs::read_to_string(existing.join("file.txt")).unwrap(), "v2");
+        assert_eq!(
+            fs::read_to_string(tmp.path().join("new/file.txt")).unwrap(),
+            "new"
+        );
+        assert!(!tmp.path().join(TRASH_DIR).exists());
+    }
+
+    #[test]
+    fn rollback_restores_removed_directories() {
+        let tmp = TempDir::new().unwrap();
+        let old = package(tmp.path(), "old", "old");
+
+        let tx = Transaction::begin(tmp.path()).unwrap();
+        tx.remove(&old).unwrap();
+        stage_with(&tx, &tmp.path().join("new"), "new");
+        tx.rollback().unwrap();
+
+        assert_eq!(fs::read_to_string(old.join("file.txt")).unwrap(), "old");
+        assert!(!tmp.path().join("new").exists());
+        assert!(!tmp.path().join(TRASH_DIR).exists());
+    }
+
+    #[test]
+    fn recover_rolls_back_interrupted_transaction() {
+        let tmp = TempDir::new().unwrap();
+        let old = package(tmp.path(), "old", "old");
+
+        let tx = Transaction::begin(tmp.path()).unwrap();
+        tx.remove(&old).unwrap();
+        stage_with(&tx, &tmp.path().join("new"), "new");
+        // Simulate a crash: the transaction is dropped without commit/rollback.
+        drop(tx);
+
+        let pending = inspect(tmp.path()).unwrap().expect("journal present");
+        assert!(pending.rolled_back);
+        assert!(!old.exists());
+
+        let recovery = recover(tmp.path()).unwrap().expect("journal present");
+        assert!(recovery.rolled_back);
+        assert_eq!(recovery.actions, 2);
+        assert_eq!(fs::read_to_string(old.join("file.txt")).unwrap(), "old");
+        assert!(!tmp.path().join("new").exists());
+        assert!(recover(tmp.path()).unwrap().is_none());
+    }
+
+    #[test]
+    fn recover_undoes_partial_commit() {
+        let tmp = TempDir::new().unwrap();
+        let existing = package(tmp.path(), "existing", "v1");
+
+        let tx = Transaction::begin(tmp.path()).unwrap();
+        stage_with(&tx, &existing, "v2");
+
+        // Simulate dying right after the existing tree was moved aside.
+        {
+            let mut journal = tx.lock();
+            journal.state = State::Committing;
+            let moved_to = journal.trash.join("replaced-x");
+            journal.actions.push(Action::Replaced {
+                dest: existing.clone(),
+                moved_to: moved_to.clone(),
+            });
+            tx.save(&journal).unwrap();
+            fs::rename(&existing, &moved_to).unwrap();
+        }
+        drop(tx);
+
+        assert!(!existing.exists());
+        let recovery = recover(tmp.path()).unwrap().unwrap();
+        assert!(recovery.rolled_back);
+        assert_eq!(fs::read_to_string(existing.join("file.txt")).unwrap(), "v1");
+    }
+
+    #[test]
+    fn begin_recovers_leftovers_first() {
+        let tmp = TempDir::new().unwrap();
+        let old = package(tmp.path(), "old", "old");
+
+        let tx = Transaction::begin(tmp.path()).unwrap();
+        tx.remove(&old).unwrap();
+        drop(tx);
+
+        let tx = Transaction::begin(tmp.path()).unwrap();
+        assert!(old.exists());
+        tx.commit().unwrap();
+    }
+}
@@ -1,25 +1,33 @@
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct PackageCheck {
     pub name: String,
-    pub install_path: String,
+    #[serde(with = "crate::encoding::path")]
+    pub install_path: PathBuf,
 }
 
-#[derive(Debug, Serialize)]
-struct VendorStateResult {
-    present: usize,
-    missing: Vec<String>,
-    incomplete: Vec<String>,
-    total: usize,
-    elapsed_ms: u128,
+#[derive(Debug, Clone, Serialize)]
+pub struct VendorStateResult {
+    pub present: usize,
+    /// Packages whose install path does not exist, sorted.
+    pub missing: Vec<String>,
+    /// Packages whose install path is an empty directory, sorted.
+    pub incomplete: Vec<String>,
+    pub total: usize,
+    pub elapsed_ms: u128,
 }
 
 pub fn run(packages: Vec<PackageCheck>) -> serde_json::Value {
+    serde_json::to_value(check(&packages)).unwrap()
+}
+
+/// Check every package's install path in parallel. The typed form of [`run`].
+pub fn check(packages: &[PackageCheck]) -> VendorStateResult {
     let start = std::time::Instant::now();
     let total = packages.len();
     let present = AtomicUsize::new(0);
@@ -64,15 +72,13 @@ pub fn run(packages: Vec<PackageCheck>) -> serde_json::Value {
     missing.sort();
     incomplete.sort();
 
-    let result = VendorStateResult {
+    VendorStateResult {
         present: present.load(Ordering::Relaxed),
         missing,
         incomplete,
         total,
         elapsed_ms: start.elapsed().as_millis(),
-    };
-
-    serde_json::to_value(result).unwrap()
+    }
 }
 
 #[cfg(test)]
@@ -91,7 +97,7 @@ mod tests {
                 fs::write(dir.join("composer.json"), b"{}").unwrap();
                 PackageCheck {
                     name: format!("vendor/pkg-{i}"),
-                    install_path: dir.to_string_lossy().to_string(),
+                    install_path: dir.clone(),
                 }
             })
             .collect();
@@ -108,11 +114,11 @@ mod tests {
         let pkgs = vec![
             PackageCheck {
                 name: "vendor/missing-1".to_string(),
-                install_path: "/nonexistent/path/1".to_string(),
+                install_path: "/nonexistent/path/1".into(),
             },
             PackageCheck {
                 name: "vendor/missing-2".to_string(),
-                install_path: "/nonexistent/path/2".to_string(),
+                install_path: "/nonexistent/path/2".into(),
             },
         ];
 
@@ -131,7 +137,7 @@ mod tests {
 
         let pkgs = vec![PackageCheck {
             name: "vendor/empty".to_string(),
-            install_path: empty_dir.to_string_lossy().to_string(),
+            install_path: empty_dir.clone(),
         }];
 
         let result = run(pkgs);
@@ -153,15 +159,15 @@ mod tests {
         let pkgs = vec![
             PackageCheck {
                 name: "vendor/good".to_string(),
-                install_path: good_dir.to_string_lossy().to_string(),
+                install_path: good_dir.clone(),
             },
             PackageCheck {
                 name: "vendor/missing".to_string(),
-                install_path: "/nonexistent/dir".to_string(),
+                install_path: "/nonexistent/dir".into(),
             },
             PackageCheck {
                 name: "vendor/empty".to_string(),
-                install_path: empty_dir.to_string_lossy().to_string(),
+                install_path: empty_dir.clone(),
             },
         ];
 
@@ -190,7 +196,7 @@ mod tests {
                 fs::write(dir.join("autoload.php"), b"<?php").unwrap();
                 PackageCheck {
                     name: format!("vendor/pkg-{i}"),
-                    install_path: dir.to_string_lossy().to_string(),
+                    install_path: dir.clone(),
                 }
             })
             .collect();
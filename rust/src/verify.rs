@@ -4,130 +4,128 @@ use serde::{Deserialize, Serialize};
 use sha1::Sha1;
 use sha2::{Digest, Sha256};
 use std::fs;
-use std::path::Path;
+use std::io;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicUsize, Ordering};
 
-#[derive(Debug, Deserialize)]
+use crate::limits::Limiter;
+
+/// Values accepted in [`VerifyTarget::algorithm`].
+pub const HASH_ALGORITHMS: &[&str] = &["sha256", "sha1"];
+
+#[derive(Debug, Clone, Deserialize)]
 pub struct VerifyTarget {
-    pub path: String,
+    #[serde(with = "crate::encoding::path")]
+    pub path: PathBuf,
     pub name: String,
+    /// One of [`HASH_ALGORITHMS`].
     pub algorithm: String,
     pub expected_hash: String,
 }
 
-#[derive(Debug, Serialize)]
-struct VerifyResult {
-    verified: usize,
-    failed: Vec<VerifyFailure>,
-    total: usize,
-    elapsed_ms: u128,
+#[derive(Debug, Clone, Serialize)]
+pub struct VerifyResult {
+    pub verified: usize,
+    pub failed: Vec<VerifyFailure>,
+    pub total: usize,
+    pub elapsed_ms: u128,
 }
 
-#[derive(Debug, Serialize)]
-struct VerifyFailure {
-    name: String,
-    expected: String,
-    actual: String,
+#[derive(Debug, Clone, Serialize)]
+pub struct VerifyFailure {
+    pub name: String,
+    pub expected: String,
+    /// Empty when the file could not be hashed; see `error`.
+    pub actual: String,
     #[serde(skip_serializing_if = "Option::is_none")]
-    error: Option<String>,
+    pub error: Option<String>,
 }
 
 pub fn run(targets: Vec<VerifyTarget>) -> serde_json::Value {
+    run_with(targets, &Limiter::default())
+}
+
+/// Like [`run`], reading archives under `limiter`'s I/O and memory limits.
+pub fn run_with(targets: Vec<VerifyTarget>, limiter: &Limiter) -> serde_json::Value {
+    serde_json::to_value(verify(&targets, limiter)).unwrap()
+}
+
+/// Hash every target in parallel and compare. The typed form of [`run_with`].
+pub fn verify(targets: &[VerifyTarget], limiter: &Limiter) -> VerifyResult {
     let start = std::time::Instant::now();
     let total = targets.len();
     let verified = AtomicUsize::new(0);
 
-    let failed: Vec<VerifyFailure> = targets
-        .par_iter()
-        .filter_map(|target| {
-            let path = Path::new(&target.path);
-
-            let file = match fs::File::open(path) {
-                Ok(f) => f,
-                Err(e) => {
-                    return Some(VerifyFailure {
-                        name: target.name.clone(),
-                        expected: target.expected_hash.clone(),
-                        actual: String::new(),
-                        error: Some(e.to_string()),
-                    });
-                }
-            };
-
-            let file_len = match file.metadata() {
-                Ok(m) => m.len(),
-                Err(e) => {
-                    return Some(VerifyFailure {
-                        name: target.name.clone(),
-                        expected: target.expected_hash.clone(),
-                        actual: String::new(),
-                        error: Some(e.to_string()),
-                    });
-                }
-            };
-
-            // mmap cannot map zero-length files; use an empty slice instead.
-            let mmap = if file_len > 0 {
-                match unsafe { Mmap::map(&file) } {
-                    Ok(m) => Some(m),
-                    Err(e) => {
-                        return Some(VerifyFailure {
+    let chunks = limiter.budget_chunks(targets, |target| {
+        fs::metadata(&target.path).map(|m| m.len()).unwrap_or(0)
+    });
+    let failed: Vec<VerifyFailure> = chunks
+        .into_iter()
+        .flat_map(|chunk| {
+            chunk
+                .par_iter()
+                .filter_map(|target| {
+                    let path = Path::new(&target.path);
+                    match limiter.io(|| hash_file(path, &target.algorithm)) {
+                        Ok(actual) if actual == target.expected_hash => {
+                            verified.fetch_add(1, Ordering::Relaxed);
+                            None
+                        }
+                        Ok(actual) => Some(VerifyFailure {
+                            name: target.name.clone(),
+                            expected: target.expected_hash.clone(),
+                            actual,
+                            error: None,
+                        }),
+                        Err(e) => Some(VerifyFailure {
                             name: target.name.clone(),
                             expected: target.expected_hash.clone(),
                             actual: String::new(),
                             error: Some(e.to_string()),
-                        });
+                        }),
                     }
-                }
-            } else {
-                None
-            };
-
-            let bytes: &[u8] = mmap.as_deref().unwrap_or(&[]);
-
-            let actual_hash = match target.algorithm.as_str() {
-                "sha256" => {
-                    let mut hasher = Sha256::new();
-                    hasher.update(bytes);
-                    format!("{:x}", hasher.finalize())
-                }
-                "sha1" => {
-                    let mut hasher = Sha1::new();
-                    hasher.update(bytes);
-                    format!("{:x}", hasher.finalize())
-                }
-                other => {
-                    return Some(VerifyFailure {
-                        name: target.name.clone(),
-                        expected: target.expected_hash.clone(),
-                        actual: String::new(),
-                        error: Some(format!("unsupported algorithm: {other}")),
-                    });
-                }
-            };
-
-            if actual_hash == target.expected_hash {
-                verified.fetch_add(1, Ordering::Relaxed);
-                None
-            } else {
-                Some(VerifyFailure {
-                    name: target.name.clone(),
-                    expected: target.expected_hash.clone(),
-                    actual: actual_hash,
-                    error: None,
                 })
-            }
+                .collect::<Vec<_>>()
         })
         .collect();
 
-    let result = VerifyResult {
+    VerifyResult {
         verified: verified.load(Ordering::Relaxed),
         failed,
         total,
         elapsed_ms: start.elapsed().as_millis(),
+    }
+}
+
+/// Hex digest of the file at `path`.
+fn hash_file(path: &Path, algorithm: &str) -> io::Result<String> {
+    let file = fs::File::open(path)?;
+    let file_len = file.metadata()?.len();
+
+    // mmap cannot map zero-length files; use an empty slice instead.
+    let mmap = if file_len > 0 {
+        Some(unsafe { Mmap::map(&file)? })
+    } else {
+        None
     };
+    let bytes: &[u8] = mmap.as_deref().unwrap_or(&[]);
 
-    serde_json::to_value(result).unwrap()
+    match algorithm {
+        "sha256" => {
+            let mut hasher = Sha256::new();
+            hasher.update(bytes);
+            Ok(format!("{:x}", hasher.finalize()))
+        }
+        "sha1" => {
+            let mut hasher = Sha1::new();
+            hasher.update(bytes);
+            Ok(format!("{:x}", hasher.finalize()))
+        }
+        other => Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unsupported algorithm: {other}"),
+        )),
+    }
 }
 
 #[cfg(test)]
@@ -147,7 +145,7 @@ mod tests {
         let expected = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
 
         let targets = vec![VerifyTarget {
-            path: file.to_string_lossy().to_string(),
+            path: file.clone(),
             name: "test-file".to_string(),
             algorithm: "sha256".to_string(),
             expected_hash: expected.to_string(),
@@ -169,7 +167,7 @@ mod tests {
         let expected = "2aae6c35c94fcfb415dbe95f408b9ce91ee846ed";
 
         let targets = vec![VerifyTarget {
-            path: file.to_string_lossy().to_string(),
+            path: file.clone(),
             name: "test-file".to_string(),
             algorithm: "sha1".to_string(),
             expected_hash: expected.to_string(),
@@ -187,7 +185,7 @@ mod tests {
         fs::write(&file, b"hello world").unwrap();
 
         let targets = vec![VerifyTarget {
-            path: file.to_string_lossy().to_string(),
+            path: file.clone(),
             name: "test-file".to_string(),
             algorithm: "sha256".to_string(),
             expected_hash: "deadbeef".to_string(),
@@ -205,7 +203,7 @@ mod tests {
     #[test]
     fn verify_missing_file_reports_error() {
         let targets = vec![VerifyTarget {
-            path: "/nonexistent/file.zip".to_string(),
+            path: "/nonexistent/file.zip".into(),
             name: "missing-pkg".to_string(),
             algorithm: "sha256".to_string(),
             expected_hash: "abc123".to_string(),
@@ -225,7 +223,7 @@ mod tests {
         fs::write(&file, b"content").unwrap();
 
         let targets = vec![VerifyTarget {
-            path: file.to_string_lossy().to_string(),
+            path: file.clone(),
             name: "test-file".to_string(),
             algorithm: "md5".to_string(),
             expected_hash: "abc".to_string(),
@@ -252,7 +250,7 @@ mod tests {
             let hash = format!("{:x}", hasher.finalize());
 
             targets.push(VerifyTarget {
-                path: file.to_string_lossy().to_string(),
+                path: file.clone(),
                 name: format!("pkg-{i}"),
                 algorithm: "sha256".to_string(),
                 expected_hash: hash,
@@ -275,7 +273,7 @@ mod tests {
         let expected = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
 
         let targets = vec![VerifyTarget {
-            path: file.to_string_lossy().to_string(),
+            path: file.clone(),
             name: "empty-file".to_string(),
             algorithm: "sha256".to_string(),
             expected_hash: expected.to_string(),
@@ -296,7 +294,7 @@ mod tests {
         let expected = "da39a3ee5e6b4b0d3255bfef95601890afd80709";
 
         let targets = vec![VerifyTarget {
-            path: file.to_string_lossy().to_string(),
+            path: file.clone(),
             name: "empty-file".to_string(),
             algorithm: "sha1".to_string(),
             expected_hash: expected.to_string(),
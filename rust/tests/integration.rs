@@ -21,6 +21,31 @@ fn cargo_bin() -> String {
 }
 
 fn run_binary(input_json: &str) -> serde_json::Value {
+    let output = run_binary_raw(input_json);
+    assert!(
+        output.status.success(),
+        "binary exited with error: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    serde_json::from_slice(&output.stdout).expect("invalid JSON output from binary")
+}
+
+/// A stand-in for Composer's `src/Composer` directory under `dir`, for
+/// `runtime_dir`.
+fn composer_runtime(dir: &std::path::Path) -> String {
+    let runtime = dir.join("src/Composer");
+    fs::create_dir_all(runtime.join("Autoload")).unwrap();
+    fs::write(
+        runtime.join("Autoload/ClassLoader.php"),
+        "<?php // loader\n",
+    )
+    .unwrap();
+    fs::write(runtime.join("InstalledVersions.php"), "<?php // versions\n").unwrap();
+    runtime.to_string_lossy().into_owned()
+}
+
+fn run_binary_raw(input: impl AsRef<[u8]>) -> std::process::Output {
     let bin = cargo_bin();
     let mut child = Command::new(&bin)
         .stdin(Stdio::piped())
@@ -33,17 +58,10 @@ fn run_binary(input_json: &str) -> serde_json::Value {
         .stdin
         .as_mut()
         .unwrap()
-        .write_all(input_json.as_bytes())
+        .write_all(input.as_ref())
         .unwrap();
 
-    let output = child.wait_with_output().unwrap();
-    assert!(
-        output.status.success(),
-        "binary exited with error: {}",
-        String::from_utf8_lossy(&output.stderr)
-    );
-
-    serde_json::from_slice(&output.stdout).expect("invalid JSON output from binary")
+    child.wait_with_output().unwrap()
 }
 
 #[test]
@@ -315,3 +333,1044 @@ fn classmap_staging_writes_files_and_omits_contents() {
     let autoload_php = fs::read_to_string(vendor_dir.join("autoload.php.turbo")).unwrap();
     assert!(autoload_php.contains("ComposerAutoloaderInittestsfx"));
 }
+
+fn run_cli(args: &[&str]) -> std::process::Output {
+    let bin = cargo_bin();
+    Command::new(&bin)
+        .args(args)
+        .stdin(Stdio::null())
+        .output()
+        .unwrap_or_else(|e| panic!("failed to run {bin}: {e}"))
+}
+
+#[test]
+fn help_flag_lists_subcommands() {
+    let output = run_cli(&["--help"]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    for command in [
+        "classmap",
+        "extract",
+        "verify",
+        "clean",
+        "vendor-check",
+        "batch",
+    ] {
+        assert!(stdout.contains(command), "help should mention {command}");
+    }
+
+    let output = run_cli(&["classmap", "--help"]);
+    assert!(output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("--psr4"));
+}
+
+#[test]
+fn unknown_subcommand_fails() {
+    let output = run_cli(&["frobnicate"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unknown command"));
+}
+
+#[test]
+fn classmap_subcommand_resolves_paths_from_project_dir() {
+    let tmp = TempDir::new().unwrap();
+    let src_dir = tmp.path().join("src");
+    fs::create_dir_all(&src_dir).unwrap();
+
+    let mut f = fs::File::create(src_dir.join("Foo.php")).unwrap();
+    writeln!(f, "<?php\nnamespace App;\nclass Foo {{}}").unwrap();
+
+    let project = tmp.path().to_string_lossy().to_string();
+    let runtime = composer_runtime(&tmp.path().join("composer"));
+    let output = run_cli(&[
+        "classmap",
+        "--project-dir",
+        &project,
+        "--psr4",
+        "App\\=src",
+        "--suffix",
+        "clisfx",
+        "--runtime-dir",
+        &runtime,
+    ]);
+    assert!(
+        output.status.success(),
+        "classmap failed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(result["classmap_count"].as_u64().unwrap(), 1);
+    assert!(result["files_written"].as_bool().unwrap());
+
+    let composer_dir = tmp.path().join("vendor/composer");
+    let psr4 = fs::read_to_string(composer_dir.join("autoload_psr4.php")).unwrap();
+    assert!(psr4.contains("$baseDir . '/src'"));
+    assert!(composer_dir.join("autoload_static.php").exists());
+    assert!(composer_dir.join("ClassLoader.php").exists());
+    assert!(tmp.path().join("vendor/autoload.php").exists());
+}
+
+#[test]
+fn human_format_prints_summary() {
+    let tmp = TempDir::new().unwrap();
+    let pkg_dir = tmp.path().join("pkg");
+    fs::create_dir_all(&pkg_dir).unwrap();
+    fs::write(pkg_dir.join("composer.json"), "{}").unwrap();
+
+    let output = run_cli(&[
+        "vendor-check",
+        "--format",
+        "human",
+        "--package",
+        "acme/present",
+        &pkg_dir.to_string_lossy(),
+        "--package",
+        "acme/missing",
+        "/nonexistent/acme/missing",
+    ]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("1/2 packages present"), "got: {stdout}");
+    assert!(stdout.contains("missing: acme/missing"), "got: {stdout}");
+}
+
+#[test]
+fn batch_subcommand_reads_operations_file() {
+    let tmp = TempDir::new().unwrap();
+    let clean_dir = tmp.path().join("to_clean");
+    fs::create_dir_all(&clean_dir).unwrap();
+
+    let ops_file = tmp.path().join("ops.json");
+    let ops = serde_json::json!([{
+        "command": "clean",
+        "targets": [{ "path": clean_dir.to_string_lossy(), "name": "acme/old" }]
+    }]);
+    fs::write(&ops_file, ops.to_string()).unwrap();
+
+    let output = run_cli(&["batch", &ops_file.to_string_lossy()]);
+    assert!(output.status.success());
+    let result: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(
+        result["results"][0]["result"]["cleaned"].as_u64().unwrap(),
+        1
+    );
+    assert!(!clean_dir.exists());
+}
+
+#[test]
+fn classmap_from_composer_json_without_payload() {
+    let tmp = TempDir::new().unwrap();
+    let root = fs::canonicalize(tmp.path()).unwrap();
+    fs::create_dir_all(root.join("src")).unwrap();
+    fs::create_dir_all(root.join("vendor/acme/lib/src")).unwrap();
+    fs::create_dir_all(root.join("vendor/composer")).unwrap();
+    // Composer installed as a dependency provides the runtime files.
+    composer_runtime(&root.join("vendor/composer/composer"));
+
+    fs::write(
+        root.join("composer.json"),
+        r#"{"autoload": {"psr-4": {"App\\": "src/"}}, "config": {"autoloader-suffix": "native",
+            "apcu-autoloader": true, "apcu-autoloader-prefix": "acme_"}}"#,
+    )
+    .unwrap();
+    fs::write(
+        root.join("vendor/composer/installed.json"),
+        r#"{"packages": [{"name": "acme/lib", "install-path": "../acme/lib",
+            "autoload": {"psr-4": {"Acme\\Lib\\": "src/"}}}]}"#,
+    )
+    .unwrap();
+    fs::write(
+        root.join("src/Kernel.php"),
+        "<?php\nnamespace App;\nclass Kernel {}\n",
+    )
+    .unwrap();
+    fs::write(
+        root.join("vendor/acme/lib/src/Client.php"),
+        "<?php\nnamespace Acme\\Lib;\nclass Client {}\n",
+    )
+    .unwrap();
+
+    let input = serde_json::json!({
+        "command": "classmap",
+        "project_dir": root.to_string_lossy(),
+        "from_composer_json": true
+    });
+
+    let result = run_binary(&input.to_string());
+    // App\Kernel, Acme\Lib\Client and Composer\InstalledVersions.
+    assert_eq!(result["classmap_count"].as_u64().unwrap(), 3);
+    assert!(result["files_written"].as_bool().unwrap());
+
+    let static_file = fs::read_to_string(root.join("vendor/composer/autoload_static.php")).unwrap();
+    assert!(static_file.contains("ComposerStaticInitnative"));
+    assert!(static_file
+        .contains("'Acme\\\\Lib\\\\Client' => __DIR__ . '/..' . '/acme/lib/src/Client.php'"));
+    assert!(static_file.contains("'App\\\\Kernel' => __DIR__ . '/../..' . '/src/Kernel.php'"));
+    assert!(static_file.contains(
+        "'Composer\\\\InstalledVersions' => __DIR__ . '/..' . '/composer/InstalledVersions.php'"
+    ));
+
+    let installed = fs::read_to_string(root.join("vendor/composer/installed.php")).unwrap();
+    assert!(installed
+        .starts_with("<?php return array(\n    'root' => array(\n        'name' => '__root__',"));
+    assert!(installed.contains("'install_path' => __DIR__ . '/../acme/lib',"));
+    assert!(root.join("vendor/composer/ClassLoader.php").exists());
+    assert!(root.join("vendor/composer/InstalledVersions.php").exists());
+
+    let autoload_real = fs::read_to_string(root.join("vendor/composer/autoload_real.php")).unwrap();
+    assert!(autoload_real.contains("$loader->setApcuPrefix('acme_');"));
+}
+
+#[test]
+fn classmap_without_composer_runtime_fails_before_writing() {
+    let tmp = TempDir::new().unwrap();
+    fs::create_dir_all(tmp.path().join("src")).unwrap();
+    fs::write(
+        tmp.path().join("composer.json"),
+        r#"{"autoload": {"psr-4": {"App\\": "src/"}}}"#,
+    )
+    .unwrap();
+
+    let input = serde_json::json!({
+        "command": "classmap",
+        "project_dir": tmp.path().to_string_lossy(),
+        "from_composer_json": true
+    });
+    let output = run_binary_raw(input.to_string());
+    assert_eq!(output.status.code(), Some(5));
+    let response: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(response["error"]["code"], "not_found");
+    assert!(response["error"]["path"]
+        .as_str()
+        .unwrap()
+        .ends_with("vendor/composer/ClassLoader.php"));
+    assert!(!tmp
+        .path()
+        .join("vendor/composer/autoload_real.php")
+        .exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn serve_keeps_cache_in_memory_between_requests() {
+    use std::io::{BufRead, BufReader};
+    use std::os::unix::net::UnixStream;
+
+    let tmp = TempDir::new().unwrap();
+    let src_dir = tmp.path().join("src");
+    let target_dir = tmp.path().join("vendor/composer");
+    fs::create_dir_all(&src_dir).unwrap();
+    fs::write(
+        src_dir.join("Foo.php"),
+        "<?php\nnamespace App;\nclass Foo {}\n",
+    )
+    .unwrap();
+
+    let socket = tmp.path().join("turbo.sock");
+    let bin = cargo_bin();
+    let mut server = Command::new(&bin)
+        .args(["serve", "--socket", &socket.to_string_lossy()])
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    let mut stream = None;
+    for _ in 0..100 {
+        if let Ok(s) = UnixStream::connect(&socket) {
+            stream = Some(s);
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    let stream = stream.expect("server did not start listening");
+    let mut writer = stream.try_clone().unwrap();
+    let mut reader = BufReader::new(stream);
+
+    let mut request = |value: serde_json::Value| -> serde_json::Value {
+        writeln!(writer, "{value}").unwrap();
+        let mut line = String::new();
+        reader.read_line(&mut line).unwrap();
+        serde_json::from_str(&line).unwrap()
+    };
+
+    let classmap = serde_json::json!({
+        "command": "classmap",
+        "project_dir": tmp.path().to_string_lossy(),
+        "vendor_dir": tmp.path().join("vendor").to_string_lossy(),
+        "autoload": { "psr-4": [{ "namespace": "App\\", "path": src_dir.to_string_lossy() }] },
+        "target_dir": target_dir.to_string_lossy(),
+    });
+
+    let first = request(classmap.clone());
+    assert_eq!(first["classmap_count"].as_u64().unwrap(), 1);
+    assert!(first["stats"]["daemon"].as_bool().unwrap());
+    assert!(!first["stats"]["walk_skipped"].as_bool().unwrap());
+
+    let second = request(classmap);
+    assert_eq!(second["classmap_count"].as_u64().unwrap(), 1);
+    assert!(second["stats"]["walk_skipped"].as_bool().unwrap());
+
+    let pong = request(serde_json::json!({ "command": "ping" }));
+    assert!(pong["pong"].as_bool().unwrap());
+
+    let unknown = request(serde_json::json!({ "command": "frobnicate" }));
+    assert_eq!(unknown["error"]["code"], "unknown_command");
+
+    let bye = request(serde_json::json!({ "command": "shutdown" }));
+    assert!(bye["shutdown"].as_bool().unwrap());
+
+    assert!(server.wait().unwrap().success());
+    // Caches are flushed to disk on shutdown
+    assert!(target_dir.join(".turbo-cache").exists());
+    assert!(!socket.exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn serve_default_socket_is_private() {
+    use std::io::{BufRead, BufReader};
+    use std::os::unix::fs::{MetadataExt, PermissionsExt};
+    use std::os::unix::net::UnixStream;
+
+    let tmp = TempDir::new().unwrap();
+    let mut server = Command::new(cargo_bin())
+        .arg("serve")
+        .env_remove("XDG_RUNTIME_DIR")
+        .env("TMPDIR", tmp.path())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    // SAFETY: getuid has no preconditions and cannot fail.
+    let uid = unsafe { libc::getuid() };
+    let dir = tmp.path().join(format!("turbo-composer-{uid}"));
+    let socket = dir.join("turbo-composer.sock");
+    let mut stream = None;
+    for _ in 0..100 {
+        if let Ok(s) = UnixStream::connect(&socket) {
+            stream = Some(s);
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(50));
+    }
+    let stream = stream.expect("server did not start listening");
+
+    let dir_meta = fs::symlink_metadata(&dir).unwrap();
+    assert_eq!(dir_meta.uid(), uid);
+    assert_eq!(dir_meta.permissions().mode() & 0o777, 0o700);
+    let socket_meta = fs::symlink_metadata(&socket).unwrap();
+    assert_eq!(socket_meta.permissions().mode() & 0o777, 0o600);
+
+    let mut writer = stream.try_clone().unwrap();
+    writeln!(writer, "{}", serde_json::json!({ "command": "shutdown" })).unwrap();
+    let mut line = String::new();
+    BufReader::new(stream).read_line(&mut line).unwrap();
+    assert!(server.wait().unwrap().success());
+}
+
+#[cfg(unix)]
+#[test]
+fn serve_refuses_a_socket_dir_others_can_enter() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let tmp = TempDir::new().unwrap();
+    // SAFETY: getuid has no preconditions and cannot fail.
+    let uid = unsafe { libc::getuid() };
+    let dir = tmp.path().join(format!("turbo-composer-{uid}"));
+    fs::create_dir(&dir).unwrap();
+    fs::set_permissions(&dir, fs::Permissions::from_mode(0o777)).unwrap();
+
+    let output = Command::new(cargo_bin())
+        .arg("serve")
+        .env_remove("XDG_RUNTIME_DIR")
+        .env("TMPDIR", tmp.path())
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(6));
+    assert!(!dir.join("turbo-composer.sock").exists());
+}
+
+#[test]
+#[cfg(target_os = "linux")]
+fn watch_picks_up_new_class() {
+    use std::io::{BufRead, BufReader};
+
+    let tmp = TempDir::new().unwrap();
+    let src_dir = tmp.path().join("src");
+    fs::create_dir_all(&src_dir).unwrap();
+    fs::write(
+        src_dir.join("Foo.php"),
+        "<?php\nnamespace App;\nclass Foo {}\n",
+    )
+    .unwrap();
+
+    let mut watcher = Command::new(cargo_bin())
+        .args([
+            "watch",
+            "--project-dir",
+            &tmp.path().to_string_lossy(),
+            "--psr4",
+            "App\\=src",
+            "--suffix",
+            "abc",
+            "--runtime-dir",
+            &composer_runtime(&tmp.path().join("composer")),
+        ])
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+    let mut lines = BufReader::new(watcher.stdout.take().unwrap()).lines();
+
+    let initial: serde_json::Value = serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap();
+    assert_eq!(initial["event"], "initial");
+    assert_eq!(initial["classmap_count"].as_u64().unwrap(), 1);
+
+    fs::write(
+        src_dir.join("Bar.php"),
+        "<?php\nnamespace App;\nclass Bar {}\n",
+    )
+    .unwrap();
+
+    let update: serde_json::Value = serde_json::from_str(&lines.next().unwrap().unwrap()).unwrap();
+    watcher.kill().unwrap();
+    let _ = watcher.wait();
+
+    assert_eq!(update["event"], "update");
+    assert_eq!(update["classes_added"], serde_json::json!(["App\\Bar"]));
+    assert_eq!(update["classmap_count"].as_u64().unwrap(), 2);
+    let static_file =
+        fs::read_to_string(tmp.path().join("vendor/composer/autoload_static.php")).unwrap();
+    assert!(static_file.contains("App\\\\Bar"));
+}
+
+#[test]
+fn invalid_json_returns_error_envelope() {
+    let output = run_binary_raw("{not json");
+    assert_eq!(output.status.code(), Some(3));
+    let response: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(response["error"]["code"], "invalid_request");
+    assert!(response["error"]["message"]
+        .as_str()
+        .unwrap()
+        .contains("failed to parse input JSON"));
+}
+
+#[test]
+fn unknown_command_via_stdin_returns_error_envelope() {
+    let output = run_binary_raw(r#"{"command":"frobnicate"}"#);
+    assert_eq!(output.status.code(), Some(4));
+    let response: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(response["error"]["code"], "unknown_command");
+    assert_eq!(response["error"]["operation"], "frobnicate");
+}
+
+#[test]
+fn classmap_write_failure_reports_path() {
+    let tmp = TempDir::new().unwrap();
+    let blocker = tmp.path().join("not-a-dir");
+    fs::write(&blocker, "").unwrap();
+    let target_dir = blocker.join("composer");
+
+    let input = serde_json::json!({
+        "command": "classmap",
+        "project_dir": tmp.path().to_string_lossy(),
+        "vendor_dir": tmp.path().join("vendor").to_string_lossy(),
+        "autoload": { "classmap": [] },
+        "target_dir": target_dir.to_string_lossy(),
+    });
+    let output = run_binary_raw(input.to_string());
+
+    assert_eq!(output.status.code(), Some(8));
+    let response: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(response["error"]["code"], "io");
+    assert_eq!(response["error"]["operation"], "classmap");
+    assert_eq!(
+        response["error"]["path"].as_str().unwrap(),
+        target_dir.to_string_lossy()
+    );
+}
+
+#[test]
+fn capabilities_lists_supported_features() {
+    let result = run_binary(r#"{"command":"capabilities","protocol_version":1}"#);
+    assert_eq!(result["protocol_version"], 1);
+    assert!(result["min_protocol_version"].as_u64().unwrap() <= 1);
+    let list = |key: &str| -> Vec<String> {
+        result[key]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|v| v.as_str().unwrap().to_string())
+            .collect()
+    };
+    assert!(list("commands").contains(&"classmap".to_string()));
+    assert!(list("commands").contains(&"capabilities".to_string()));
+    assert!(list("archive_formats").contains(&"tar.gz".to_string()));
+    assert_eq!(list("hash_algorithms"), vec!["sha256", "sha1"]);
+    assert!(list("classmap_options").contains(&"staging_suffix".to_string()));
+}
+
+#[test]
+fn responses_carry_protocol_version() {
+    let result = run_binary(r#"{"command":"vendor-check","check_packages":[]}"#);
+    assert_eq!(result["protocol_version"], 1);
+}
+
+#[test]
+fn newer_protocol_version_is_rejected() {
+    let output = run_binary_raw(r#"{"command":"clean","protocol_version":999}"#);
+    assert_eq!(output.status.code(), Some(10));
+    let response: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(response["error"]["code"], "incompatible_protocol");
+    assert_eq!(response["protocol_version"], 1);
+}
+
+#[test]
+fn batch_skips_dependents_of_failed_operation() {
+    let tmp = TempDir::new().unwrap();
+    let archive = tmp.path().join("pkg.zip");
+    fs::write(&archive, "not really a zip").unwrap();
+    let old_dir = tmp.path().join("old");
+    let other_dir = tmp.path().join("other");
+    fs::create_dir_all(&old_dir).unwrap();
+    fs::create_dir_all(&other_dir).unwrap();
+
+    let input = serde_json::json!({
+        "command": "batch",
+        "on_error": "skip_dependents",
+        "operations": [
+            {
+                "id": "verify",
+                "depends_on": [],
+                "command": "verify",
+                "verify_targets": [{
+                    "path": archive.to_string_lossy(),
+                    "name": "acme/pkg",
+                    "algorithm": "sha256",
+                    "expected_hash": "0000"
+                }]
+            },
+            {
+                "id": "clean-old",
+                "depends_on": ["verify"],
+                "command": "clean",
+                "targets": [{ "path": old_dir.to_string_lossy(), "name": "acme/pkg" }]
+            },
+            {
+                "id": "clean-other",
+                "depends_on": [],
+                "command": "clean",
+                "targets": [{ "path": other_dir.to_string_lossy(), "name": "acme/other" }]
+            }
+        ]
+    });
+
+    let result = run_binary(&input.to_string());
+    let results = result["results"].as_array().unwrap();
+    assert_eq!(results[0]["status"], "failed");
+    assert_eq!(results[1]["id"], "clean-old");
+    assert_eq!(results[1]["status"], "skipped");
+    assert_eq!(results[2]["status"], "ok");
+    assert!(results[2]["elapsed_ms"].is_u64());
+    assert!(results[2]["started_ms"].is_u64());
+
+    assert!(old_dir.exists());
+    assert!(!other_dir.exists());
+}
+
+#[test]
+fn batch_abort_stops_after_first_failure() {
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path().join("keep");
+    fs::create_dir_all(&dir).unwrap();
+
+    let input = serde_json::json!({
+        "command": "batch",
+        "on_error": "abort",
+        "operations": [
+            { "command": "frobnicate" },
+            { "command": "clean", "targets": [{ "path": dir.to_string_lossy(), "name": "x" }] }
+        ]
+    });
+
+    let result = run_binary(&input.to_string());
+    assert_eq!(result["aborted"], true);
+    assert_eq!(result["results"][0]["status"], "failed");
+    assert_eq!(result["results"][0]["error"]["code"], "unknown_command");
+    assert_eq!(result["results"][1]["status"], "skipped");
+    assert!(dir.exists());
+}
+
+#[test]
+fn batch_rejects_dependency_cycles() {
+    let input = serde_json::json!({
+        "command": "batch",
+        "operations": [
+            { "id": "a", "depends_on": ["b"], "command": "vendor-check" },
+            { "id": "b", "depends_on": ["a"], "command": "vendor-check" }
+        ]
+    });
+
+    let output = run_binary_raw(input.to_string());
+    assert_eq!(output.status.code(), Some(3));
+    let response: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert!(response["error"]["message"]
+        .as_str()
+        .unwrap()
+        .contains("dependency cycle"));
+}
+
+fn write_zip(path: &std::path::Path, files: &[(&str, &[u8])]) {
+    let file = fs::File::create(path).unwrap();
+    let mut zip_writer = zip::ZipWriter::new(file);
+    let options =
+        zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+    for (name, content) in files {
+        zip_writer.start_file(*name, options).unwrap();
+        zip_writer.write_all(content).unwrap();
+    }
+    zip_writer.finish().unwrap();
+}
+
+#[test]
+fn transactional_batch_rolls_back_on_failure() {
+    let tmp = TempDir::new().unwrap();
+    let vendor = tmp.path().join("vendor");
+    let old_pkg = vendor.join("acme/old");
+    let updated_pkg = vendor.join("acme/updated");
+    fs::create_dir_all(&old_pkg).unwrap();
+    fs::create_dir_all(&updated_pkg).unwrap();
+    fs::write(old_pkg.join("old.txt"), "old").unwrap();
+    fs::write(updated_pkg.join("v1.txt"), "v1").unwrap();
+
+    let good = tmp.path().join("good.zip");
+    write_zip(&good, &[("pkg/v2.txt", b"v2")]);
+    let bad = tmp.path().join("bad.zip");
+    fs::write(&bad, "not really a zip").unwrap();
+
+    let input = serde_json::json!({
+        "command": "batch",
+        "transactional": true,
+        "vendor_dir": vendor.to_string_lossy(),
+        "operations": [
+            {
+                "command": "clean",
+                "targets": [{ "path": old_pkg.to_string_lossy(), "name": "acme/old" }]
+            },
+            {
+                "command": "extract",
+                "packages": [
+                    { "zip": good.to_string_lossy(), "dest": updated_pkg.to_string_lossy(), "name": "acme/updated" },
+                    { "zip": bad.to_string_lossy(), "dest": vendor.join("acme/new").to_string_lossy(), "name": "acme/new" }
+                ]
+            }
+        ]
+    });
+
+    let result = run_binary(&input.to_string());
+    assert_eq!(result["transaction"], "rolled_back");
+    assert_eq!(result["results"][1]["status"], "failed");
+
+    assert_eq!(fs::read_to_string(old_pkg.join("old.txt")).unwrap(), "old");
+    assert!(updated_pkg.join("v1.txt").exists());
+    assert!(!updated_pkg.join("v2.txt").exists());
+    assert!(!vendor.join("acme/new").exists());
+    assert!(!vendor.join(".turbo-trash").exists());
+}
+
+#[test]
+fn transactional_batch_commits_when_all_succeed() {
+    let tmp = TempDir::new().unwrap();
+    let vendor = tmp.path().join("vendor");
+    let old_pkg = vendor.join("acme/old");
+    let updated_pkg = vendor.join("acme/updated");
+    fs::create_dir_all(&old_pkg).unwrap();
+    fs::create_dir_all(&updated_pkg).unwrap();
+    fs::write(updated_pkg.join("v1.txt"), "v1").unwrap();
+
+    let good = tmp.path().join("good.zip");
+    write_zip(&good, &[("pkg/v2.txt", b"v2")]);
+
+    let input = serde_json::json!({
+        "command": "batch",
+        "transactional": true,
+        "vendor_dir": vendor.to_string_lossy(),
+        "operations": [
+            {
+                "command": "clean",
+                "targets": [{ "path": old_pkg.to_string_lossy(), "name": "acme/old" }]
+            },
+            {
+                "command": "extract",
+                "packages": [
+                    { "zip": good.to_string_lossy(), "dest": updated_pkg.to_string_lossy(), "name": "acme/updated" }
+                ]
+            }
+        ]
+    });
+
+    let result = run_binary(&input.to_string());
+    assert_eq!(result["transaction"], "committed");
+    assert!(!old_pkg.exists());
+    assert!(!updated_pkg.join("v1.txt").exists());
+    assert_eq!(
+        fs::read_to_string(updated_pkg.join("v2.txt")).unwrap(),
+        "v2"
+    );
+    assert!(!vendor.join(".turbo-trash").exists());
+}
+
+#[test]
+fn recover_without_journal_is_a_no_op() {
+    let tmp = TempDir::new().unwrap();
+    let input = serde_json::json!({
+        "command": "recover",
+        "vendor_dir": tmp.path().to_string_lossy(),
+    });
+    let result = run_binary(&input.to_string());
+    assert!(result["recovered"].is_null());
+}
+
+#[test]
+fn classmap_writes_chrome_trace() {
+    let tmp = TempDir::new().unwrap();
+    let src_dir = tmp.path().join("src");
+    fs::create_dir_all(&src_dir).unwrap();
+    fs::write(
+        src_dir.join("User.php"),
+        "<?php\nnamespace App;\n\nclass User {}\n",
+    )
+    .unwrap();
+    let trace_file = tmp.path().join("trace.json");
+
+    let input = serde_json::json!({
+        "command": "classmap",
+        "project_dir": tmp.path().to_string_lossy(),
+        "vendor_dir": tmp.path().join("vendor").to_string_lossy(),
+        "target_dir": tmp.path().join("vendor/composer").to_string_lossy(),
+        "autoload": {
+            "psr-4": [{ "namespace": "App\\", "path": src_dir.to_string_lossy() }]
+        },
+        "trace_file": trace_file.to_string_lossy(),
+    });
+    run_binary(&input.to_string());
+
+    let trace: serde_json::Value = serde_json::from_slice(&fs::read(&trace_file).unwrap()).unwrap();
+    let events = trace["traceEvents"].as_array().unwrap();
+    let names: Vec<&str> = events.iter().filter_map(|e| e["name"].as_str()).collect();
+    for expected in [
+        "stdin_read",
+        "json_parse",
+        "cache_load",
+        "walk_directories",
+        "parse_file",
+        "psr_filter",
+        "generate_classmap_file",
+        "write_file",
+        "cache_save",
+    ] {
+        assert!(names.contains(&expected), "missing {expected} in {names:?}");
+    }
+    assert!(events.iter().all(|e| e["ph"] == "X" && e["ts"].is_number()));
+}
+
+#[test]
+fn resource_limits_are_accepted_by_every_stage() {
+    let tmp = TempDir::new().unwrap();
+    let mut packages = Vec::new();
+    let mut verify_targets = Vec::new();
+    for i in 0..4 {
+        let archive = tmp.path().join(format!("pkg{i}.zip"));
+        write_zip(&archive, &[("pkg/src/Foo.php", b"<?php\nclass Foo {}\n")]);
+        packages.push(serde_json::json!({
+            "zip": archive.to_string_lossy(),
+            "dest": tmp.path().join(format!("vendor/acme/pkg{i}")).to_string_lossy(),
+            "name": format!("acme/pkg{i}"),
+        }));
+        verify_targets.push(serde_json::json!({
+            "path": archive.to_string_lossy(),
+            "name": format!("acme/pkg{i}"),
+            "algorithm": "sha1",
+            "expected_hash": "0000",
+        }));
+    }
+
+    let input = serde_json::json!({
+        "command": "batch",
+        "threads": 1,
+        "io_concurrency": 1,
+        "memory_budget_mb": 1,
+        "operations": [
+            { "command": "verify", "verify_targets": verify_targets },
+            { "command": "extract", "packages": packages, "threads": 2 },
+            {
+                "command": "classmap",
+                "project_dir": tmp.path().to_string_lossy(),
+                "vendor_dir": tmp.path().join("vendor").to_string_lossy(),
+                "autoload": { "classmap": [tmp.path().join("vendor").to_string_lossy()] },
+                "write_files": false
+            }
+        ]
+    });
+
+    let result = run_binary(&input.to_string());
+    let results = result["results"].as_array().unwrap();
+    assert_eq!(results[0]["result"]["failed"].as_array().unwrap().len(), 4);
+    assert_eq!(results[1]["result"]["extracted"], 4);
+    assert_eq!(results[2]["result"]["classmap_count"], 1);
+}
+
+#[test]
+fn classmap_dry_run_reports_changes_and_writes_nothing() {
+    let tmp = TempDir::new().unwrap();
+    let src_dir = tmp.path().join("src");
+    fs::create_dir_all(&src_dir).unwrap();
+    fs::write(
+        src_dir.join("User.php"),
+        "<?php\nnamespace App;\n\nclass User {}\n",
+    )
+    .unwrap();
+    let target_dir = tmp.path().join("vendor/composer");
+    let runtime = composer_runtime(&tmp.path().join("composer"));
+    let request = |dry_run: bool| {
+        serde_json::json!({
+            "command": "classmap",
+            "project_dir": tmp.path().to_string_lossy(),
+            "vendor_dir": tmp.path().join("vendor").to_string_lossy(),
+            "target_dir": target_dir.to_string_lossy(),
+            "suffix": "abc",
+            "runtime_dir": runtime,
+            "autoload": {
+                "psr-4": [{ "namespace": "App\\", "path": src_dir.to_string_lossy() }]
+            },
+            "dry_run": dry_run,
+        })
+        .to_string()
+    };
+
+    let output = run_binary(&request(true));
+    assert_eq!(output["dry_run"], true);
+    assert_eq!(output["files_written"], false);
+    assert_eq!(output["would_change"].as_array().unwrap().len(), 8);
+    assert!(!tmp.path().join("vendor").exists());
+
+    run_binary(&request(false));
+    let output = run_binary(&request(true));
+    assert_eq!(output["would_change"], serde_json::json!([]));
+
+    // The directory cache compares mtimes with one-second resolution.
+    std::thread::sleep(std::time::Duration::from_secs(1));
+    fs::write(
+        src_dir.join("Post.php"),
+        "<?php\nnamespace App;\n\nclass Post {}\n",
+    )
+    .unwrap();
+    let output = run_binary(&request(true));
+    assert_eq!(
+        output["would_change"],
+        serde_json::json!(["autoload_classmap.php", "autoload_static.php"])
+    );
+}
+
+#[test]
+fn batch_dry_run_leaves_vendor_untouched() {
+    let tmp = TempDir::new().unwrap();
+    let vendor = tmp.path().join("vendor");
+    let old = vendor.join("acme/old");
+    fs::create_dir_all(&old).unwrap();
+    fs::write(old.join("file.txt"), "12345").unwrap();
+    let zip_path = tmp.path().join("new.zip");
+    write_zip(&zip_path, &[("src/A.php", b"<?php\n")]);
+
+    let input = serde_json::json!({
+        "command": "batch",
+        "transactional": true,
+        "vendor_dir": vendor.to_string_lossy(),
+        "dry_run": true,
+        "operations": [
+            { "command": "clean", "targets": [{ "name": "acme/old", "path": old.to_string_lossy() }] },
+            { "command": "extract", "packages": [{
+                "name": "acme/new",
+                "zip": zip_path.to_string_lossy(),
+                "dest": vendor.join("acme/new").to_string_lossy(),
+            }] },
+        ],
+    });
+    let output = run_binary(&input.to_string());
+    let results = output["results"].as_array().unwrap();
+    assert_eq!(results[0]["result"]["total_bytes"], 5);
+    let files = &results[1]["result"]["packages"][0]["files"];
+    assert_eq!(files[0]["path"], "A.php");
+    assert_eq!(files[0]["action"], "create");
+
+    assert!(old.join("file.txt").exists());
+    assert!(!vendor.join("acme/new").exists());
+    assert!(!vendor.join(".turbo-trash").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn msgpack_request_handles_non_utf8_paths() {
+    use std::os::unix::ffi::OsStrExt;
+    use std::path::PathBuf;
+
+    #[derive(serde::Serialize)]
+    struct Target {
+        name: &'static str,
+        #[serde(with = "turbo_composer::encoding::path")]
+        path: PathBuf,
+    }
+    #[derive(serde::Serialize)]
+    struct Request {
+        command: &'static str,
+        dry_run: bool,
+        targets: Vec<Target>,
+    }
+
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path().join(std::ffi::OsStr::from_bytes(b"caf\xe9-pkg"));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("file.txt"), "abc").unwrap();
+    let request = |dry_run| {
+        rmp_serde::to_vec_named(&Request {
+            command: "clean",
+            dry_run,
+            targets: vec![Target {
+                name: "acme/cafe",
+                path: dir.clone(),
+            }],
+        })
+        .unwrap()
+    };
+
+    let output = run_binary_raw(request(true));
+    assert!(output.status.success());
+    let response: serde_json::Value = rmp_serde::from_slice(&output.stdout).unwrap();
+    assert_eq!(response["total_bytes"], 3);
+    let echoed: Vec<u8> =
+        serde_json::from_value(response["would_remove"][0]["path"].clone()).unwrap();
+    assert_eq!(echoed, dir.as_os_str().as_bytes());
+
+    let output = run_binary_raw(request(false));
+    let response: serde_json::Value = rmp_serde::from_slice(&output.stdout).unwrap();
+    assert_eq!(response["cleaned"], 1);
+    assert!(!dir.exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn msgpack_response_returns_non_utf8_paths_as_byte_arrays() {
+    use std::os::unix::ffi::OsStrExt;
+
+    let tmp = TempDir::new().unwrap();
+    let dir = tmp.path().join(std::ffi::OsStr::from_bytes(b"caf\xe9-pkg"));
+    fs::create_dir_all(&dir).unwrap();
+    let path: Vec<u8> = dir.as_os_str().as_bytes().to_vec();
+    let request = rmp_serde::to_vec_named(&serde_json::json!({
+        "command": "clean",
+        "dry_run": true,
+        "targets": [{"name": "acme/cafe", "path": path}],
+    }))
+    .unwrap();
+
+    let output = run_binary_raw(request);
+    assert!(output.status.success());
+
+    // On the wire, the value after the "path" key is an array (fixarray or
+    // array 16/32), not a bin (0xc4-0xc6).
+    let key = b"\xa4path";
+    let at = output
+        .stdout
+        .windows(key.len())
+        .position(|w| w == key)
+        .expect("response has a path");
+    let marker = output.stdout[at + key.len()];
+    assert!(
+        matches!(marker, 0x90..=0x9f | 0xdc | 0xdd),
+        "path marker {marker:#04x}"
+    );
+
+    let response: serde_json::Value = rmp_serde::from_slice(&output.stdout).unwrap();
+    let echoed = response["would_remove"][0]["path"].as_array().unwrap();
+    let echoed: Vec<u8> = echoed.iter().map(|b| b.as_u64().unwrap() as u8).collect();
+    assert_eq!(echoed, path);
+}
+
+#[test]
+fn msgpack_classmap_accepts_root_package_from_php() {
+    let tmp = TempDir::new().unwrap();
+    let target_dir = tmp.path().join("vendor/composer");
+    fs::create_dir_all(&target_dir).unwrap();
+
+    // msgpack_pack() sends an empty PHP array as an array, not a map.
+    let request = rmp_serde::to_vec_named(&serde_json::json!({
+        "command": "classmap",
+        "project_dir": tmp.path().to_string_lossy(),
+        "vendor_dir": tmp.path().join("vendor").to_string_lossy(),
+        "target_dir": target_dir.to_string_lossy(),
+        "runtime_dir": composer_runtime(&tmp.path().join("composer")),
+        "root_package": {
+            "name": "acme/app",
+            "pretty_version": "1.0.0",
+            "version": "1.0.0.0",
+            "type": "project",
+            "aliases": [],
+            "require": {"php": "^8.1"},
+            "replace": {"acme/legacy": "self.version"},
+            "provide": [],
+        },
+    }))
+    .unwrap();
+
+    let output = run_binary_raw(request);
+    assert!(
+        output.status.success(),
+        "stderr: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let response: serde_json::Value = rmp_serde::from_slice(&output.stdout).unwrap();
+    let installed = response["installed_file_content"].as_str().unwrap();
+    assert!(installed.contains("'acme/legacy' => array("), "{installed}");
+    assert!(installed.contains("'replaced' => array("), "{installed}");
+}
+
+#[cfg(unix)]
+#[test]
+fn classmap_rejects_non_utf8_php_paths() {
+    use std::os::unix::ffi::OsStrExt;
+
+    let tmp = TempDir::new().unwrap();
+    let src_dir = tmp.path().join("src");
+    fs::create_dir_all(&src_dir).unwrap();
+    fs::write(
+        src_dir.join(std::ffi::OsStr::from_bytes(b"Caf\xe9.php")),
+        "<?php\nnamespace App;\nclass Cafe {}\n",
+    )
+    .unwrap();
+
+    let input = serde_json::json!({
+        "command": "classmap",
+        "project_dir": tmp.path().to_string_lossy(),
+        "vendor_dir": tmp.path().join("vendor").to_string_lossy(),
+        "autoload": { "classmap": [src_dir.to_string_lossy()] },
+    });
+    let output = run_binary_raw(input.to_string());
+    assert_eq!(output.status.code(), Some(3));
+    let response: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(response["error"]["code"], "invalid_request");
+    assert!(response["error"]["message"]
+        .as_str()
+        .unwrap()
+        .contains("must be valid UTF-8"));
+}
+
+#[test]
+fn msgpack_errors_are_msgpack_encoded() {
+    let request = rmp_serde::to_vec_named(&serde_json::json!({"command": "frobnicate"})).unwrap();
+    let output = run_binary_raw(request);
+    assert_eq!(output.status.code(), Some(4));
+    let response: serde_json::Value = rmp_serde::from_slice(&output.stdout).unwrap();
+    assert_eq!(response["error"]["code"], "unknown_command");
+}
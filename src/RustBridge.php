@@ -6,15 +6,31 @@ namespace TurboComposer;
 
 use Composer\Composer;
 use Composer\IO\IOInterface;
+use FFI;
 
+use function class_exists;
 use function fclose;
+use function fgets;
+use function file_exists;
+use function function_exists;
 use function fwrite;
+use function getenv;
+use function in_array;
+use function is_array;
+use function is_int;
 use function is_resource;
+use function is_string;
 use function json_decode;
 use function json_encode;
+use function msgpack_pack;
+use function msgpack_unpack;
 use function proc_close;
 use function proc_open;
 use function stream_get_contents;
+use function stream_get_meta_data;
+use function stream_set_timeout;
+use function stream_socket_client;
+use function strlen;
 use function trim;
 
 use const JSON_THROW_ON_ERROR;
@@ -23,6 +39,29 @@ use const JSON_UNESCAPED_UNICODE;
 
 class RustBridge
 {
+    /**
+     * Request/response protocol spoken by this plugin. Sent with every request
+     * so a binary that cannot serve it refuses with `incompatible_protocol`.
+     */
+    public const PROTOCOL_VERSION = 1;
+
+    /** Commands every binary understands, even ones without `capabilities`. */
+    private const BASELINE_COMMANDS = ['classmap', 'extract', 'verify', 'clean', 'vendor-check', 'batch'];
+
+    /**
+     * How long to wait for a `serve` response. A cold classmap on a large
+     * monorepo can run for minutes, far past `default_socket_timeout`, and a
+     * spawned binary is not timed out either.
+     */
+    private const SERVER_TIMEOUT_SECONDS = 3600;
+
+    /** `extra.turbo-composer` settings forwarded with every request, by request field. */
+    private const RESOURCE_LIMITS = [
+        'threads' => 'threads',
+        'io-concurrency' => 'io_concurrency',
+        'memory-budget-mb' => 'memory_budget_mb',
+    ];
+
     private Composer $composer;
     private IOInterface $io;
     private ?string $fallbackDir;
@@ -30,6 +69,25 @@ class RustBridge
     private null|false|string $binaryPath = null;
     private bool $resolved = false;
 
+    /**
+     * The `error` object of the last failed request, if the binary reported one.
+     *
+     * @var array{code: string, message: string, path: ?string, operation: ?string}|null
+     */
+    private ?array $lastError = null;
+
+    private ?array $capabilities = null;
+
+    /** The loaded `library`, or false once loading it has failed. */
+    private null|false|FFI $ffi = null;
+
+    /** C declarations of the library's `turbo_dispatch` ABI. */
+    private const FFI_CDEF = <<<'C'
+        typedef struct { uint8_t *ptr; size_t len; } turbo_buffer;
+        turbo_buffer turbo_dispatch(const char *request, size_t len);
+        void turbo_free(turbo_buffer buffer);
+        C;
+
     public function __construct(Composer $composer, IOInterface $io, ?string $fallbackDir = null)
     {
         $this->composer = $composer;
@@ -52,6 +110,54 @@ class RustBridge
         return $this->binaryPath !== false;
     }
 
+    /**
+     * The structured error from the last request that failed, or null if it
+     * succeeded or the failure was not reported by the binary (crash, bad output).
+     *
+     * @return array{code: string, message: string, path: ?string, operation: ?string}|null
+     */
+    public function lastError(): ?array
+    {
+        return $this->lastError;
+    }
+
+    /**
+     * Whether Composer's own PHP implementation is worth trying after the last
+     * failure. A full disk or a permission problem would fail there as well,
+     * and Composer's generator would quietly skip what `strict-psr` rejected.
+     */
+    public function canFallBack(): bool
+    {
+        return !in_array(
+            $this->lastError['code'] ?? null,
+            ['disk_full', 'permission_denied', 'psr_violation'],
+            true,
+        );
+    }
+
+    /**
+     * What the binary supports, as reported by its `capabilities` command.
+     * Binaries that predate the command report only the baseline commands.
+     *
+     * @return array{commands: list<string>, archive_formats?: list<string>, hash_algorithms?: list<string>, classmap_options?: list<string>, encodings?: list<string>}
+     */
+    public function capabilities(): array
+    {
+        if ($this->capabilities === null) {
+            $result = $this->run(['command' => 'capabilities']);
+            $this->capabilities = is_array($result['commands'] ?? null)
+                ? $result
+                : ['commands' => self::BASELINE_COMMANDS];
+        }
+
+        return $this->capabilities;
+    }
+
+    public function supports(string $command): bool
+    {
+        return in_array($command, $this->capabilities()['commands'], true);
+    }
+
     public function run(array $payload): ?array
     {
         $collect = $this->startAsync($payload);
@@ -66,13 +172,28 @@ class RustBridge
      */
     public function startAsync(array $payload): ?callable
     {
+        $this->lastError = null;
+        $payload['protocol_version'] ??= self::PROTOCOL_VERSION;
+        $payload += $this->resourceLimits();
+
+        $collect = $this->startOnServer($payload) ?? $this->startInProcess($payload);
+        if ($collect !== null) {
+            return $collect;
+        }
+
         $this->resolve();
 
         if ($this->binaryPath === false) {
             return null;
         }
 
-        $json = json_encode($payload, JSON_UNESCAPED_SLASHES | JSON_UNESCAPED_UNICODE | JSON_THROW_ON_ERROR);
+        // MessagePack avoids JSON escaping on large classmap responses. Only
+        // used once `capabilities` has shown the binary speaks it.
+        $msgpack = function_exists('msgpack_pack')
+            && in_array('msgpack', $this->capabilities['encodings'] ?? [], true);
+        $request = $msgpack
+            ? msgpack_pack(self::msgpackPayload($payload))
+            : json_encode($payload, JSON_UNESCAPED_SLASHES | JSON_UNESCAPED_UNICODE | JSON_THROW_ON_ERROR);
 
         $descriptors = [
             0 => ['pipe', 'r'],
@@ -86,12 +207,13 @@ class RustBridge
             return null;
         }
 
-        fwrite($pipes[0], $json);
+        fwrite($pipes[0], $request);
         fclose($pipes[0]);
 
         $io = $this->io;
+        $lastError = &$this->lastError;
 
-        return static function () use ($proc, $pipes, $io): ?array {
+        return static function () use ($proc, $pipes, $io, $msgpack, &$lastError): ?array {
             $stdout = stream_get_contents($pipes[1]);
             $stderr = stream_get_contents($pipes[2]);
             fclose($pipes[1]);
@@ -104,10 +226,22 @@ class RustBridge
             }
 
             if ($exit !== 0) {
-                $io->writeError("<warning>turbo-composer:</warning> Binary failed (exit {$exit}): {$stderr}");
+                $lastError = self::decodeError((string) $stdout, $msgpack);
+                $reason = $lastError !== null ? "{$lastError['code']}: {$lastError['message']}" : $stderr;
+                $io->writeError("<warning>turbo-composer:</warning> Binary failed (exit {$exit}): {$reason}");
                 return null;
             }
 
+            if ($msgpack) {
+                $result = msgpack_unpack((string) $stdout);
+                if (!is_array($result)) {
+                    $io->writeError('<warning>turbo-composer:</warning> Could not parse binary output as MessagePack.');
+                    return null;
+                }
+
+                return $result;
+            }
+
             try {
                 return json_decode($stdout, associative: true, flags: JSON_THROW_ON_ERROR);
             } catch (\JsonException $e) {
@@ -119,6 +253,244 @@ class RustBridge
         };
     }
 
+    /**
+     * Objects in the payload (maps cast to `(object)` so JSON keeps them
+     * objects when empty) turned back into arrays: with `msgpack.php_only`
+     * on, msgpack_pack() packs objects in a PHP-specific form Rust cannot
+     * read. An empty one then packs as an array, which Rust accepts as an
+     * empty map.
+     */
+    private static function msgpackPayload(array $payload): array
+    {
+        foreach ($payload as $key => $value) {
+            if ($value instanceof \stdClass) {
+                $value = (array) $value;
+            }
+            if (is_array($value)) {
+                $payload[$key] = self::msgpackPayload($value);
+            }
+        }
+
+        return $payload;
+    }
+
+    /**
+     * Send the request to a running `turbo-composer serve` process, which keeps
+     * classmap caches in memory between runs. Returns null when no server is
+     * configured or reachable, in which case the binary is spawned as usual.
+     *
+     * @return (callable(): ?array)|null
+     */
+    private function startOnServer(array $payload): ?callable
+    {
+        $socket = $this->socketPath();
+        if ($socket === null || !file_exists($socket)) {
+            return null;
+        }
+
+        $conn = @stream_socket_client('unix://' . $socket, $errno, $errstr, 1.0);
+        if ($conn === false) {
+            $this->io->write(
+                "<info>turbo-composer:</info> Server at {$socket} unavailable ({$errstr}), spawning binary.",
+                true,
+                IOInterface::VERBOSE,
+            );
+            return null;
+        }
+
+        stream_set_timeout($conn, self::SERVER_TIMEOUT_SECONDS);
+        $json = json_encode($payload, JSON_UNESCAPED_SLASHES | JSON_UNESCAPED_UNICODE | JSON_THROW_ON_ERROR);
+        fwrite($conn, $json . "\n");
+
+        $io = $this->io;
+        $lastError = &$this->lastError;
+
+        return static function () use ($conn, $io, &$lastError): ?array {
+            $line = fgets($conn);
+            $timedOut = stream_get_meta_data($conn)['timed_out'];
+            fclose($conn);
+
+            if ($line === false) {
+                $io->writeError(
+                    $timedOut
+                        ? '<warning>turbo-composer:</warning> Server did not respond within '
+                            . self::SERVER_TIMEOUT_SECONDS . ' seconds.'
+                        : '<warning>turbo-composer:</warning> Server closed the connection without a response.',
+                );
+                return null;
+            }
+
+            try {
+                $result = json_decode($line, associative: true, flags: JSON_THROW_ON_ERROR);
+            } catch (\JsonException $e) {
+                $io->writeError(
+                    '<warning>turbo-composer:</warning> Could not parse server output as JSON: ' . $e->getMessage(),
+                );
+                return null;
+            }
+
+            if (isset($result['error'])) {
+                $lastError = self::decodeError($line);
+                $reason = $lastError !== null
+                    ? "{$lastError['code']}: {$lastError['message']}"
+                    : json_encode($result['error']);
+                $io->writeError("<warning>turbo-composer:</warning> Server failed: {$reason}");
+                return null;
+            }
+
+            return $result;
+        };
+    }
+
+    /**
+     * Run the request in-process through the `turbo_dispatch` C ABI of the
+     * shared library, avoiding a process spawn. Returns null when no library
+     * is configured, the FFI extension is missing or the library fails to
+     * load, in which case the binary is spawned as usual.
+     *
+     * @return (callable(): ?array)|null
+     */
+    private function startInProcess(array $payload): ?callable
+    {
+        $ffi = $this->loadLibrary();
+        if ($ffi === null) {
+            return null;
+        }
+
+        $json = json_encode($payload, JSON_UNESCAPED_SLASHES | JSON_UNESCAPED_UNICODE | JSON_THROW_ON_ERROR);
+        $buffer = $ffi->turbo_dispatch($json, strlen($json));
+        $response = FFI::string($buffer->ptr, $buffer->len);
+        $ffi->turbo_free($buffer);
+
+        try {
+            $result = json_decode($response, associative: true, flags: JSON_THROW_ON_ERROR);
+        } catch (\JsonException $e) {
+            $this->io->writeError(
+                '<warning>turbo-composer:</warning> Could not parse library output as JSON: ' . $e->getMessage(),
+            );
+            return static fn (): ?array => null;
+        }
+
+        if (isset($result['error'])) {
+            $this->lastError = self::decodeError($response);
+            $reason = $this->lastError !== null
+                ? "{$this->lastError['code']}: {$this->lastError['message']}"
+                : json_encode($result['error']);
+            $this->io->writeError("<warning>turbo-composer:</warning> Library call failed: {$reason}");
+            return static fn (): ?array => null;
+        }
+
+        return static fn (): ?array => $result;
+    }
+
+    private function loadLibrary(): ?FFI
+    {
+        if ($this->ffi === null) {
+            $path = $this->libraryPath();
+            if ($path === null || !class_exists(FFI::class) || !file_exists($path)) {
+                $this->ffi = false;
+                return null;
+            }
+
+            try {
+                $this->ffi = FFI::cdef(self::FFI_CDEF, $path);
+            } catch (\FFI\Exception $e) {
+                $this->io->write(
+                    "<info>turbo-composer:</info> Could not load {$path} ({$e->getMessage()}), spawning binary.",
+                    true,
+                    IOInterface::VERBOSE,
+                );
+                $this->ffi = false;
+            }
+        }
+
+        return $this->ffi !== false ? $this->ffi : null;
+    }
+
+    /**
+     * Extract the `{"error": {...}}` envelope from a failed response.
+     *
+     * @return array{code: string, message: string, path: ?string, operation: ?string}|null
+     */
+    private static function decodeError(string $output, bool $msgpack = false): ?array
+    {
+        try {
+            $decoded = $msgpack
+                ? msgpack_unpack($output)
+                : json_decode(trim($output), associative: true, flags: JSON_THROW_ON_ERROR);
+        } catch (\JsonException) {
+            return null;
+        }
+
+        $error = is_array($decoded) ? $decoded['error'] ?? null : null;
+        if (!is_array($error) || !is_string($error['code'] ?? null)) {
+            return null;
+        }
+
+        return [
+            'code' => $error['code'],
+            'message' => (string) ($error['message'] ?? ''),
+            'path' => isset($error['path']) ? (string) $error['path'] : null,
+            'operation' => isset($error['operation']) ? (string) $error['operation'] : null,
+        ];
+    }
+
+    /**
+     * Socket of a `turbo-composer serve` process: the TURBO_COMPOSER_SOCKET
+     * environment variable, or `extra.turbo-composer.socket` in composer.json.
+     */
+    private function socketPath(): ?string
+    {
+        $env = getenv('TURBO_COMPOSER_SOCKET');
+        if (is_string($env) && $env !== '') {
+            return $env;
+        }
+
+        $extra = $this->composer->getPackage()->getExtra();
+        $socket = $extra['turbo-composer']['socket'] ?? null;
+
+        return is_string($socket) && $socket !== '' ? $socket : null;
+    }
+
+    /**
+     * Shared library to call in-process: the TURBO_COMPOSER_LIBRARY
+     * environment variable, or `extra.turbo-composer.library` in composer.json.
+     */
+    private function libraryPath(): ?string
+    {
+        $env = getenv('TURBO_COMPOSER_LIBRARY');
+        if (is_string($env) && $env !== '') {
+            return $env;
+        }
+
+        $extra = $this->composer->getPackage()->getExtra();
+        $library = $extra['turbo-composer']['library'] ?? null;
+
+        return is_string($library) && $library !== '' ? $library : null;
+    }
+
+    /**
+     * `threads`, `io-concurrency` and `memory-budget-mb` from
+     * `extra.turbo-composer`, as request fields. Unset or invalid values are
+     * left out so the binary uses its defaults.
+     *
+     * @return array<string, int>
+     */
+    private function resourceLimits(): array
+    {
+        $extra = $this->composer->getPackage()->getExtra();
+        $config = $extra['turbo-composer'] ?? [];
+        $limits = [];
+        foreach (self::RESOURCE_LIMITS as $key => $field) {
+            $value = $config[$key] ?? null;
+            if (is_int($value) && $value > 0) {
+                $limits[$field] = $value;
+            }
+        }
+
+        return $limits;
+    }
+
     private function resolve(): void
     {
         if ($this->resolved) {
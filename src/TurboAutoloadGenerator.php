@@ -5,25 +5,43 @@ declare(strict_types=1);
 namespace TurboComposer;
 
 use Composer\Autoload\AutoloadGenerator;
+use Composer\Composer;
 use Composer\Config;
 use Composer\EventDispatcher\EventDispatcher;
+use Composer\Filter\PlatformRequirementFilter\PlatformRequirementFilterFactory;
+use Composer\Filter\PlatformRequirementFilter\PlatformRequirementFilterInterface;
 use Composer\Installer\InstallationManager;
 use Composer\IO\IOInterface;
 use Composer\Package\AliasPackage;
+use Composer\Package\Link;
 use Composer\Package\Locker;
+use Composer\Package\PackageInterface;
+use Composer\Package\RootAliasPackage;
 use Composer\Package\RootPackageInterface;
 use Composer\Repository\InstalledRepositoryInterface;
+use Composer\Repository\PlatformRepository;
 
 use function array_key_exists;
+use function array_keys;
 use function array_merge;
+use function copy;
+use function count;
+use function dirname;
 use function file_exists;
 use function file_get_contents;
+use function getcwd;
+use function implode;
+use function is_dir;
 use function microtime;
+use function mkdir;
 use function preg_match;
 use function rename;
 use function round;
 use function rtrim;
 use function str_starts_with;
+use function strlen;
+use function substr;
+use function trim;
 use function unlink;
 
 class TurboAutoloadGenerator extends AutoloadGenerator
@@ -31,6 +49,10 @@ class TurboAutoloadGenerator extends AutoloadGenerator
     private IOInterface $io;
     private RustBridge $bridge;
     private bool $turboDevMode = false;
+    private bool $turboClassMapAuthoritative = false;
+    private bool $turboApcu = false;
+    private ?string $turboApcuPrefix = null;
+    private ?PlatformRequirementFilterInterface $turboPlatformRequirementFilter = null;
 
     private const STAGING_SUFFIX = '.turbo';
 
@@ -42,6 +64,12 @@ class TurboAutoloadGenerator extends AutoloadGenerator
         'autoload_files.php',
         'autoload_static.php',
         'autoload_real.php',
+        'installed.php',
+        'ClassLoader.php',
+        'InstalledVersions.php',
+        'LICENSE',
+        'platform_check.php',
+        'include_paths.php',
     ];
 
     public function __construct(EventDispatcher $eventDispatcher, IOInterface $io, RustBridge $bridge)
@@ -57,6 +85,25 @@ class TurboAutoloadGenerator extends AutoloadGenerator
         parent::setDevMode($devMode);
     }
 
+    public function setClassMapAuthoritative(bool $classMapAuthoritative): void
+    {
+        $this->turboClassMapAuthoritative = $classMapAuthoritative;
+        parent::setClassMapAuthoritative($classMapAuthoritative);
+    }
+
+    public function setApcu(bool $apcu, ?string $apcuPrefix = null): void
+    {
+        $this->turboApcu = $apcu;
+        $this->turboApcuPrefix = $apcuPrefix;
+        parent::setApcu($apcu, $apcuPrefix);
+    }
+
+    public function setPlatformRequirementFilter(PlatformRequirementFilterInterface $platformRequirementFilter): void
+    {
+        $this->turboPlatformRequirementFilter = $platformRequirementFilter;
+        parent::setPlatformRequirementFilter($platformRequirementFilter);
+    }
+
     public function dump(
         Config $config,
         InstalledRepositoryInterface $localRepo,
@@ -68,24 +115,8 @@ class TurboAutoloadGenerator extends AutoloadGenerator
         ?Locker $locker = null,
         bool $strictAmbiguous = false,
     ) {
-        if (!$scanPsrPackages) {
-            $this->io->write(
-                '<info>turbo-composer:</info> Non-optimised dump — using default generator.',
-                true,
-                IOInterface::VERBOSE,
-            );
-            return parent::dump(
-                $config,
-                $localRepo,
-                $rootPackage,
-                $installationManager,
-                $targetDir,
-                false,
-                $suffix,
-                $locker,
-                $strictAmbiguous,
-            );
-        }
+        // An authoritative classmap is useless unless every psr-4/psr-0 root is in it.
+        $scanPsrPackages = $scanPsrPackages || $this->turboClassMapAuthoritative;
 
         $totalStart = microtime(true);
         $this->io->write('<info>turbo-composer:</info> Rust-accelerated classmap generation…');
@@ -110,8 +141,22 @@ class TurboAutoloadGenerator extends AutoloadGenerator
         $payload = $this->buildPayload($projectDir, $vendorDir, $localRepo, $rootPackage, $installationManager);
         $payload['target_dir'] = $absTargetDir;
         $payload['staging_suffix'] = self::STAGING_SUFFIX;
-        $payload['has_platform_check'] = file_exists($absTargetDir . '/platform_check.php');
+        $payload['platform_check'] = $config->get('platform-check');
+        $payload['ignore_platform_reqs'] = $this->ignoredPlatformRequirements($localRepo, $rootPackage);
         $payload['has_files_autoload'] = $payload['autoload']['files'] !== [];
+        $payload['strict_ambiguous'] = $strictAmbiguous;
+        // Without -o, Composer only scans the classmap roots.
+        $payload['scan_psr_packages'] = $scanPsrPackages;
+        $payload['authoritative'] = $this->turboClassMapAuthoritative;
+        $payload['apcu_autoloader'] = $this->turboApcu;
+        $payload['apcu_prefix'] = $this->turboApcuPrefix;
+        $payload['prepend_autoloader'] = $config->get('prepend-autoloader') !== false;
+        $payload['strict_psr'] = ($rootPackage->getExtra()['turbo-composer']['strict-psr'] ?? false) === true;
+        $payload['root_package'] = $this->rootPackagePayload($rootPackage);
+        $runtimeDir = $this->runtimeDir($config);
+        if ($runtimeDir !== null) {
+            $payload['runtime_dir'] = $runtimeDir;
+        }
 
         if ($resolvedSuffix !== null) {
             $payload['suffix'] = $resolvedSuffix;
@@ -119,13 +164,10 @@ class TurboAutoloadGenerator extends AutoloadGenerator
         $buildPayloadMs = round((microtime(true) - $t0) * 1000);
 
         // Decide whether we can skip parent::dump entirely.
-        // When Rust generates autoload.php + autoload_real.php (requires suffix),
-        // and the infrastructure files already exist from a previous dump,
-        // there's no need to run parent::dump at all.
-        $canSkipParentDump =
-            $resolvedSuffix !== null
-            && file_exists($absTargetDir . '/ClassLoader.php')
-            && file_exists($absTargetDir . '/installed.php');
+        // Rust generates autoload.php + autoload_real.php (requires suffix),
+        // installed.php and platform_check.php, and copies ClassLoader.php /
+        // InstalledVersions.php (requires the runtime dir).
+        $canSkipParentDump = $resolvedSuffix !== null && $runtimeDir !== null;
 
         // Start Rust in the background — it writes staged files directly to disk
         $collect = $this->bridge->startAsync($payload);
@@ -170,6 +212,12 @@ class TurboAutoloadGenerator extends AutoloadGenerator
             // Clean up any partially staged files
             $this->cleanStagedFiles($absTargetDir, $vendorDir);
 
+            // Composer would hit the same full disk or permission problem.
+            $error = $this->bridge->lastError();
+            if ($error !== null && !$this->bridge->canFallBack()) {
+                throw new \RuntimeException("turbo-composer: {$error['message']}");
+            }
+
             $this->io->writeError('<warning>turbo-composer:</warning> Rust binary failed — '
             . 're-running with default Composer optimisation…');
             return parent::dump(
@@ -178,7 +226,7 @@ class TurboAutoloadGenerator extends AutoloadGenerator
                 $rootPackage,
                 $installationManager,
                 $targetDir,
-                true,
+                $scanPsrPackages,
                 $suffix,
                 $locker,
                 $strictAmbiguous,
@@ -187,6 +235,8 @@ class TurboAutoloadGenerator extends AutoloadGenerator
 
         // Promote staged files — atomic rename overwrites parent::dump's versions
         $this->promoteStagedFiles($absTargetDir, $vendorDir);
+        $this->reportPsrViolations($rustResult['psr_violations'] ?? []);
+        $this->reportAmbiguousClasses($rustResult['ambiguous'] ?? []);
 
         $totalMs = round((microtime(true) - $totalStart) * 1000);
         $count = $rustResult['classmap_count'] ?? 0;
@@ -212,6 +262,52 @@ class TurboAutoloadGenerator extends AutoloadGenerator
         return $result;
     }
 
+    /**
+     * Print Composer's "does not comply with psr-4 autoloading standard"
+     * warnings for the classes Rust skipped.
+     *
+     * @param list<array{class: string, file: string, standard: string, namespace: string, base_path: string}> $violations
+     */
+    private function reportPsrViolations(array $violations): void
+    {
+        $cwd = getcwd();
+        foreach ($violations as $violation) {
+            $file = $violation['file'];
+            if ($cwd !== false && str_starts_with($file, $cwd . '/')) {
+                $file = '.' . substr($file, strlen($cwd));
+            }
+            $this->io->writeError(
+                "<warning>Class {$violation['class']} located in {$file} does not comply with "
+                . "{$violation['standard']} autoloading standard "
+                . "(rule: {$violation['namespace']} => {$violation['base_path']}). Skipping.</warning>",
+            );
+        }
+    }
+
+    /**
+     * Print Composer's "Ambiguous class resolution" warnings for the classes
+     * Rust found in more than one file.
+     *
+     * @param list<array{class: string, chosen: string, others: list<string>}> $ambiguous
+     */
+    private function reportAmbiguousClasses(array $ambiguous): void
+    {
+        foreach ($ambiguous as $entry) {
+            $where = count($entry['others']) > 1
+                ? 'was found ' . (count($entry['others']) + 1) . 'x: in'
+                : 'was found in both';
+            $this->io->writeError(
+                '<warning>Warning: Ambiguous class resolution, "'
+                . $entry['class']
+                . "\" {$where} \""
+                . $entry['chosen']
+                . '" and "'
+                . implode('", "', $entry['others'])
+                . '", the first will be used.</warning>',
+            );
+        }
+    }
+
     /**
      * Rename staged files (.turbo suffix) to their final names.
      * This is atomic on POSIX systems (same filesystem).
@@ -258,12 +354,15 @@ class TurboAutoloadGenerator extends AutoloadGenerator
         InstallationManager $installationManager,
     ): array {
         $packages = $localRepo->getCanonicalPackages();
+        $devPackageNames = array_flip($localRepo->getDevPackageNames());
 
         $psr4 = [];
         $psr0 = [];
         $classmap = [];
         $files = [];
         $excludePatterns = [];
+        // Composer lists the root's include paths first.
+        $includePaths = $this->includePathEntries($rootPackage, $projectDir, false);
 
         foreach ($packages as $package) {
             if ($package instanceof AliasPackage) {
@@ -273,25 +372,32 @@ class TurboAutoloadGenerator extends AutoloadGenerator
             if ($installPath === null) {
                 continue;
             }
-            $entries = $this->collectAutoloadEntries($package->getAutoload(), $installPath, $package);
+            $dev = isset($devPackageNames[$package->getName()]);
+            $entries = $this->collectAutoloadEntries($package->getAutoload(), $installPath, $package, $dev);
             $psr4 = array_merge($psr4, $entries['psr4']);
             $psr0 = array_merge($psr0, $entries['psr0']);
             $classmap = array_merge($classmap, $entries['classmap']);
             $files = array_merge($files, $entries['files']);
+            $includePaths = array_merge($includePaths, $this->includePathEntries($package, $installPath, $dev));
 
+            if ($dev && !$this->turboDevMode) {
+                continue;
+            }
             $excludePatterns = array_merge($excludePatterns, $this->resolveExcludePatterns(
                 $package->getAutoload(),
                 $installPath,
             ));
         }
 
-        $autoloads = [$rootPackage->getAutoload()];
-        if ($this->turboDevMode) {
-            $autoloads[] = $rootPackage->getDevAutoload();
-        }
+        // Dev mappings are always sent, flagged, and Rust drops them when
+        // dev_mode is off.
+        $autoloads = [
+            [$rootPackage->getAutoload(), false],
+            [$rootPackage->getDevAutoload(), true],
+        ];
 
-        foreach ($autoloads as $autoload) {
-            $entries = $this->collectAutoloadEntries($autoload, $projectDir, $rootPackage);
+        foreach ($autoloads as [$autoload, $dev]) {
+            $entries = $this->collectAutoloadEntries($autoload, $projectDir, $rootPackage, $dev);
             $psr4 = array_merge($psr4, $entries['psr4']);
             $psr0 = array_merge($psr0, $entries['psr0']);
             $classmap = array_merge($classmap, $entries['classmap']);
@@ -309,11 +415,6 @@ class TurboAutoloadGenerator extends AutoloadGenerator
             ));
         }
 
-        $installedVersionsPath = $vendorDir . '/composer/InstalledVersions.php';
-        if (file_exists($installedVersionsPath)) {
-            $classmap[] = $installedVersionsPath;
-        }
-
         return [
             'command' => 'classmap',
             'project_dir' => $projectDir,
@@ -323,11 +424,101 @@ class TurboAutoloadGenerator extends AutoloadGenerator
                 'psr-0' => $psr0,
                 'classmap' => $classmap,
                 'files' => $files,
+                'include-path' => $includePaths,
             ],
             'exclude_from_classmap' => $excludePatterns,
+            'dev_mode' => $this->turboDevMode,
+        ];
+    }
+
+    /**
+     * The root package as installed.php lists it.
+     *
+     * @return array<string, mixed>
+     */
+    private function rootPackagePayload(RootPackageInterface $rootPackage): array
+    {
+        $aliases = [];
+        if ($rootPackage instanceof RootAliasPackage) {
+            $aliases[] = $rootPackage->getPrettyVersion();
+            $rootPackage = $rootPackage->getAliasOf();
+        }
+
+        $links = static function (array $links): array {
+            $constraints = [];
+            foreach ($links as $link) {
+                /** @var Link $link */
+                $constraints[$link->getTarget()] = $link->getPrettyConstraint();
+            }
+            return $constraints;
+        };
+
+        return [
+            'name' => $rootPackage->getName(),
+            'pretty_version' => $rootPackage->getPrettyVersion(),
+            'version' => $rootPackage->getVersion(),
+            'reference' => $rootPackage->getSourceReference() ?: $rootPackage->getDistReference() ?: null,
+            'type' => $rootPackage->getType(),
+            'aliases' => $aliases,
+            'require' => (object) $links($rootPackage->getRequires()),
+            'replace' => (object) $links($rootPackage->getReplaces()),
+            'provide' => (object) $links($rootPackage->getProvides()),
         ];
     }
 
+    /**
+     * The platform requirements that --ignore-platform-req(s) leaves out of
+     * platform_check.php, resolved to names since Rust cannot ask the filter.
+     *
+     * @return list<string>
+     */
+    private function ignoredPlatformRequirements(
+        InstalledRepositoryInterface $localRepo,
+        RootPackageInterface $rootPackage,
+    ): array {
+        $filter = $this->turboPlatformRequirementFilter ?? PlatformRequirementFilterFactory::ignoreNothing();
+        $ignored = [];
+        foreach ([...$localRepo->getCanonicalPackages(), $rootPackage] as $package) {
+            foreach ($package->getRequires() as $link) {
+                $target = $link->getTarget();
+                if (PlatformRepository::isPlatformPackage($target) && $filter->isIgnored($target)) {
+                    $ignored[$target] = true;
+                }
+            }
+        }
+
+        return array_keys($ignored);
+    }
+
+    /**
+     * Composer's src/Composer directory, which holds the ClassLoader.php and
+     * InstalledVersions.php that Rust copies. Rust cannot read inside a phar,
+     * so from composer.phar they are extracted once per Composer version.
+     */
+    private function runtimeDir(Config $config): ?string
+    {
+        $composerDir = dirname((string) (new \ReflectionClass(Composer::class))->getFileName());
+        if (!str_starts_with($composerDir, 'phar://')) {
+            return $composerDir;
+        }
+
+        $runtimeDir = $config->get('cache-dir') . '/turbo-composer/runtime-' . Composer::getVersion() . '/src/Composer';
+        if (file_exists($runtimeDir . '/InstalledVersions.php')) {
+            return $runtimeDir;
+        }
+
+        if (!is_dir($runtimeDir . '/Autoload') && !@mkdir($runtimeDir . '/Autoload', 0o755, true)) {
+            return null;
+        }
+        @copy($composerDir . '/../../LICENSE', $runtimeDir . '/../../LICENSE');
+        // InstalledVersions.php goes last: it marks the extraction complete.
+        $copied =
+            @copy($composerDir . '/Autoload/ClassLoader.php', $runtimeDir . '/Autoload/ClassLoader.php')
+            && @copy($composerDir . '/InstalledVersions.php', $runtimeDir . '/InstalledVersions.php');
+
+        return $copied ? $runtimeDir : null;
+    }
+
     /**
      * Resolve exclude-from-classmap patterns to absolute-path-anchored regex strings,
      * matching Composer's own pattern resolution logic.
@@ -356,9 +547,29 @@ class TurboAutoloadGenerator extends AutoloadGenerator
     }
 
     /**
-     * @return array{psr4: list<array{namespace: string, path: string}>, psr0: list<array{namespace: string, path: string}>, classmap: list<string>, files: list<array{identifier: string, path: string}>}
+     * A package's legacy `include-path` entries, resolved like Composer's
+     * getIncludePathsFile(): against the install path without target-dir.
+     *
+     * @return list<array{path: string, dev: bool}>
+     */
+    private function includePathEntries(PackageInterface $package, string $installPath, bool $dev): array
+    {
+        $targetDir = $package->getTargetDir();
+        if ($targetDir !== null && $targetDir !== '' && !$package instanceof RootPackageInterface) {
+            $installPath = substr($installPath, 0, -strlen('/' . $targetDir));
+        }
+
+        $entries = [];
+        foreach ($package->getIncludePaths() as $includePath) {
+            $entries[] = ['path' => $installPath . '/' . trim($includePath, '/'), 'dev' => $dev];
+        }
+        return $entries;
+    }
+
+    /**
+     * @return array{psr4: list<array{namespace: string, path: string, dev: bool}>, psr0: list<array{namespace: string, path: string, dev: bool}>, classmap: list<array{path: string, dev: bool}>, files: list<array{identifier: string, path: string, dev: bool}>}
      */
-    private function collectAutoloadEntries(array $autoload, string $basePath, mixed $package): array
+    private function collectAutoloadEntries(array $autoload, string $basePath, mixed $package, bool $dev): array
     {
         $psr4 = [];
         $psr0 = [];
@@ -371,6 +582,7 @@ class TurboAutoloadGenerator extends AutoloadGenerator
                     $psr4[] = [
                         'namespace' => $ns,
                         'path' => rtrim($basePath . '/' . $path, '/'),
+                        'dev' => $dev,
                     ];
                 }
             }
@@ -382,6 +594,7 @@ class TurboAutoloadGenerator extends AutoloadGenerator
                     $psr0[] = [
                         'namespace' => $ns,
                         'path' => rtrim($basePath . '/' . $path, '/'),
+                        'dev' => $dev,
                     ];
                 }
             }
@@ -389,7 +602,7 @@ class TurboAutoloadGenerator extends AutoloadGenerator
 
         if (array_key_exists('classmap', $autoload)) {
             foreach ((array) $autoload['classmap'] as $path) {
-                $classmap[] = rtrim($basePath . '/' . $path, '/');
+                $classmap[] = ['path' => rtrim($basePath . '/' . $path, '/'), 'dev' => $dev];
             }
         }
 
@@ -398,6 +611,7 @@ class TurboAutoloadGenerator extends AutoloadGenerator
                 $files[] = [
                     'identifier' => $this->getFileIdentifier($package, $path),
                     'path' => rtrim($basePath . '/' . $path, '/'),
+                    'dev' => $dev,
                 ];
             }
         }
@@ -18,6 +18,7 @@ use function array_values;
 use function count;
 use function file_exists;
 use function glob;
+use function is_array;
 use function is_dir;
 use function method_exists;
 use function microtime;
@@ -57,6 +58,7 @@ class TurboInstallerPlugin implements PluginInterface, EventSubscriberInterface
             return;
         }
 
+        $this->recoverInterruptedBatch();
         $this->swapAutoloadGenerator();
         $io->write('<info>turbo-composer:</info> Rust acceleration active.');
     }
@@ -227,6 +229,8 @@ class TurboInstallerPlugin implements PluginInterface, EventSubscriberInterface
         if ($this->pendingCleanups !== []) {
             $operationCounts['clean'] = count($this->pendingCleanups);
             $operations[] = [
+                'id' => 'clean',
+                'depends_on' => [],
                 'command' => 'clean',
                 'targets' => array_values($this->pendingCleanups),
             ];
@@ -236,6 +240,8 @@ class TurboInstallerPlugin implements PluginInterface, EventSubscriberInterface
         if ($this->pendingVerifications !== []) {
             $operationCounts['verify'] = count($this->pendingVerifications);
             $operations[] = [
+                'id' => 'verify',
+                'depends_on' => [],
                 'command' => 'verify',
                 'verify_targets' => array_values($this->pendingVerifications),
             ];
@@ -244,7 +250,11 @@ class TurboInstallerPlugin implements PluginInterface, EventSubscriberInterface
 
         if ($this->pendingExtractions !== []) {
             $operationCounts['extract'] = count($this->pendingExtractions);
+            // Verification runs alongside the cleanup; extraction waits for the
+            // cleanup, since a removed and a re-installed package share a path.
             $operations[] = [
+                'id' => 'extract',
+                'depends_on' => isset($operationCounts['clean']) ? ['clean'] : [],
                 'command' => 'extract',
                 'packages' => array_values($this->pendingExtractions),
             ];
@@ -274,10 +284,15 @@ class TurboInstallerPlugin implements PluginInterface, EventSubscriberInterface
             return;
         }
 
-        $batchResult = $this->bridge->run([
-            'command' => 'batch',
-            'operations' => $operations,
-        ]);
+        $payload = ['command' => 'batch', 'operations' => $operations];
+        if ($this->bridge->supports('recover')) {
+            // Stage everything and only swap it into vendor/ once every
+            // operation has succeeded; an interrupted run is rolled back the
+            // next time a transactional batch starts.
+            $payload['transactional'] = true;
+            $payload['vendor_dir'] = $this->composer->getConfig()->get('vendor-dir');
+        }
+        $batchResult = $this->bridge->run($payload);
 
         $elapsed = round((microtime(true) - $start) * 1000);
 
@@ -292,13 +307,20 @@ class TurboInstallerPlugin implements PluginInterface, EventSubscriberInterface
             $command = $entry['command'] ?? '';
             $result = $entry['result'] ?? null;
             if ($result === null || ($entry['error'] ?? null) !== null) {
-                $error = $entry['error'] ?? 'unknown';
+                $error = $entry['error'] ?? null;
+                $error = is_array($error) ? $error['message'] ?? 'unknown' : $error ?? 'unknown';
                 $this->io->writeError("<warning>turbo-composer:</warning> Batch {$command} failed: {$error}");
                 continue;
             }
             $count = $operationCounts[$command] ?? 0;
             $this->logBatchResult($command, $result, $count, $elapsed);
         }
+
+        if (($batchResult['transaction'] ?? null) === 'rolled_back') {
+            $this->io->writeError(
+                '<warning>turbo-composer:</warning> Batch rolled back, vendor/ is unchanged — Composer will handle normally.',
+            );
+        }
     }
 
     private function logBatchResult(string $command, array $result, int $count, float $elapsed): void
@@ -355,6 +377,35 @@ class TurboInstallerPlugin implements PluginInterface, EventSubscriberInterface
         return null;
     }
 
+    /**
+     * Roll back a transactional batch that a killed run left half done, so
+     * packages moved into vendor/.turbo-trash are back before Composer reads
+     * vendor/. The binary is only asked when a batch journal is on disk.
+     */
+    private function recoverInterruptedBatch(): void
+    {
+        $vendorDir = $this->composer->getConfig()->get('vendor-dir');
+        if (!file_exists($vendorDir . '/.turbo-trash/journal.json') || !$this->bridge->supports('recover')) {
+            return;
+        }
+
+        $result = $this->bridge->run(['command' => 'recover', 'vendor_dir' => $vendorDir]);
+        if ($result === null) {
+            $this->io->writeError(
+                '<warning>turbo-composer:</warning> Could not recover an interrupted batch; '
+                . 'run `turbo-composer recover vendor` to retry.',
+            );
+            return;
+        }
+
+        $recovered = $result['recovered'] ?? null;
+        if (is_array($recovered) && ($recovered['rolled_back'] ?? false)) {
+            $this->io->writeError(
+                "<warning>turbo-composer:</warning> Rolled back an interrupted batch ({$recovered['actions']} steps).",
+            );
+        }
+    }
+
     private function swapAutoloadGenerator(): void
     {
         $generator = new TurboAutoloadGenerator($this->composer->getEventDispatcher(), $this->io, $this->bridge);
@@ -18,6 +18,7 @@ use TurboComposer\RustBridge;
 
 use function chmod;
 use function file_put_contents;
+use function function_exists;
 use function is_dir;
 use function is_link;
 use function mkdir;
@@ -167,6 +168,47 @@ class RustBridgeTest extends TestCase
         $this->assertNull($result);
     }
 
+    public function testRunRecordsStructuredError(): void
+    {
+        $this->placeFakeBinary(
+            output: '{"error":{"code":"disk_full","message":"No space left on device","path":"/v","operation":"classmap"}}',
+            exitCode: 7,
+        );
+
+        $bridge = new RustBridge($this->composer, $this->io, $this->noFallbackDir);
+        $result = $bridge->run(['command' => 'classmap']);
+
+        $this->assertNull($result);
+        $this->assertSame('disk_full', $bridge->lastError()['code'] ?? null);
+        $this->assertSame('/v', $bridge->lastError()['path'] ?? null);
+        $this->assertFalse($bridge->canFallBack());
+    }
+
+    public function testStrictPsrFailureDoesNotFallBack(): void
+    {
+        $this->placeFakeBinary(
+            output: '{"error":{"code":"psr_violation","message":"1 classes do not comply","path":"/p/src/Foo.php","operation":"classmap"}}',
+            exitCode: 12,
+        );
+
+        $bridge = new RustBridge($this->composer, $this->io, $this->noFallbackDir);
+        $bridge->run(['command' => 'classmap']);
+
+        $this->assertSame('psr_violation', $bridge->lastError()['code'] ?? null);
+        $this->assertFalse($bridge->canFallBack());
+    }
+
+    public function testRunWithoutErrorEnvelopeAllowsFallBack(): void
+    {
+        $this->placeFakeBinary(exitCode: 1);
+
+        $bridge = new RustBridge($this->composer, $this->io, $this->noFallbackDir);
+        $bridge->run(['command' => 'classmap']);
+
+        $this->assertNull($bridge->lastError());
+        $this->assertTrue($bridge->canFallBack());
+    }
+
     public function testRunReturnsNullOnInvalidJsonOutput(): void
     {
         $this->placeFakeBinary(output: 'this is not valid json {{{');
@@ -188,6 +230,55 @@ class RustBridgeTest extends TestCase
         $this->assertIsArray($result);
         $this->assertSame('classmap', $result['command']);
         $this->assertSame('/test', $result['project_dir']);
+        $this->assertSame(RustBridge::PROTOCOL_VERSION, $result['protocol_version']);
+    }
+
+    public function testMsgpackRequestCarriesRootPackageMaps(): void
+    {
+        if (!function_exists('msgpack_pack')) {
+            $this->markTestSkipped('The msgpack extension is not installed.');
+        }
+
+        $this->placeFakeMsgpackEchoBinary();
+
+        $bridge = new RustBridge($this->composer, $this->io, $this->noFallbackDir);
+        $this->assertContains('msgpack', $bridge->capabilities()['encodings']);
+
+        $result = $bridge->run([
+            'command' => 'classmap',
+            'root_package' => [
+                'name' => 'acme/app',
+                'require' => (object) ['php' => '^8.1'],
+                'replace' => (object) ['acme/legacy' => 'self.version'],
+                'provide' => (object) [],
+            ],
+        ]);
+
+        $this->assertIsArray($result);
+        $this->assertSame('classmap', $result['command']);
+        $this->assertSame(['php' => '^8.1'], $result['root_package']['require']);
+        $this->assertSame(['acme/legacy' => 'self.version'], $result['root_package']['replace']);
+        $this->assertSame([], $result['root_package']['provide']);
+    }
+
+    public function testCapabilitiesFromBinary(): void
+    {
+        $this->placeFakeBinary(output: '{"commands":["classmap","capabilities","watch"],"protocol_version":1}');
+
+        $bridge = new RustBridge($this->composer, $this->io, $this->noFallbackDir);
+
+        $this->assertTrue($bridge->supports('watch'));
+        $this->assertFalse($bridge->supports('extract'));
+    }
+
+    public function testCapabilitiesFallBackToBaselineForOlderBinary(): void
+    {
+        $this->placeFakeBinary(exitCode: 1);
+
+        $bridge = new RustBridge($this->composer, $this->io, $this->noFallbackDir);
+
+        $this->assertTrue($bridge->supports('extract'));
+        $this->assertFalse($bridge->supports('capabilities'));
     }
 
     /**
@@ -246,6 +337,38 @@ class RustBridgeTest extends TestCase
         chmod($binaryPath, 0o755);
     }
 
+    /**
+     * Place a fake binary that lists msgpack in its capabilities, answering
+     * JSON requests with them and echoing MessagePack requests back.
+     */
+    private function placeFakeMsgpackEchoBinary(): void
+    {
+        $binDir = $this->tempDir . '/vendor/turbo-composer';
+        if (!is_dir($binDir)) {
+            mkdir($binDir, 0o755, true);
+        }
+
+        $script = <<<'BASH'
+            #!/bin/bash
+            if [ "$1" = "--version" ] || [ "$1" = "-V" ]; then
+                echo "turbo-composer 0.1.0"
+                exit 0
+            fi
+            input=$(mktemp)
+            cat > "$input"
+            if [ "$(head -c 1 "$input")" = "{" ]; then
+                printf '%s' '{"commands":["classmap","capabilities"],"encodings":["json","msgpack"]}'
+            else
+                cat "$input"
+            fi
+            rm -f "$input"
+            BASH;
+
+        $binaryPath = $binDir . '/turbo-composer';
+        file_put_contents($binaryPath, $script);
+        chmod($binaryPath, 0o755);
+    }
+
     private function removeDirectory(string $dir): void
     {
         if (!is_dir($dir)) {
@@ -0,0 +1,189 @@
+<?php
+
+declare(strict_types=1);
+
+namespace TurboComposer\Tests\Unit;
+
+use Composer\Composer;
+use Composer\Config;
+use Composer\EventDispatcher\EventDispatcher;
+use Composer\IO\IOInterface;
+use Composer\Package\RootPackageInterface;
+use Composer\Repository\InstalledRepositoryInterface;
+use Composer\Repository\RepositoryManager;
+use PHPUnit\Framework\MockObject\Stub;
+use PHPUnit\Framework\TestCase;
+use TurboComposer\TurboInstallerPlugin;
+
+use function chmod;
+use function explode;
+use function file_exists;
+use function file_get_contents;
+use function file_put_contents;
+use function is_dir;
+use function is_link;
+use function json_decode;
+use function mkdir;
+use function rmdir;
+use function sys_get_temp_dir;
+use function trim;
+use function uniqid;
+use function unlink;
+
+class TurboInstallerPluginTest extends TestCase
+{
+    private string $tempDir;
+    private Composer&Stub $composer;
+    private IOInterface&Stub $io;
+
+    protected function setUp(): void
+    {
+        if (PHP_OS_FAMILY === 'Windows') {
+            $this->markTestSkipped('Plugin tests use shell scripts as fake binaries and are not supported on Windows.');
+        }
+
+        $this->tempDir = sys_get_temp_dir() . '/turbo-composer-test-' . uniqid();
+        mkdir($this->tempDir . '/vendor', 0o755, true);
+
+        $config = $this->createStub(Config::class);
+        $config
+            ->method('get')
+            ->willReturnCallback(fn(string $key) => match ($key) {
+                'vendor-dir' => $this->tempDir . '/vendor',
+                default => null,
+            });
+
+        $rootPackage = $this->createStub(RootPackageInterface::class);
+        $rootPackage
+            ->method('getExtra')
+            ->willReturn([
+                'turbo-composer' => [
+                    'version' => '0.1.0',
+                    'base-url' => 'https://example.invalid',
+                ],
+            ]);
+
+        $localRepo = $this->createStub(InstalledRepositoryInterface::class);
+        $localRepo->method('findPackages')->willReturn([]);
+
+        $repoManager = $this->createStub(RepositoryManager::class);
+        $repoManager->method('getLocalRepository')->willReturn($localRepo);
+
+        $this->composer = $this->createStub(Composer::class);
+        $this->composer->method('getConfig')->willReturn($config);
+        $this->composer->method('getPackage')->willReturn($rootPackage);
+        $this->composer->method('getRepositoryManager')->willReturn($repoManager);
+        $this->composer->method('getEventDispatcher')->willReturn($this->createStub(EventDispatcher::class));
+
+        $this->io = $this->createStub(IOInterface::class);
+    }
+
+    protected function tearDown(): void
+    {
+        if (($this->tempDir ?? null) !== null) {
+            $this->removeDirectory($this->tempDir);
+        }
+    }
+
+    public function testActivateRecoversAnInterruptedBatch(): void
+    {
+        $this->placeRecordingBinary();
+        mkdir($this->tempDir . '/vendor/.turbo-trash');
+        file_put_contents($this->tempDir . '/vendor/.turbo-trash/journal.json', '{}');
+
+        (new TurboInstallerPlugin())->activate($this->composer, $this->io);
+
+        $recover = $this->recordedRequests('recover');
+        $this->assertCount(1, $recover);
+        $this->assertSame($this->tempDir . '/vendor', $recover[0]['vendor_dir']);
+    }
+
+    public function testActivateSkipsRecoveryWithoutJournal(): void
+    {
+        $this->placeRecordingBinary();
+
+        (new TurboInstallerPlugin())->activate($this->composer, $this->io);
+
+        $this->assertSame([], $this->recordedRequests('recover'));
+    }
+
+    /**
+     * Place a fake binary that appends every request to `requests.log` and
+     * answers `capabilities` and `recover`.
+     */
+    private function placeRecordingBinary(): void
+    {
+        $binDir = $this->tempDir . '/vendor/turbo-composer';
+        if (!is_dir($binDir)) {
+            mkdir($binDir, 0o755, true);
+        }
+
+        $script = <<<'BASH'
+            #!/bin/bash
+            if [ "$1" = "--version" ] || [ "$1" = "-V" ]; then
+                echo "turbo-composer 0.1.0"
+                exit 0
+            fi
+            request=$(cat)
+            printf '%s\n' "$request" >> "$(dirname "$0")/requests.log"
+            case "$request" in
+                *'"command":"capabilities"'*)
+                    printf '%s' '{"commands":["classmap","batch","recover","capabilities"]}' ;;
+                *'"command":"recover"'*)
+                    printf '%s' '{"recovered":{"rolled_back":true,"actions":2}}' ;;
+                *)
+                    printf '%s' '{}' ;;
+            esac
+            BASH;
+
+        $binaryPath = $binDir . '/turbo-composer';
+        file_put_contents($binaryPath, $script);
+        chmod($binaryPath, 0o755);
+    }
+
+    /**
+     * Requests the recording binary received for `$command`.
+     *
+     * @return list<array<string, mixed>>
+     */
+    private function recordedRequests(string $command): array
+    {
+        $log = $this->tempDir . '/vendor/turbo-composer/requests.log';
+        if (!file_exists($log)) {
+            return [];
+        }
+
+        $requests = [];
+        foreach (explode("\n", trim((string) file_get_contents($log))) as $line) {
+            $request = json_decode($line, associative: true);
+            if (($request['command'] ?? null) === $command) {
+                $requests[] = $request;
+            }
+        }
+
+        return $requests;
+    }
+
+    private function removeDirectory(string $dir): void
+    {
+        if (!is_dir($dir)) {
+            return;
+        }
+
+        $items = new \RecursiveIteratorIterator(
+            new \RecursiveDirectoryIterator($dir, \FilesystemIterator::SKIP_DOTS),
+            \RecursiveIteratorIterator::CHILD_FIRST,
+        );
+
+        foreach ($items as $item) {
+            $path = $item->getPathname();
+            if (is_link($path) || !$item->isDir()) {
+                unlink($path);
+                continue;
+            }
+            rmdir($path);
+        }
+
+        rmdir($dir);
+    }
+}